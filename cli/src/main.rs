@@ -1,9 +1,31 @@
-use std::net::IpAddr;
+use std::{
+    net::{IpAddr, SocketAddr},
+    process,
+    sync::Arc,
+    time::Duration,
+};
 
-use clap::{Parser, Subcommand};
-use client::client::{PingClient, PingClientConfig, PingClientConnectionType};
-use common::{message::Message, utils::gen_certs::gen_certs};
-use server::server::{PongServer, PongServerConfig};
+use clap::{Parser, Subcommand, ValueEnum};
+use client::{
+    balancer::{
+        ConsistentHashing, EjectionPolicy, LeastLatency, RandomSelection, RoundRobin,
+        SelectionStrategy,
+    },
+    client::{PingClient, PingClientConfig, PingClientConnectionType},
+    discovery::{beacon, DiscoveryConfig, DiscoveryQuery},
+    pipeline::PipelineConfig,
+    retry::{
+        DecorrelatedJitterBackoff, ExponentialBackoff, FixedDelay, FullJitterBackoff, RetryPolicy,
+    },
+    target::ServerUrl,
+    tls::CertificateVerification,
+};
+use common::{beacon::DEFAULT_BEACON_GROUP, message::Message, utils::gen_certs::gen_certs};
+use server::{
+    beacon::BeaconConfig,
+    request_handler::{Echo, Pong, RequestHandler, Static},
+    server::{PongServer, PongServerConfig},
+};
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -12,18 +34,163 @@ struct Cli {
     command: Option<SubCommand>,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum RetryPolicyKind {
+    Fixed,
+    Exponential,
+    FullJitter,
+    DecorrelatedJitter,
+}
+
+impl RetryPolicyKind {
+    fn policy(self, base: Duration, max_delay: Duration) -> Arc<dyn RetryPolicy> {
+        match self {
+            RetryPolicyKind::Fixed => Arc::new(FixedDelay::new(base)),
+            RetryPolicyKind::Exponential => Arc::new(ExponentialBackoff::new(base, max_delay)),
+            RetryPolicyKind::FullJitter => Arc::new(FullJitterBackoff::new(base, max_delay)),
+            RetryPolicyKind::DecorrelatedJitter => {
+                Arc::new(DecorrelatedJitterBackoff::new(base, max_delay))
+            }
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum SelectionStrategyKind {
+    RoundRobin,
+    Random,
+    LeastLatency,
+    ConsistentHash,
+}
+
+impl SelectionStrategyKind {
+    fn strategy(self) -> Arc<dyn SelectionStrategy> {
+        match self {
+            SelectionStrategyKind::RoundRobin => Arc::new(RoundRobin::new()),
+            SelectionStrategyKind::Random => Arc::new(RandomSelection),
+            SelectionStrategyKind::LeastLatency => Arc::new(LeastLatency),
+            SelectionStrategyKind::ConsistentHash => Arc::new(ConsistentHashing),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum RequestHandlerKind {
+    Pong,
+    Echo,
+    Static,
+}
+
+impl RequestHandlerKind {
+    fn handler(self, payload: &str) -> Arc<dyn RequestHandler> {
+        match self {
+            RequestHandlerKind::Pong => Arc::new(Pong),
+            RequestHandlerKind::Echo => Arc::new(Echo),
+            RequestHandlerKind::Static => Arc::new(Static(payload.as_bytes().to_vec())),
+        }
+    }
+}
+
 #[derive(Subcommand, Debug)]
 enum SubCommand {
     #[clap(about = "Run the client and send specified number of Ping! messages")]
     Client {
-        #[clap(long, default_value = "127.0.0.1")]
-        host: IpAddr,
+        /// WebTransport URLs of the servers, their host is resolved and used to verify the certificate
+        #[clap(default_value = "https://localhost:4433")]
+        urls: Vec<String>,
 
-        #[clap(long, default_value = "4433")]
-        port: u16,
+        /// Strategy selecting the server every connection is made to
+        #[clap(long, value_enum, default_value = "round-robin")]
+        selection: SelectionStrategyKind,
+
+        /// Number of failed connections in a row a server is ejected from the selection after
+        #[clap(long, default_value = "2")]
+        eject_after_failures: u32,
+
+        /// Seconds a failing server is ejected for the first time, doubling with every ejection
+        #[clap(long, default_value = "5")]
+        ejection_secs: u64,
 
         #[clap(long, default_value = "3")]
         ping_count: u32,
+
+        /// Number of streams the pings are spread over and sent on concurrently
+        #[clap(long, default_value = "1")]
+        streams: usize,
+
+        /// Number of pings kept in flight on every stream, 1 waits for every response before sending the next ping
+        #[clap(long, default_value = "1")]
+        pipeline_depth: usize,
+
+        /// Time a pipelined ping is waited for in milliseconds, after which it is accounted as lost
+        #[clap(long, default_value = "5000")]
+        request_timeout_millis: u64,
+
+        /// Codec to serialize messages with (bincode, json, cbor or msgpack)
+        #[clap(long)]
+        codec: Option<String>,
+
+        /// Maximum size of a single message in bytes
+        #[clap(long, default_value = "1048576")]
+        max_frame_size: u64,
+
+        /// Number of times to reconnect after losing the connection mid-session
+        #[clap(long, default_value = "3")]
+        max_reconnects: u16,
+
+        /// Policy computing the delays between connection attempts
+        #[clap(long, value_enum, default_value = "full-jitter")]
+        retry_policy: RetryPolicyKind,
+
+        /// Base delay between connection attempts in milliseconds
+        #[clap(long, default_value = "200")]
+        retry_delay_millis: u64,
+
+        /// Upper bound of the delay between connection attempts in milliseconds
+        #[clap(long, default_value = "5000")]
+        max_retry_delay_millis: u64,
+
+        /// Number of retries after a failed connection attempt
+        #[clap(long, default_value = "3")]
+        max_retries: u16,
+
+        /// Maximum time spent retrying to connect in milliseconds
+        #[clap(long, default_value = "30000")]
+        max_retry_elapsed_millis: u64,
+
+        /// PEM file with the certificate authorities to verify the server certificate with,
+        /// instead of the ones trusted by the system
+        #[clap(long, conflicts_with = "insecure")]
+        ca_cert: Option<String>,
+
+        /// SHA-256 hash of the server certificate to accept, in base64 or hex, instead of verifying it
+        /// with certificate authorities. Can be repeated, e.g. while rotating certificates
+        #[clap(long = "cert-hash", conflicts_with_all = ["insecure", "ca_cert"])]
+        cert_hashes: Vec<String>,
+
+        /// Accept any server certificate. Never use this outside of local testing!
+        #[clap(long)]
+        insecure: bool,
+
+        /// Discover the servers through the SRV records of this name, e.g. _pong._udp.pong.default.svc.cluster.local
+        #[clap(long, conflicts_with_all = ["headless", "lan"])]
+        srv: Option<String>,
+
+        /// Discover the servers through the addresses of this headless service, listening on the port of the URL
+        #[clap(long, conflicts_with = "lan")]
+        headless: Option<String>,
+
+        /// Discover the servers announcing themselves on the local network instead of connecting to the URL
+        #[clap(long)]
+        lan: bool,
+
+        /// Group the servers announce themselves to on the local network
+        #[clap(long, default_value_t = DEFAULT_BEACON_GROUP)]
+        beacon_group: SocketAddr,
+
+        /// Nameserver to discover the servers with, instead of the ones of /etc/resolv.conf
+        #[clap(long)]
+        nameserver: Option<SocketAddr>,
     },
     #[clap(about = "Run the server")]
     Server {
@@ -38,9 +205,59 @@ enum SubCommand {
 
         #[clap(long, default_value = "key.pem")]
         key_path: String,
+
+        /// Maximum size of a single message in bytes
+        #[clap(long, default_value = "1048576")]
+        max_frame_size: u64,
+
+        /// How long responses are kept for their client to reconnect and resume its session, in seconds
+        #[clap(long, default_value = "60")]
+        mailbox_ttl_secs: u64,
+
+        /// Maximum number of recent responses kept per client
+        #[clap(long, default_value = "128")]
+        mailbox_capacity: usize,
+
+        /// How requests are answered: with "Pong!", with their own payload or with `--payload`
+        #[clap(long, value_enum, default_value = "pong")]
+        respond_with: RequestHandlerKind,
+
+        /// Payload every request is answered with when responding with `static`
+        #[clap(long, default_value = "")]
+        payload: String,
+
+        /// Announce the server on the local network for clients to discover it
+        #[clap(long)]
+        announce: bool,
+
+        /// Group to announce the server to, a multicast, broadcast or unicast address
+        #[clap(long, default_value_t = DEFAULT_BEACON_GROUP)]
+        beacon_group: SocketAddr,
+
+        /// Seconds between two announcements
+        #[clap(long, default_value = "1")]
+        announce_interval_secs: u64,
+
+        /// Name the certificate of the server is issued for, announced for clients to verify it with
+        #[clap(long, default_value = "localhost")]
+        server_name: String,
+    },
+    #[clap(about = "List the servers announcing themselves on the local network")]
+    Discover {
+        /// Group the servers announce themselves to
+        #[clap(long, default_value_t = DEFAULT_BEACON_GROUP)]
+        beacon_group: SocketAddr,
+
+        /// Seconds to listen for announcements
+        #[clap(long, default_value = "2")]
+        listen_secs: u64,
     },
     #[clap(about = "Generate certificate files in current working directory")]
-    GenCerts,
+    GenCerts {
+        /// Number of days the certificate is valid, clients pin its hash only up to 14 days
+        #[clap(long, default_value = "14")]
+        validity_days: u16,
+    },
 }
 
 #[tokio::main]
@@ -49,18 +266,95 @@ async fn main() {
 
     match &cli.command {
         Some(SubCommand::Client {
-            host,
-            port,
+            urls,
+            selection,
+            eject_after_failures,
+            ejection_secs,
             ping_count,
+            streams,
+            pipeline_depth,
+            request_timeout_millis,
+            codec,
+            max_frame_size,
+            max_reconnects,
+            retry_policy,
+            retry_delay_millis,
+            max_retry_delay_millis,
+            max_retries,
+            max_retry_elapsed_millis,
+            ca_cert,
+            cert_hashes,
+            insecure,
+            srv,
+            headless,
+            lan,
+            beacon_group,
+            nameserver,
         }) => {
-            let ping_client_config = PingClientConfig {
-                host: *host,
-                port: *port,
+            let certificate_verification = match (ca_cert, insecure) {
+                (_, true) => CertificateVerification::Insecure,
+                (Some(ca_path), false) => CertificateVerification::CustomCa {
+                    ca_path: ca_path.clone(),
+                },
+                (None, false) if !cert_hashes.is_empty() => CertificateVerification::PinnedHashes {
+                    hashes: cert_hashes.clone(),
+                },
+                (None, false) => CertificateVerification::SystemRoots,
+            };
+
+            let discovery_query = match (srv, headless) {
+                (Some(name), _) => Some(DiscoveryQuery::Srv { name: name.clone() }),
+                (None, Some(name)) => match ServerUrl::parse(&urls[0]) {
+                    Ok(url) => Some(DiscoveryQuery::Headless {
+                        name: name.clone(),
+                        port: url.port,
+                    }),
+                    Err(error) => {
+                        eprintln!("{}", error);
+                        process::exit(2);
+                    }
+                },
+                (None, None) if *lan => Some(DiscoveryQuery::Beacon {
+                    group: *beacon_group,
+                }),
+                (None, None) => None,
+            };
+            let discovery = discovery_query.map(|query| DiscoveryConfig {
+                nameserver: *nameserver,
+                ..DiscoveryConfig::new(query)
+            });
+
+            let mut ping_client_config = PingClientConfig {
+                urls: urls.clone(),
+                selection_strategy: selection.strategy(),
+                ejection_policy: EjectionPolicy {
+                    consecutive_failures: *eject_after_failures,
+                    base_ejection_time: Duration::from_secs(*ejection_secs),
+                    ..EjectionPolicy::default()
+                },
                 connection_type: PingClientConnectionType::Bidirectional,
-                max_retries: 3,
-                retry_timeout_millis: 1000,
+                streams: *streams,
+                pipeline: (*pipeline_depth > 1).then_some(PipelineConfig {
+                    depth: *pipeline_depth,
+                    request_timeout: Duration::from_millis(*request_timeout_millis),
+                }),
+                retry_policy: retry_policy.policy(
+                    Duration::from_millis(*retry_delay_millis),
+                    Duration::from_millis(*max_retry_delay_millis),
+                ),
+                max_retries: *max_retries,
+                max_retry_elapsed: Some(Duration::from_millis(*max_retry_elapsed_millis)),
+                max_frame_size: *max_frame_size,
+                max_reconnects: *max_reconnects,
+                certificate_verification,
+                discovery,
+                ..Default::default()
             };
 
+            if let Some(codec) = codec {
+                ping_client_config.codecs = vec![codec.clone()];
+            }
+
             let mut ping_client = PingClient::new(ping_client_config);
 
             let times = if ping_count == &0 {
@@ -69,33 +363,97 @@ async fn main() {
                 Some(*ping_count)
             };
 
-            let message = Message::new_request("Ping!".to_string());
+            let message = Message::new_request("Ping!");
 
-            ping_client
-                .send_message(&message, times)
-                .await
-                .expect("sending message failed");
+            // Ctrl-C stops sending, the statistics are printed either way
+            let result = tokio::select! {
+                result = ping_client.send_message(&message, times) => result,
+                _ = tokio::signal::ctrl_c() => Ok(()),
+            };
+
+            println!("\n--- {} ping statistics ---", urls.join(", "));
+            let stream_statistics = ping_client.get_stream_statistics();
+            if stream_statistics.len() > 1 {
+                for (index, summary) in stream_statistics.iter().enumerate() {
+                    println!("stream {}: {}", index, summary);
+                }
+            }
+            println!("{}", ping_client.get_statistics());
+
+            result.expect("sending message failed");
         }
         Some(SubCommand::Server {
             host,
             port,
             certificate_path,
             key_path,
+            max_frame_size,
+            mailbox_ttl_secs,
+            mailbox_capacity,
+            respond_with,
+            payload,
+            announce,
+            beacon_group,
+            announce_interval_secs,
+            server_name,
         }) => {
+            let beacon = announce.then(|| BeaconConfig {
+                group: *beacon_group,
+                interval: Duration::from_secs(*announce_interval_secs),
+                server_name: server_name.clone(),
+            });
+
             let pong_server_config = PongServerConfig {
                 host: *host,
                 port: *port,
                 certificate_path: certificate_path.clone(),
                 certificate_key_path: key_path.clone(),
+                max_frame_size: *max_frame_size,
+                mailbox_ttl: Duration::from_secs(*mailbox_ttl_secs),
+                mailbox_capacity: *mailbox_capacity,
+                beacon,
             };
 
-            let pong_server = PongServer::new(pong_server_config);
+            let pong_server = PongServer::new(pong_server_config, respond_with.handler(payload));
 
             pong_server.serve().await.expect("Server failed");
         }
-        Some(SubCommand::GenCerts) => {
-            gen_certs("cert.pem".to_string(), "key.pem".to_string())
-                .expect("failed to generate certs");
+        Some(SubCommand::Discover {
+            beacon_group,
+            listen_secs,
+        }) => {
+            println!(
+                "Listening for announcements to {} for {} seconds...",
+                beacon_group, listen_secs
+            );
+
+            let servers = beacon::listen(*beacon_group, Duration::from_secs(*listen_secs))
+                .await
+                .expect("failed to listen for announcements");
+
+            if servers.is_empty() {
+                println!("No server found");
+            }
+
+            for server in servers {
+                let announcement = server.announcement;
+                println!(
+                    "{}  name={} version={} ttl={}s cert-sha256={}",
+                    SocketAddr::new(server.address, announcement.port),
+                    announcement.server_name,
+                    announcement.protocol_version,
+                    announcement.ttl.as_secs(),
+                    announcement.certificate_hash.as_deref().unwrap_or("-"),
+                );
+            }
+        }
+        Some(SubCommand::GenCerts { validity_days }) => {
+            gen_certs(
+                "cert.pem".to_string(),
+                "key.pem".to_string(),
+                time::Duration::days((*validity_days).into()),
+            )
+            .expect("failed to generate certs");
         }
         None => {}
     }
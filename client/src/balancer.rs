@@ -0,0 +1,511 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use rand::{Rng, RngCore};
+use tokio::time::Instant;
+
+use crate::{error::ClientSetupError, target::ServerUrl};
+
+/// How much a new latency sample weighs in the moving average of an endpoint.
+const LATENCY_SAMPLE_WEIGHT: f64 = 0.3;
+
+/// Represents an endpoint the `LoadBalancer` may select, as seen by a `SelectionStrategy`.
+///
+/// # Fields
+/// * `index` - The position of the endpoint among the configured ones.
+/// * `url` - The URL of the endpoint.
+/// * `latency` - The moving average of the round-trip times measured with the endpoint, `None` until measured.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Candidate<'a> {
+    pub index: usize,
+    pub url: &'a ServerUrl,
+    pub latency: Option<Duration>,
+}
+
+/// Decides which endpoint the `PingClient` connects to.
+///
+/// Strategies only choose among the endpoints which are not ejected, the `LoadBalancer`
+/// takes care of ejecting the unhealthy ones.
+pub trait SelectionStrategy: Send + Sync {
+    /// Selects the endpoint to connect to.
+    ///
+    /// # Arguments
+    /// * `candidates` - The endpoints to choose from, in the configured order. Never empty.
+    /// * `client_id` - The identifier of the client, for strategies keeping clients on the same endpoint.
+    /// * `rng` - The source of randomness for random strategies.
+    ///
+    /// # Returns
+    /// Returns the position of the selected endpoint in `candidates`.
+    fn select(&self, candidates: &[Candidate], client_id: &str, rng: &mut dyn RngCore) -> usize;
+}
+
+/// Selects the endpoints one after the other, skipping the ejected ones.
+#[derive(Debug, Default)]
+pub struct RoundRobin {
+    next: AtomicUsize,
+}
+
+impl RoundRobin {
+    /// Creates a new `RoundRobin` strategy, starting with the first endpoint.
+    ///
+    /// # Returns
+    /// Returns a `RoundRobin` instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SelectionStrategy for RoundRobin {
+    fn select(&self, candidates: &[Candidate], _client_id: &str, _rng: &mut dyn RngCore) -> usize {
+        let next = self.next.load(Ordering::Relaxed);
+        let selected = candidates
+            .iter()
+            .position(|candidate| candidate.index >= next)
+            .unwrap_or(0);
+
+        self.next
+            .store(candidates[selected].index + 1, Ordering::Relaxed);
+
+        selected
+    }
+}
+
+/// Selects an endpoint at random.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct RandomSelection;
+
+impl SelectionStrategy for RandomSelection {
+    fn select(&self, candidates: &[Candidate], _client_id: &str, rng: &mut dyn RngCore) -> usize {
+        rng.gen_range(0..candidates.len())
+    }
+}
+
+/// Selects the endpoint with the lowest measured latency.
+///
+/// Endpoints which have not been measured yet are selected first, so that every endpoint gets measured.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct LeastLatency;
+
+impl SelectionStrategy for LeastLatency {
+    fn select(&self, candidates: &[Candidate], _client_id: &str, _rng: &mut dyn RngCore) -> usize {
+        candidates
+            .iter()
+            .position(|candidate| candidate.latency.is_none())
+            .or_else(|| (0..candidates.len()).min_by_key(|&position| candidates[position].latency))
+            .unwrap_or(0)
+    }
+}
+
+/// Keeps every client on the same endpoint, for sticky sessions.
+///
+/// Endpoints are selected by rendezvous hashing of the client ID: every endpoint gets a score from
+/// the hash of the client ID and its URL, the highest score wins. While an endpoint is ejected its clients
+/// move to their second best endpoint, and come back once it is readmitted. The clients of the other
+/// endpoints do not move.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct ConsistentHashing;
+
+impl ConsistentHashing {
+    /// Computes the score of an endpoint for a client.
+    fn score(client_id: &str, url: &ServerUrl) -> u64 {
+        let hash = common::hash::hash(format!("{}\n{}", client_id, url).as_bytes());
+
+        u64::from_be_bytes(
+            hash[..8]
+                .try_into()
+                .expect("SHA-256 hashes are 32 bytes long"),
+        )
+    }
+}
+
+impl SelectionStrategy for ConsistentHashing {
+    fn select(&self, candidates: &[Candidate], client_id: &str, _rng: &mut dyn RngCore) -> usize {
+        (0..candidates.len())
+            .max_by_key(|&position| Self::score(client_id, candidates[position].url))
+            .unwrap_or(0)
+    }
+}
+
+/// Decides when an unhealthy endpoint is ejected and for how long.
+///
+/// An endpoint is ejected after `consecutive_failures` failed connections in a row, for `base_ejection_time`.
+/// Once readmitted it is retried, and ejected again at the first failure for twice as long, up to `max_ejection_time`,
+/// until a connection to it succeeds.
+///
+/// # Fields
+/// * `consecutive_failures` - The number of failed connections in a row an endpoint is ejected after.
+/// * `base_ejection_time` - How long an endpoint is ejected the first time.
+/// * `max_ejection_time` - The upper bound of the ejection time.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct EjectionPolicy {
+    pub consecutive_failures: u32,
+    pub base_ejection_time: Duration,
+    pub max_ejection_time: Duration,
+}
+
+impl Default for EjectionPolicy {
+    fn default() -> Self {
+        Self {
+            consecutive_failures: 2,
+            base_ejection_time: Duration::from_secs(5),
+            max_ejection_time: Duration::from_secs(60),
+        }
+    }
+}
+
+/// The health of an endpoint.
+struct EndpointHealth {
+    url: ServerUrl,
+    latency: Option<Duration>,
+    consecutive_failures: u32,
+    ejections: u32,
+    ejected_until: Option<Instant>,
+}
+
+impl EndpointHealth {
+    fn is_ejected(&self, now: Instant) -> bool {
+        matches!(self.ejected_until, Some(until) if until > now)
+    }
+}
+
+/// The health of all the endpoints, and the one the client is connected to.
+struct BalancerState {
+    endpoints: Vec<EndpointHealth>,
+    connected: Option<usize>,
+}
+
+/// Spreads the connections of a client over several endpoints, ejecting the unhealthy ones.
+///
+/// Every connection attempt selects an endpoint with the `SelectionStrategy` among the ones which are not ejected.
+/// If all of them are ejected, the one readmitted the soonest is selected rather than not connecting at all.
+pub struct LoadBalancer {
+    strategy: Arc<dyn SelectionStrategy>,
+    ejection: EjectionPolicy,
+    client_id: String,
+    state: Mutex<BalancerState>,
+}
+
+impl LoadBalancer {
+    /// Creates a new `LoadBalancer`, all the endpoints being healthy.
+    ///
+    /// # Arguments
+    /// * `urls` - The URLs of the endpoints.
+    /// * `strategy` - Selects the endpoint to connect to.
+    /// * `ejection` - Decides when an unhealthy endpoint is ejected and for how long.
+    /// * `client_id` - The identifier of the client, given to the strategy.
+    ///
+    /// # Returns
+    /// * `Result` - The `LoadBalancer`, or a `ClientSetupError::NoServerUrl` if `urls` is empty.
+    pub fn new(
+        urls: Vec<ServerUrl>,
+        strategy: Arc<dyn SelectionStrategy>,
+        ejection: EjectionPolicy,
+        client_id: &str,
+    ) -> Result<Self, ClientSetupError> {
+        if urls.is_empty() {
+            return Err(ClientSetupError::NoServerUrl);
+        }
+
+        let endpoints = urls
+            .into_iter()
+            .map(|url| EndpointHealth {
+                url,
+                latency: None,
+                consecutive_failures: 0,
+                ejections: 0,
+                ejected_until: None,
+            })
+            .collect();
+
+        Ok(Self {
+            strategy,
+            ejection,
+            client_id: client_id.to_string(),
+            state: Mutex::new(BalancerState {
+                endpoints,
+                connected: None,
+            }),
+        })
+    }
+
+    /// Selects the endpoint to connect to.
+    ///
+    /// # Returns
+    /// Returns the index of the endpoint, to report the outcome of the connection with, along with its URL.
+    pub fn select(&self) -> (usize, ServerUrl) {
+        let now = Instant::now();
+        let state = self.state.lock().expect("load balancer state poisoned");
+
+        let candidates = state
+            .endpoints
+            .iter()
+            .enumerate()
+            .filter(|(_, endpoint)| !endpoint.is_ejected(now))
+            .map(|(index, endpoint)| Candidate {
+                index,
+                url: &endpoint.url,
+                latency: endpoint.latency,
+            })
+            .collect::<Vec<_>>();
+
+        let index = if candidates.is_empty() {
+            (0..state.endpoints.len())
+                .min_by_key(|&index| state.endpoints[index].ejected_until)
+                .expect("there is at least one endpoint")
+        } else {
+            let selected =
+                self.strategy
+                    .select(&candidates, &self.client_id, &mut rand::thread_rng());
+
+            candidates[selected.min(candidates.len() - 1)].index
+        };
+
+        (index, state.endpoints[index].url.clone())
+    }
+
+    /// Reports a successful connection, readmitting the endpoint for good.
+    ///
+    /// # Arguments
+    /// * `index` - The index of the endpoint, as given by `select`.
+    /// * `latency` - How long establishing the connection took, a round trip along with the TLS handshake.
+    pub fn record_success(&self, index: usize, latency: Duration) {
+        let mut state = self.state.lock().expect("load balancer state poisoned");
+        state.connected = Some(index);
+
+        let endpoint = &mut state.endpoints[index];
+        endpoint.consecutive_failures = 0;
+        endpoint.ejections = 0;
+        endpoint.ejected_until = None;
+        Self::add_latency_sample(endpoint, latency);
+    }
+
+    /// Reports a failed connection, ejecting the endpoint if it keeps failing.
+    ///
+    /// # Arguments
+    /// * `index` - The index of the endpoint, as given by `select`.
+    pub fn record_failure(&self, index: usize) {
+        let mut state = self.state.lock().expect("load balancer state poisoned");
+        if state.connected == Some(index) {
+            state.connected = None;
+        }
+
+        let endpoint = &mut state.endpoints[index];
+        endpoint.consecutive_failures += 1;
+
+        // A readmitted endpoint which has not succeeded since is ejected again at its first failure
+        if endpoint.ejections > 0
+            || endpoint.consecutive_failures >= self.ejection.consecutive_failures
+        {
+            let factor = 2u32.saturating_pow(endpoint.ejections);
+            let ejection_time = self
+                .ejection
+                .base_ejection_time
+                .saturating_mul(factor)
+                .min(self.ejection.max_ejection_time);
+
+            endpoint.ejections += 1;
+            endpoint.consecutive_failures = 0;
+            endpoint.ejected_until = Some(Instant::now() + ejection_time);
+        }
+    }
+
+    /// Reports a round-trip time measured with an endpoint.
+    ///
+    /// # Arguments
+    /// * `index` - The index of the endpoint, as given by `select`.
+    /// * `latency` - The measured round-trip time.
+    pub fn record_latency(&self, index: usize, latency: Duration) {
+        let mut state = self.state.lock().expect("load balancer state poisoned");
+
+        Self::add_latency_sample(&mut state.endpoints[index], latency);
+    }
+
+    /// The index of the endpoint the last connection has been established to, `None` once it has failed.
+    pub fn connected(&self) -> Option<usize> {
+        self.state
+            .lock()
+            .expect("load balancer state poisoned")
+            .connected
+    }
+
+    /// Adds a latency sample to the exponentially weighted moving average of an endpoint.
+    fn add_latency_sample(endpoint: &mut EndpointHealth, sample: Duration) {
+        endpoint.latency = Some(match endpoint.latency {
+            Some(latency) => {
+                latency.mul_f64(1.0 - LATENCY_SAMPLE_WEIGHT) + sample.mul_f64(LATENCY_SAMPLE_WEIGHT)
+            }
+            None => sample,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(host: &str) -> ServerUrl {
+        ServerUrl {
+            host: host.to_string(),
+            port: 4433,
+            path: "/".to_string(),
+        }
+    }
+
+    fn balancer(strategy: Arc<dyn SelectionStrategy>, client_id: &str) -> LoadBalancer {
+        LoadBalancer::new(
+            vec![url("pong-0"), url("pong-1"), url("pong-2"), url("canary")],
+            strategy,
+            EjectionPolicy::default(),
+            client_id,
+        )
+        .unwrap()
+    }
+
+    fn select_hosts(balancer: &LoadBalancer, count: usize) -> Vec<String> {
+        (0..count).map(|_| balancer.select().1.host).collect()
+    }
+
+    fn eject(balancer: &LoadBalancer, index: usize) {
+        for _ in 0..EjectionPolicy::default().consecutive_failures {
+            balancer.record_failure(index);
+        }
+    }
+
+    #[test]
+    fn test_should_require_an_endpoint() {
+        assert!(matches!(
+            LoadBalancer::new(
+                vec![],
+                Arc::new(RoundRobin::new()),
+                EjectionPolicy::default(),
+                "client"
+            ),
+            Err(ClientSetupError::NoServerUrl)
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_round_robin_over_endpoints_which_are_not_ejected() {
+        let balancer = balancer(Arc::new(RoundRobin::new()), "client");
+
+        assert_eq!(
+            select_hosts(&balancer, 5),
+            ["pong-0", "pong-1", "pong-2", "canary", "pong-0"]
+        );
+
+        eject(&balancer, 2);
+
+        assert_eq!(
+            select_hosts(&balancer, 4),
+            ["pong-1", "canary", "pong-0", "pong-1"]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_eject_failing_endpoint_and_retry_it_later() {
+        let balancer = balancer(Arc::new(RoundRobin::new()), "client");
+        let canary_selected = |balancer: &LoadBalancer| {
+            select_hosts(balancer, 4)
+                .iter()
+                .any(|host| host == "canary")
+        };
+
+        // A single failure is tolerated
+        balancer.record_failure(3);
+        assert!(canary_selected(&balancer));
+
+        balancer.record_failure(3);
+        assert!(!canary_selected(&balancer));
+
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert!(canary_selected(&balancer));
+
+        // Failing again right after its readmission ejects it for twice as long
+        balancer.record_failure(3);
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert!(!canary_selected(&balancer));
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert!(canary_selected(&balancer));
+
+        // Succeeding readmits it for good, two failures in a row are needed to eject it again
+        balancer.record_success(3, Duration::from_millis(10));
+        balancer.record_failure(3);
+        assert!(canary_selected(&balancer));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_select_endpoint_readmitted_soonest_when_all_are_ejected() {
+        let balancer = balancer(Arc::new(RandomSelection), "client");
+
+        for index in [1, 3, 0, 2] {
+            eject(&balancer, index);
+            tokio::time::advance(Duration::from_secs(1)).await;
+        }
+
+        assert_eq!(select_hosts(&balancer, 3), ["pong-1"; 3]);
+    }
+
+    #[test]
+    fn test_should_select_random_endpoints() {
+        let balancer = balancer(Arc::new(RandomSelection), "client");
+        eject(&balancer, 3);
+
+        let mut hosts = select_hosts(&balancer, 200);
+        hosts.sort();
+        hosts.dedup();
+
+        assert_eq!(hosts, ["pong-0", "pong-1", "pong-2"]);
+    }
+
+    #[test]
+    fn test_should_select_least_latency_endpoint_once_all_are_measured() {
+        let balancer = balancer(Arc::new(LeastLatency), "client");
+
+        for (index, latency) in [(0, 30), (1, 10), (2, 20)] {
+            balancer.record_latency(index, Duration::from_millis(latency));
+        }
+
+        // The canary has not been measured yet
+        assert_eq!(balancer.select().1.host, "canary");
+
+        balancer.record_success(3, Duration::from_millis(40));
+        assert_eq!(balancer.select().1.host, "pong-1");
+
+        // The moving average of pong-1 goes above the latency of pong-2
+        for _ in 0..5 {
+            balancer.record_latency(1, Duration::from_millis(50));
+        }
+        assert_eq!(balancer.select().1.host, "pong-2");
+    }
+
+    #[test]
+    fn test_should_keep_client_on_same_endpoint_with_consistent_hashing() {
+        let clients = (0..64).map(|id| format!("client-{}", id));
+        let mut spread = vec![0; 4];
+
+        for client_id in clients {
+            let balancer = balancer(Arc::new(ConsistentHashing), &client_id);
+            let (sticky, _) = balancer.select();
+            assert_eq!(balancer.select().0, sticky);
+            spread[sticky] += 1;
+
+            // Ejecting another endpoint does not move the client
+            let other = (sticky + 1) % 4;
+            eject(&balancer, other);
+            assert_eq!(balancer.select().0, sticky);
+
+            // Ejecting its endpoint moves the client, which goes back once the endpoint is healthy again
+            eject(&balancer, sticky);
+            assert_ne!(balancer.select().0, sticky);
+            balancer.record_success(sticky, Duration::from_millis(10));
+            assert_eq!(balancer.select().0, sticky);
+        }
+
+        assert!(spread.iter().all(|&clients| clients > 0), "{:?}", spread);
+    }
+}
@@ -1,15 +1,37 @@
 use std::{
-    net::{IpAddr, Ipv6Addr, SocketAddr},
+    future::Future,
+    net::{Ipv6Addr, SocketAddr},
+    sync::Arc,
     time::Duration,
 };
 
-use common::{error::ConnectionError, message::Message};
+use common::{
+    codec,
+    error::{ConnectionError, HandshakeError},
+    message::{
+        hello::{Capabilities, ConnectionParameters, HelloMessage, DEFAULT_MAX_MESSAGE_SIZE},
+        id::MessageId,
+        Message,
+    },
+    transport::Connection,
+};
 
+use futures::future::try_join_all;
+use tokio::time::Instant;
 use wtransport::{ClientConfig, Endpoint};
 
 use crate::{
+    balancer::{EjectionPolicy, LoadBalancer, RoundRobin, SelectionStrategy},
+    discovery::{failover, Discovery, DiscoveryConfig},
     error::{ClientError, ClientSetupError},
-    handler::{send_bidirectional, send_datagram, send_unidirectional},
+    handler::{perform_handshake, send_datagram, send_lock_step, send_pipelined},
+    happy_eyeballs::{race, CONNECTION_ATTEMPT_DELAY},
+    inbox::{answered_requests, merge_replayed, InboxEntry},
+    pipeline::PipelineConfig,
+    retry::{retry, FullJitterBackoff, RetryPolicy},
+    stats::{PingStatistics, PingSummary},
+    target::ServerUrl,
+    tls::{certificate_verifier, tls_config, CertificateVerification, RecordingVerifier},
 };
 
 /// Represents the type of connection the `PingClient` will establish.
@@ -26,25 +48,123 @@ pub enum PingClientConnectionType {
 /// Represents the configuration for a `PingClient`.
 ///
 /// # Fields
-/// * `host` - IP address of the server to connect to.
-/// * `port` - Port of the server to connect to.
+/// * `urls` - WebTransport URLs of the servers to connect to, e.g. `https://pong.example.internal:4433/ping`.
+///   Their host is resolved on every connection attempt, and is the name the server certificate is verified for.
+/// * `selection_strategy` - Selects the server every connection attempt is made to among the `urls`.
+/// * `ejection_policy` - Decides when a failing server is ejected from the selection and for how long.
 /// * `connection_type` - Specifies the type of connection to establish.
-/// * `max_retries` - Maximum number of connection attempts.
-/// * `retry_timeout_millis` - Amount of time (in milliseconds) to wait between connection attempts.
+/// * `streams` - The number of streams opened on the connection to send the pings over concurrently,
+///   e.g. to measure head-of-line blocking. The pings are spread over them. Not used with datagrams.
+/// * `pipeline` - Keeps several requests in flight on the stream instead of waiting for every response
+///   before sending the next request. Not used with datagrams. `None` for the lock-step mode.
+/// * `datagram_timeout` - The time the reply to a datagram is waited for, after which the ping is accounted as lost.
+/// * `retry_policy` - Decides how long to wait between connection attempts.
+/// * `max_retries` - Maximum number of retries after a failed connection attempt.
+/// * `max_retry_elapsed` - Maximum time spent retrying to connect. `None` for no limit.
+/// * `client_id` - Stable identifier the client announces to the server during the handshake.
+/// * `codecs` - Names of the codecs offered to the server, the preferred one first.
+/// * `max_frame_size` - The maximum size of a single message in bytes. Larger responses are rejected and their stream is reset.
+/// * `max_reconnects` - Maximum number of times the client reconnects after losing the connection halfway through.
+/// * `certificate_verification` - How the certificate presented by the server is verified.
+/// * `discovery` - Discovers the servers to connect to through DNS or LAN announcements instead of connecting to the host of `url`.
+///   The discovered endpoints are tried in rank order until one of them accepts the connection.
 pub struct PingClientConfig {
-    pub host: IpAddr,
-    pub port: u16,
+    pub urls: Vec<String>,
+    pub selection_strategy: Arc<dyn SelectionStrategy>,
+    pub ejection_policy: EjectionPolicy,
     pub connection_type: PingClientConnectionType,
+    pub streams: usize,
+    pub pipeline: Option<PipelineConfig>,
+    pub datagram_timeout: Duration,
+    pub retry_policy: Arc<dyn RetryPolicy>,
     pub max_retries: u16,
-    pub retry_timeout_millis: u64,
+    pub max_retry_elapsed: Option<Duration>,
+    pub client_id: String,
+    pub codecs: Vec<String>,
+    pub max_frame_size: u64,
+    pub max_reconnects: u16,
+    pub certificate_verification: CertificateVerification,
+    pub discovery: Option<DiscoveryConfig>,
+}
+
+impl Default for PingClientConfig {
+    /// Creates a configuration targeting a local server with a random client ID.
+    fn default() -> Self {
+        Self {
+            urls: vec!["https://localhost:4433".to_string()],
+            selection_strategy: Arc::new(RoundRobin::new()),
+            ejection_policy: EjectionPolicy::default(),
+            connection_type: PingClientConnectionType::Bidirectional,
+            streams: 1,
+            pipeline: None,
+            datagram_timeout: Duration::from_secs(1),
+            retry_policy: Arc::new(FullJitterBackoff::new(
+                Duration::from_millis(200),
+                Duration::from_secs(5),
+            )),
+            max_retries: 3,
+            max_retry_elapsed: Some(Duration::from_secs(30)),
+            client_id: MessageId::generate().to_string(),
+            codecs: codec::supported_codec_names(),
+            max_frame_size: DEFAULT_MAX_MESSAGE_SIZE,
+            max_reconnects: 3,
+            certificate_verification: CertificateVerification::default(),
+            discovery: None,
+        }
+    }
 }
 
 /// Represents a `PingClient` used to send Ping! messages to the server.
 ///
 /// The `PingClient` uses the settings from a `PingClientConfig` to control its behavior.
+/// The health of the servers is kept from one `send_message` call to the next.
 pub struct PingClient {
     config: PingClientConfig,
-    inbox: Vec<Message>,
+    balancer: Option<Arc<LoadBalancer>>,
+    discovery: Option<Arc<Discovery>>,
+    inbox: Vec<InboxEntry>,
+    stats: PingStatistics,
+    stream_stats: Vec<PingStatistics>,
+    stream_progress: Vec<StreamProgress>,
+}
+
+/// Builds the configuration of the endpoint of a connection attempt.
+///
+/// The configuration is built for every attempt due to limitations of `wtransport` crate, which sets
+/// the certificate verifier for the whole endpoint.
+///
+/// # Arguments
+/// * `recorder` - The verifier of the certificate presented to the attempt, `None` if it is not verified.
+///
+/// # Returns
+/// Returns the `ClientConfig` of the endpoint, bound to an ephemeral port of any address.
+fn client_config(recorder: Option<Arc<RecordingVerifier>>) -> ClientConfig {
+    let config =
+        ClientConfig::builder().with_bind_address(SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0));
+
+    match recorder {
+        Some(recorder) => config.with_custom_tls(tls_config(recorder)),
+        None => config.with_no_cert_validation(),
+    }
+}
+
+/// Where the servers every connection attempt is made to come from.
+///
+/// * `Discovered` - The servers are discovered anew for every attempt.
+/// * `Balanced` - The servers are selected among the configured URLs.
+enum ServerSelection {
+    Discovered(Arc<Discovery>),
+    Balanced(Arc<LoadBalancer>),
+}
+
+/// The pings sent on a stream of the current connection.
+///
+/// They are kept apart while the streams are driven concurrently, and added to the ones of the client
+/// once the connection is done with, see `PingClient::settle_streams`.
+#[derive(Default)]
+struct StreamProgress {
+    inbox: Vec<InboxEntry>,
+    stats: PingStatistics,
 }
 
 impl PingClient {
@@ -57,13 +177,22 @@ impl PingClient {
     /// Returns a `PingClient` instance.
     pub fn new(config: PingClientConfig) -> Self {
         Self {
+            balancer: None,
+            discovery: config.discovery.clone().map(Discovery::new).map(Arc::new),
             config,
             inbox: vec![],
+            stats: PingStatistics::new(),
+            stream_stats: vec![],
+            stream_progress: vec![],
         }
     }
 
     /// Asynchronously sends a message to a server using the client's connection settings.
     ///
+    /// Every connection attempt selects one of the URLs, resolves its host anew and races the resolved addresses
+    /// following happy eyeballs. If the connection is lost halfway through, the client reconnects,
+    /// up to `max_reconnects` times, and carries on with the remaining messages.
+    ///
     /// # Arguments
     /// * `message` - The `Message` instance to be sent.
     /// * `times` - The number of times to attempt sending the message.
@@ -75,90 +204,532 @@ impl PingClient {
         message: &Message,
         times: Option<u32>,
     ) -> Result<(), ClientError> {
-        // Building the client configuration with the bind address and no certificate validation
-        // The configuration is happening here due to limitations of `wttransport` crate
-        let config = ClientConfig::builder()
-            .with_bind_address(SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0))
-            .with_no_cert_validation();
-
-        let endpoint = Endpoint::client(config)
-            .map_err(|_| ClientError::SetupError(ClientSetupError::EndpointCreationError))?;
-
-        // Handle retry logic in case of endpoint connection failure
-        let mut retries = 0;
-        let connection = loop {
-            let maybe_connecting = endpoint.connect(
-                SocketAddr::new(self.config.host, self.config.port),
-                "localhost",
-            );
+        let selection = self.server_selection()?;
+        let verifier = certificate_verifier(&self.config.certificate_verification)?;
 
-            if maybe_connecting.is_err() {
-                println!("connection failed, retrying...");
+        let verifier = verifier.as_ref();
+        let selection = &selection;
+        let retry_policy = self.config.retry_policy.clone();
+        let retry_policy = retry_policy.as_ref();
+        let max_retries = self.config.max_retries;
+        let max_retry_elapsed = self.config.max_retry_elapsed;
 
-                tokio::time::sleep(Duration::from_millis(self.config.retry_timeout_millis)).await;
+        self.send_message_over(
+            move || async move {
+                let connection = retry(
+                    move || async move {
+                        // The server name is sent with SNI and the certificate is verified for it
+                        let connect_address = move |address, server_name: String| async move {
+                            // Every attempt records its own verification failure, as the attempts run concurrently
+                            let recorder = verifier
+                                .map(|verifier| Arc::new(RecordingVerifier::new(verifier.clone())));
 
-                if retries > self.config.max_retries {
-                    return Err(ClientError::ConnectionError(
-                        ConnectionError::MaxRetriesReached {
-                            retry_count: retries,
-                        },
-                    ));
-                }
+                            let endpoint = Endpoint::client(client_config(recorder.clone()))
+                                .map_err(|_| ConnectionError::ConnectFailed {
+                                    reason: ClientSetupError::EndpointCreationError.to_string(),
+                                })?;
 
-                retries += 1;
+                            endpoint
+                                .connect(address, &server_name)
+                                .map_err(|error| ConnectionError::ConnectFailed {
+                                    reason: error.to_string(),
+                                })?
+                                .await
+                                .map_err(|error| {
+                                    // The handshake fails with a generic error if the certificate has been rejected
+                                    match recorder.and_then(|recorder| recorder.take_failure()) {
+                                        Some(reason) => {
+                                            ConnectionError::CertificateVerificationFailed {
+                                                reason,
+                                            }
+                                        }
+                                        None => ConnectionError::from(error),
+                                    }
+                                })
+                        };
 
-                continue;
-            }
+                        let discovery = match selection {
+                            ServerSelection::Discovered(discovery) => discovery,
+                            ServerSelection::Balanced(balancer) => {
+                                let (index, url) = balancer.select();
 
-            let connecting = maybe_connecting.unwrap();
+                                let result = async {
+                                    let addresses = url.resolve().await?;
+                                    let started_at = Instant::now();
 
-            // The retry logic duplicates here because the `connecting.await` call can fail as well
-            // and we would like to handle this case as well
-            let maybe_connection = connecting.await;
+                                    race(&addresses, CONNECTION_ATTEMPT_DELAY, |address| {
+                                        connect_address(address, url.host.clone())
+                                    })
+                                    .await
+                                    .map(|connection| (connection, started_at.elapsed()))
+                                }
+                                .await;
 
-            if maybe_connection.is_err() {
-                println!("connection failed, retrying...");
+                                return match result {
+                                    Ok((connection, latency)) => {
+                                        balancer.record_success(index, latency);
+                                        Ok(connection)
+                                    }
+                                    Err(error) => {
+                                        balancer.record_failure(index);
+                                        Err(error)
+                                    }
+                                };
+                            }
+                        };
 
-                tokio::time::sleep(Duration::from_millis(self.config.retry_timeout_millis)).await;
+                        let endpoints = discovery.endpoints().await.map_err(|error| {
+                            ConnectionError::ResolutionFailed {
+                                host: discovery.name(),
+                                reason: error.to_string(),
+                            }
+                        })?;
 
-                if retries > self.config.max_retries {
-                    return Err(ClientError::ConnectionError(
-                        ConnectionError::MaxRetriesReached {
-                            retry_count: retries,
-                        },
-                    ));
-                }
+                        failover(endpoints, |discovered| async move {
+                            let addresses = discovered.resolve().await?;
 
-                retries += 1;
+                            race(&addresses, CONNECTION_ATTEMPT_DELAY, |address| {
+                                connect_address(address, discovered.server_name.clone())
+                            })
+                            .await
+                        })
+                        .await
+                    },
+                    retry_policy,
+                    max_retries,
+                    max_retry_elapsed,
+                )
+                .await?;
 
-                continue;
-            }
+                Ok(connection)
+            },
+            message,
+            times,
+        )
+        .await
+    }
 
-            break maybe_connection.unwrap();
-        };
+    /// Sends a message over the connections established by `connect`.
+    ///
+    /// This is what `send_message` does over WebTransport, it can be used to run the client over any other transport.
+    /// `connect` is called again whenever the connection is lost, after which the handshake is performed anew,
+    /// resuming the session, and the stream of the configured connection type is reopened.
+    /// The messages already sent are not sent again. The responses the server could not deliver are replayed
+    /// on resumption, the ones left without response are accounted as lost.
+    ///
+    /// # Arguments
+    /// * `connect` - Establishes a new connection to the server.
+    /// * `message` - The `Message` instance to be sent.
+    /// * `times` - The number of times to attempt sending the message.
+    ///
+    /// # Returns
+    /// * `Result` - An empty `Ok` result if the message is sent successfully, or a `ClientError` if an error occurs.
+    pub async fn send_message_over<C, F, Fut>(
+        &mut self,
+        mut connect: F,
+        message: &Message,
+        times: Option<u32>,
+    ) -> Result<(), ClientError>
+    where
+        C: Connection,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<C, ClientError>>,
+    {
+        let mut remaining = times;
+        let mut reconnects = 0;
 
-        match self.config.connection_type {
-            PingClientConnectionType::Bidirectional => {
-                send_bidirectional(&connection, message, times, &mut self.inbox).await?;
+        loop {
+            let connection = connect().await?;
+            let parameters = self.handshake(&connection, reconnects > 0).await?;
+
+            let sent_before = self.stats.sent();
+            let received_before = self.inbox.len();
+
+            let result = self
+                .send_over(&connection, &parameters, message, remaining)
+                .await;
+            self.record_round_trips(received_before);
+
+            let error = match result {
+                Ok(()) => return Ok(()),
+                Err(error) => error,
+            };
+
+            if error.is_connection_lost() {
+                self.record_connection_lost();
             }
-            PingClientConnectionType::Unidirectional => {
-                send_unidirectional(&connection, message, times, &mut self.inbox).await?;
+
+            if !error.is_connection_lost() || reconnects >= self.config.max_reconnects {
+                return Err(error);
             }
-            PingClientConnectionType::Datagram => {
-                send_datagram(&connection, message, times, &mut self.inbox).await?;
+
+            let sent = (self.stats.sent() - sent_before) as u32;
+            remaining = remaining.map(|count| count.saturating_sub(sent));
+
+            // The connection was lost while waiting for the last response, there is nothing left to send
+            if remaining == Some(0) {
+                return Ok(());
             }
+
+            println!("connection lost ({}), reconnecting...", error);
+
+            self.stats.record_reconnect(error.to_string());
+            reconnects += 1;
+        }
+    }
+
+    /// Tells where the servers every connection attempt is made to come from.
+    ///
+    /// The configured URLs are only needed when the servers are not discovered.
+    ///
+    /// # Returns
+    /// * `Result` - The discovery if configured, the load balancer otherwise, or a `ClientSetupError` if a URL is invalid
+    ///   or none is configured.
+    fn server_selection(&mut self) -> Result<ServerSelection, ClientSetupError> {
+        match &self.discovery {
+            Some(discovery) => Ok(ServerSelection::Discovered(discovery.clone())),
+            None => self.balancer().map(ServerSelection::Balanced),
+        }
+    }
+
+    /// Gives the load balancer spreading the connections over the configured URLs, creating it on first use.
+    ///
+    /// # Returns
+    /// * `Result` - The load balancer, or a `ClientSetupError` if a URL is invalid or none is configured.
+    fn balancer(&mut self) -> Result<Arc<LoadBalancer>, ClientSetupError> {
+        if let Some(balancer) = &self.balancer {
+            return Ok(balancer.clone());
+        }
+
+        let urls = self
+            .config
+            .urls
+            .iter()
+            .map(|url| ServerUrl::parse(url))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for url in urls.iter().filter(|url| url.requests_other_path()) {
+            eprintln!(
+                "WARNING: only the session at / is requested from {}, the path {} is not sent yet",
+                url, url.path
+            );
         }
+        let balancer = Arc::new(LoadBalancer::new(
+            urls,
+            self.config.selection_strategy.clone(),
+            self.config.ejection_policy,
+            &self.config.client_id,
+        )?);
 
-        Ok(())
+        self.balancer = Some(balancer.clone());
+        Ok(balancer)
+    }
+
+    /// Reports the round-trip times of the responses received since `received_before` to the load balancer,
+    /// as latencies of the server the client is connected to.
+    fn record_round_trips(&self, received_before: usize) {
+        let (balancer, index) = match self.connected_server() {
+            Some(connected) => connected,
+            None => return,
+        };
+
+        for round_trip_time in self.inbox[received_before..]
+            .iter()
+            .filter_map(|entry| entry.round_trip_time)
+        {
+            balancer.record_latency(index, round_trip_time);
+        }
     }
 
-    /// Returns the messages received by the client.
+    /// Reports the loss of the connection to the load balancer, as a failure of the server the client was connected to.
+    fn record_connection_lost(&self) {
+        if let Some((balancer, index)) = self.connected_server() {
+            balancer.record_failure(index);
+        }
+    }
+
+    /// The load balancer along with the index of the server the client is connected to, if connected through it.
+    fn connected_server(&self) -> Option<(&LoadBalancer, usize)> {
+        let balancer = self.balancer.as_deref()?;
+
+        balancer.connected().map(|index| (balancer, index))
+    }
+
+    /// Performs the handshake over a newly established connection.
+    ///
+    /// Only the configured codecs compiled into the client are offered, the handshake itself uses the preferred one.
+    /// When resuming, the responses replayed by the server are added to the inbox unless already received.
+    ///
+    /// # Arguments
+    /// * `connection` - The newly established connection.
+    /// * `resume` - Whether to resume the session of the previous connection, telling the server the requests
+    ///   whose responses are in the inbox already.
+    ///
+    /// # Returns
+    /// * `Result` - The negotiated `ConnectionParameters`, or a `ClientError` if the handshake has failed.
+    async fn handshake<C: Connection>(
+        &mut self,
+        connection: &C,
+        resume: bool,
+    ) -> Result<ConnectionParameters, ClientError> {
+        let codecs: Vec<String> = self
+            .config
+            .codecs
+            .iter()
+            .filter(|name| codec::codec_by_name(name).is_some())
+            .cloned()
+            .collect();
+
+        let preferred_codec = codecs
+            .first()
+            .and_then(|name| codec::codec_by_name(name))
+            .ok_or_else(|| ClientSetupError::UnsupportedCodecs {
+                codecs: self.config.codecs.clone(),
+            })?;
+
+        let capabilities = Capabilities {
+            codecs,
+            max_message_size: self.config.max_frame_size,
+            ..Capabilities::default()
+        };
+
+        let hello = HelloMessage::new(self.config.client_id.clone(), capabilities);
+        let received = resume.then(|| answered_requests(&self.inbox));
+        let (ack, replayed) =
+            perform_handshake(connection, &hello, preferred_codec, received).await?;
+        let parameters = ack.parameters()?;
+
+        for _ in 0..merge_replayed(&mut self.inbox, replayed) {
+            self.stats.record_replayed();
+        }
+
+        if let PingClientConnectionType::Datagram = self.config.connection_type {
+            if !parameters.datagrams_supported {
+                return Err(ClientError::HandshakeError(
+                    HandshakeError::DatagramsUnsupported,
+                ));
+            }
+        }
+
+        Ok(parameters)
+    }
+
+    /// Sends the message over the streams of the configured connection type, pipelined if configured.
+    ///
+    /// The configured number of streams is opened on the connection and the pings are spread over them,
+    /// every stream being driven concurrently with its own statistics, which are then added to the overall ones.
+    /// The first stream to fail ends the run, the other streams are given up on.
+    ///
+    /// # Arguments
+    /// * `connection` - The connection to send the message over.
+    /// * `parameters` - The connection parameters negotiated during the handshake.
+    /// * `message` - The `Message` instance to be sent.
+    /// * `times` - The number of times to send the message, over all the streams.
+    ///
+    /// # Returns
+    /// * `Result` - An empty `Ok` result if the message is sent successfully, or a `ClientError` if an error occurs
+    ///   on any of the streams.
+    async fn send_over<C: Connection>(
+        &mut self,
+        connection: &C,
+        parameters: &ConnectionParameters,
+        message: &Message,
+        times: Option<u32>,
+    ) -> Result<(), ClientError> {
+        // Streams left over by an interrupted call are done with
+        self.settle_streams();
+
+        if let PingClientConnectionType::Datagram = self.config.connection_type {
+            return send_datagram(
+                connection,
+                message,
+                times,
+                self.config.datagram_timeout,
+                &mut self.inbox,
+                &mut self.stats,
+                parameters,
+            )
+            .await;
+        }
+
+        let counts = split_count(times, self.config.streams);
+
+        let mut streams = Vec::with_capacity(counts.len());
+        for _ in 0..counts.len() {
+            // Unidirectional pairs are set up one after the other, so that every reply stream is matched
+            // with the request stream it has been opened for
+            streams.push(match self.config.connection_type {
+                PingClientConnectionType::Unidirectional => {
+                    (connection.open_uni().await?, connection.accept_uni().await?)
+                }
+                _ => connection.open_bi().await?,
+            });
+        }
+
+        let pipeline = self.config.pipeline;
+        self.stream_progress = counts.iter().map(|_| StreamProgress::default()).collect();
+
+        let result = try_join_all(
+            streams
+                .into_iter()
+                .zip(counts)
+                .zip(&mut self.stream_progress)
+                .map(|((streams, count), progress)| async move {
+                    let StreamProgress { inbox, stats } = progress;
+
+                    match &pipeline {
+                        Some(pipeline) => {
+                            send_pipelined(
+                                streams, message, count, pipeline, inbox, stats, parameters,
+                            )
+                            .await
+                        }
+                        None => {
+                            send_lock_step(streams, message, count, inbox, stats, parameters).await
+                        }
+                    }
+                }),
+        )
+        .await;
+
+        self.settle_streams();
+
+        result.map(|_| ())
+    }
+
+    /// Adds the pings sent on the streams of the current connection to the overall ones and to the ones
+    /// of their stream rank, once the connection is done with.
+    fn settle_streams(&mut self) {
+        if self.stream_stats.len() < self.stream_progress.len() {
+            self.stream_stats
+                .resize_with(self.stream_progress.len(), PingStatistics::new);
+        }
+
+        for (index, progress) in self.stream_progress.drain(..).enumerate() {
+            self.inbox.extend(progress.inbox);
+            self.stats.merge(&progress.stats);
+            self.stream_stats[index].merge(&progress.stats);
+        }
+    }
+
+    /// Returns the messages received by the client along with their round-trip times.
     /// This method is used for testing purposes.
     ///
     /// # Returns
     ///
-    /// Returns a `Vec<Message>` containing the messages received by the client.
-    pub fn get_indbox(&self) -> Vec<Message> {
-        self.inbox.clone()
+    /// Returns a `Vec<InboxEntry>` containing the messages received by the client.
+    pub fn get_indbox(&self) -> Vec<InboxEntry> {
+        self.inbox
+            .iter()
+            .chain(
+                self.stream_progress
+                    .iter()
+                    .flat_map(|progress| &progress.inbox),
+            )
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the latency statistics of all the pings sent by the client so far.
+    ///
+    /// The statistics are kept even if sending has failed or has been interrupted.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `PingSummary` with the sent and received counts, loss and round-trip times.
+    pub fn get_statistics(&self) -> PingSummary {
+        let mut stats = self.stats.clone();
+
+        for progress in &self.stream_progress {
+            stats.merge(&progress.stats);
+        }
+
+        stats.summary()
+    }
+
+    /// Returns the latency statistics of the pings sent on every stream so far.
+    ///
+    /// Streams are numbered in the order they are opened on every connection, the statistics of a stream
+    /// include the ones of the streams opened at the same rank before reconnecting.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `PingSummary` per stream, empty if no stream has been opened yet.
+    pub fn get_stream_statistics(&self) -> Vec<PingSummary> {
+        let ranks = self.stream_stats.len().max(self.stream_progress.len());
+
+        (0..ranks)
+            .map(|index| {
+                let mut stats = self.stream_stats.get(index).cloned().unwrap_or_default();
+
+                if let Some(progress) = self.stream_progress.get(index) {
+                    stats.merge(&progress.stats);
+                }
+
+                stats.summary()
+            })
+            .collect()
+    }
+}
+
+/// Spreads the pings over the streams, the first streams sending one more ping if they cannot be spread evenly.
+///
+/// No more streams than pings are used, but at least one.
+///
+/// # Arguments
+/// * `times` - The number of times to send the message, `None` to send it indefinitely on every stream.
+/// * `streams` - The configured number of streams.
+///
+/// # Returns
+/// Returns the number of times to send the message on every stream.
+fn split_count(times: Option<u32>, streams: usize) -> Vec<Option<u32>> {
+    let streams = match times {
+        Some(count) => streams.min(count as usize).max(1),
+        None => streams.max(1),
+    } as u32;
+
+    (0..streams)
+        .map(|index| times.map(|count| count / streams + u32::from(index < count % streams)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discovery::DiscoveryQuery;
+
+    #[test]
+    fn test_should_not_require_urls_when_discovering_servers() {
+        let mut discovering = PingClient::new(PingClientConfig {
+            urls: vec![],
+            discovery: Some(DiscoveryConfig::new(DiscoveryQuery::Srv {
+                name: "_pong._udp.example.internal".to_string(),
+            })),
+            ..Default::default()
+        });
+        let mut balancing = PingClient::new(PingClientConfig {
+            urls: vec![],
+            ..Default::default()
+        });
+
+        assert!(matches!(
+            discovering.server_selection(),
+            Ok(ServerSelection::Discovered(_))
+        ));
+        assert!(matches!(
+            balancing.server_selection(),
+            Err(ClientSetupError::NoServerUrl)
+        ));
+    }
+
+    #[test]
+    fn test_should_spread_pings_over_streams() {
+        assert_eq!(
+            split_count(Some(10), 4),
+            [Some(3), Some(3), Some(2), Some(2)]
+        );
+        assert_eq!(split_count(Some(2), 4), [Some(1), Some(1)]);
+        assert_eq!(split_count(Some(3), 0), [Some(3)]);
+        assert_eq!(split_count(None, 3), [None, None, None]);
     }
 }
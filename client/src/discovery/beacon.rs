@@ -0,0 +1,180 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use common::beacon::{Announcement, MAX_ANNOUNCEMENT_SIZE};
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use tokio::{net::UdpSocket, time::Instant};
+
+use crate::error::DiscoveryError;
+
+use super::DiscoveredEndpoint;
+
+/// Represents a server heard announcing itself on the local network.
+///
+/// # Fields
+/// * `address` - The address to connect to the server at, the announced one or else the one the announcement
+///   has been received from.
+/// * `announcement` - The last announcement of the server.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AnnouncedServer {
+    pub address: IpAddr,
+    pub announcement: Announcement,
+}
+
+impl AnnouncedServer {
+    /// The endpoint to connect to the server at.
+    pub fn endpoint(&self) -> DiscoveredEndpoint {
+        DiscoveredEndpoint {
+            server_name: self.announcement.server_name.clone(),
+            port: self.announcement.port,
+            addresses: vec![self.address],
+            priority: 0,
+            weight: 1,
+        }
+    }
+}
+
+/// Listens for the announcements of servers on the local network.
+///
+/// Datagrams which are not announcements are ignored, the group may be shared with other applications.
+/// A server announcing itself several times is listed once, with its last announcement.
+///
+/// # Arguments
+/// * `group` - The address announcements are sent to, usually a multicast group which is joined on
+///   the default interface. Other applications can listen to the same group at the same time.
+/// * `duration` - How long to listen, at least the interval between two announcements to hear every server.
+///
+/// # Returns
+/// * `Result` - The servers heard, in the order they have been heard first, or a `DiscoveryError::IoError`
+///   if the group could not be listened to.
+pub async fn listen(
+    group: SocketAddr,
+    duration: Duration,
+) -> Result<Vec<AnnouncedServer>, DiscoveryError> {
+    let deadline = Instant::now() + duration;
+    let socket = bind(group)?;
+
+    let mut servers: Vec<AnnouncedServer> = Vec::new();
+    let mut buf = [0; MAX_ANNOUNCEMENT_SIZE];
+
+    while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        let (read, source) = received?;
+
+        let announcement = match Announcement::decode(&buf[..read]) {
+            Ok(announcement) => announcement,
+            Err(_) => continue,
+        };
+        let server = AnnouncedServer {
+            address: announcement.host.unwrap_or(source.ip()),
+            announcement,
+        };
+
+        match servers.iter_mut().find(|heard| {
+            heard.address == server.address && heard.announcement.port == server.announcement.port
+        }) {
+            Some(heard) => *heard = server,
+            None => servers.push(server),
+        }
+    }
+
+    Ok(servers)
+}
+
+/// Binds the socket announcements are received on, joining `group` if it is a multicast group.
+fn bind(group: SocketAddr) -> Result<UdpSocket, DiscoveryError> {
+    let socket = Socket::new(Domain::for_address(group), Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+
+    let any_port = |address: IpAddr| SockAddr::from(SocketAddr::new(address, group.port()));
+
+    match group.ip() {
+        IpAddr::V4(address) if address.is_multicast() => {
+            socket.bind(&any_port(IpAddr::V4(Ipv4Addr::UNSPECIFIED)))?;
+            socket.join_multicast_v4(&address, &Ipv4Addr::UNSPECIFIED)?;
+        }
+        IpAddr::V6(address) if address.is_multicast() => {
+            socket.set_only_v6(true)?;
+            socket.bind(&any_port(IpAddr::V6(Ipv6Addr::UNSPECIFIED)))?;
+            socket.join_multicast_v6(&address, 0)?;
+        }
+        // Broadcast announcements are received on their port, whatever the address
+        IpAddr::V4(address) if address.is_broadcast() => {
+            socket.bind(&any_port(IpAddr::V4(Ipv4Addr::UNSPECIFIED)))?;
+        }
+        _ => socket.bind(&SockAddr::from(group))?,
+    }
+
+    socket.set_nonblocking(true)?;
+
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Finds a free port on the loopback interface to send announcements to.
+    fn free_address() -> SocketAddr {
+        std::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    }
+
+    fn announcement(port: u16, host: Option<IpAddr>) -> Announcement {
+        Announcement {
+            server_name: "localhost".to_string(),
+            host,
+            port,
+            certificate_hash: None,
+            protocol_version: 1,
+            ttl: Duration::from_secs(15),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_should_list_announced_servers_once() {
+        let group = free_address();
+        let listening = tokio::spawn(listen(group, Duration::from_millis(300)));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let sender = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let announced_host = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 7));
+        for payload in [
+            announcement(4433, None).encode(),
+            b"not an announcement".to_vec(),
+            announcement(4434, Some(announced_host)).encode(),
+            announcement(4433, None).encode(),
+        ] {
+            sender.send_to(&payload, group).await.unwrap();
+        }
+
+        let servers = listening.await.unwrap().unwrap();
+
+        assert_eq!(
+            servers,
+            vec![
+                AnnouncedServer {
+                    address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                    announcement: announcement(4433, None),
+                },
+                AnnouncedServer {
+                    address: announced_host,
+                    announcement: announcement(4434, Some(announced_host)),
+                },
+            ]
+        );
+        assert_eq!(
+            servers[1].endpoint(),
+            DiscoveredEndpoint {
+                server_name: "localhost".to_string(),
+                port: 4434,
+                addresses: vec![announced_host],
+                priority: 0,
+                weight: 1,
+            }
+        );
+    }
+}
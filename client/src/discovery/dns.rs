@@ -0,0 +1,129 @@
+use std::{net::SocketAddr, str::FromStr, time::Duration};
+
+use hickory_resolver::{
+    config::{NameServerConfigGroup, ResolverConfig, ResolverOpts},
+    error::{ResolveError, ResolveErrorKind},
+    proto::{
+        op::ResponseCode,
+        rr::{Name, Record, RecordType},
+    },
+    system_conf::read_system_conf,
+    TokioAsyncResolver,
+};
+
+use crate::error::DiscoveryError;
+
+/// Creates the resolver discovery lookups are made with.
+///
+/// Every lookup is a single query, answered within `timeout` or given up on. The resolver does not cache
+/// the answers, as the discovered endpoints are cached for the TTL of their records anyway.
+///
+/// # Arguments
+/// * `nameserver` - The nameserver to query, the ones of `/etc/resolv.conf` if `None`.
+/// * `timeout` - How long to wait for the answer of the nameserver.
+///
+/// # Returns
+/// * `Result` - The resolver, or `DiscoveryError::NoNameserver` if no nameserver is configured.
+pub fn resolver(
+    nameserver: Option<SocketAddr>,
+    timeout: Duration,
+) -> Result<TokioAsyncResolver, DiscoveryError> {
+    let (config, mut options) = match nameserver {
+        Some(nameserver) => (
+            ResolverConfig::from_parts(
+                None,
+                vec![],
+                NameServerConfigGroup::from_ips_clear(&[nameserver.ip()], nameserver.port(), true),
+            ),
+            ResolverOpts::default(),
+        ),
+        None => read_system_conf().map_err(|_| DiscoveryError::NoNameserver)?,
+    };
+
+    if config.name_servers().is_empty() {
+        return Err(DiscoveryError::NoNameserver);
+    }
+
+    options.timeout = timeout;
+    options.attempts = 1;
+    options.cache_size = 0;
+    options.use_hosts_file = false;
+
+    Ok(TokioAsyncResolver::tokio(config, options))
+}
+
+/// Looks up the records of a name.
+///
+/// # Arguments
+/// * `resolver` - The resolver to look the name up with.
+/// * `name` - The name to look up.
+/// * `record_type` - The type of the records to look up.
+///
+/// # Returns
+/// * `Result` - The records answering the question, along with the addresses of the SRV targets the nameserver
+///   has added, empty if the name has no record of that type, or a `DiscoveryError` if the name cannot be resolved.
+pub async fn lookup(
+    resolver: &TokioAsyncResolver,
+    name: &str,
+    record_type: RecordType,
+) -> Result<Vec<Record>, DiscoveryError> {
+    let query_name = Name::from_str(name).map_err(|_| DiscoveryError::InvalidName {
+        name: name.to_string(),
+    })?;
+
+    match resolver.lookup(query_name, record_type).await {
+        Ok(lookup) => Ok(lookup.records().to_vec()),
+        Err(error) => match error.kind() {
+            ResolveErrorKind::NoRecordsFound {
+                response_code: ResponseCode::NoError,
+                ..
+            } => Ok(vec![]),
+            _ => Err(lookup_error(name, error)),
+        },
+    }
+}
+
+/// Maps the error of a failed lookup.
+fn lookup_error(name: &str, error: ResolveError) -> DiscoveryError {
+    let name = name.to_string();
+
+    match error.kind() {
+        ResolveErrorKind::NoRecordsFound {
+            response_code: ResponseCode::NXDomain,
+            ..
+        } => DiscoveryError::NameNotFound { name },
+        ResolveErrorKind::NoRecordsFound { response_code, .. } => DiscoveryError::ServerFailure {
+            name,
+            code: (*response_code).into(),
+        },
+        ResolveErrorKind::Timeout => DiscoveryError::TimedOut { name },
+        ResolveErrorKind::Io(error) => DiscoveryError::IoError {
+            kind: error.kind(),
+            message: error.to_string(),
+        },
+        _ => DiscoveryError::LookupFailed {
+            name,
+            reason: error.to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_should_reject_invalid_names() {
+        let resolver =
+            resolver(Some(([127, 0, 0, 1], 53).into()), Duration::from_millis(50)).unwrap();
+
+        for name in ["pong..svc", &"a".repeat(64)] {
+            assert_eq!(
+                lookup(&resolver, name, RecordType::A).await,
+                Err(DiscoveryError::InvalidName {
+                    name: name.to_string()
+                })
+            );
+        }
+    }
+}
@@ -0,0 +1,779 @@
+pub mod beacon;
+pub mod dns;
+
+use std::{
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    sync::Mutex,
+    time::Duration,
+};
+
+use common::{error::ConnectionError, message::hello::PROTOCOL_VERSION};
+use hickory_resolver::proto::rr::{Name, RData, Record, RecordType};
+use rand::{Rng, RngCore};
+use tokio::time::Instant;
+
+use crate::{
+    error::DiscoveryError,
+    happy_eyeballs::{interleave_families, most_telling_error},
+    target::ServerUrl,
+};
+
+/// Represents what is looked up to discover the endpoints of a service.
+///
+/// * `Srv` - The SRV records of `name`, e.g. `_pong._udp.pong.default.svc.cluster.local`.
+///   Every record points at a target host and port, ranked by priority and weight. The certificate of
+///   an endpoint is verified for its target host.
+/// * `Headless` - The A and AAAA records of `name`, e.g. the ones of a Kubernetes headless service
+///   `pong.default.svc.cluster.local`, which has an address for every ready pod. All the endpoints listen
+///   on `port`, their certificates are verified for `name`.
+/// * `Beacon` - The announcements servers send to `group` on the local network, see `beacon::listen`.
+///   The certificate of an endpoint is verified for its announced server name.
+#[derive(Debug, PartialEq, Clone)]
+pub enum DiscoveryQuery {
+    Srv { name: String },
+    Headless { name: String, port: u16 },
+    Beacon { group: SocketAddr },
+}
+
+impl DiscoveryQuery {
+    /// The name looked up, or the group listened to for announcements.
+    pub fn name(&self) -> String {
+        match self {
+            DiscoveryQuery::Srv { name } | DiscoveryQuery::Headless { name, .. } => name.clone(),
+            DiscoveryQuery::Beacon { group } => group.to_string(),
+        }
+    }
+}
+
+/// Represents the configuration of the service discovery.
+///
+/// # Fields
+/// * `query` - What is looked up to discover the endpoints.
+/// * `nameserver` - The nameserver to query, the ones of `/etc/resolv.conf` if `None`.
+/// * `timeout` - How long to wait for the answer of the nameserver, or to listen for announcements.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DiscoveryConfig {
+    pub query: DiscoveryQuery,
+    pub nameserver: Option<SocketAddr>,
+    pub timeout: Duration,
+}
+
+impl DiscoveryConfig {
+    /// Creates the configuration of a discovery through the system nameserver.
+    ///
+    /// Announcements are listened to for 2 seconds, twice the default interval servers announce themselves at.
+    ///
+    /// # Arguments
+    /// * `query` - What is looked up to discover the endpoints.
+    ///
+    /// # Returns
+    /// Returns a `DiscoveryConfig` instance.
+    pub fn new(query: DiscoveryQuery) -> Self {
+        Self {
+            query,
+            nameserver: None,
+            timeout: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Represents an endpoint of a discovered service.
+///
+/// # Fields
+/// * `server_name` - The name the certificate of the endpoint is verified for.
+/// * `port` - The port the endpoint listens on.
+/// * `addresses` - The addresses of the endpoint given by the nameserver, empty if `server_name` has to be resolved.
+/// * `priority` - The priority of the endpoint, lower ones are tried first.
+/// * `weight` - The relative weight of the endpoint among the ones of the same priority.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DiscoveredEndpoint {
+    pub server_name: String,
+    pub port: u16,
+    pub addresses: Vec<IpAddr>,
+    pub priority: u16,
+    pub weight: u16,
+}
+
+impl DiscoveredEndpoint {
+    /// Resolves the addresses to connect to the endpoint at.
+    ///
+    /// # Returns
+    /// * `Result` - The addresses in happy eyeballs order, or a `ConnectionError::ResolutionFailed`
+    ///   if the server name had to be resolved and could not be.
+    pub async fn resolve(&self) -> Result<Vec<SocketAddr>, ConnectionError> {
+        if self.addresses.is_empty() {
+            let url = ServerUrl {
+                host: self.server_name.clone(),
+                port: self.port,
+                path: "/".to_string(),
+            };
+
+            return url.resolve().await;
+        }
+
+        Ok(interleave_families(
+            self.addresses
+                .iter()
+                .map(|address| SocketAddr::new(*address, self.port))
+                .collect(),
+        ))
+    }
+}
+
+/// The endpoints of the last lookup, kept until their TTL expires.
+#[derive(Clone)]
+struct CachedEndpoints {
+    expires_at: Instant,
+    endpoints: Vec<DiscoveredEndpoint>,
+}
+
+/// Discovers the endpoints of a service through DNS or announcements, caching them for the TTL of their records.
+///
+/// The endpoints are ranked anew every time they are asked for, so that clients sharing a cached answer
+/// still spread over the endpoints of the same priority. If the nameserver cannot be reached, the expired
+/// endpoints are served until it is reachable again, rather than not connecting at all.
+pub struct Discovery {
+    config: DiscoveryConfig,
+    cache: Mutex<Option<CachedEndpoints>>,
+}
+
+impl Discovery {
+    /// Creates a new `Discovery`, nothing is looked up until endpoints are asked for.
+    ///
+    /// # Arguments
+    /// * `config` - The configuration of the discovery.
+    ///
+    /// # Returns
+    /// Returns a `Discovery` instance.
+    pub fn new(config: DiscoveryConfig) -> Self {
+        Self {
+            config,
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// The name looked up, or the group listened to for announcements.
+    pub fn name(&self) -> String {
+        self.config.query.name()
+    }
+
+    /// Gives the endpoints of the service, looking them up if the cached ones have expired.
+    ///
+    /// # Returns
+    /// * `Result` - The endpoints ranked in the order they should be tried in, or a `DiscoveryError`
+    ///   if they could not be looked up.
+    pub async fn endpoints(&self) -> Result<Vec<DiscoveredEndpoint>, DiscoveryError> {
+        let now = Instant::now();
+        let cached = self.cache.lock().expect("discovery cache poisoned").clone();
+
+        let endpoints = match cached {
+            Some(cached) if cached.expires_at > now => cached.endpoints,
+            stale => match self.lookup().await {
+                Ok((endpoints, ttl)) => {
+                    *self.cache.lock().expect("discovery cache poisoned") = Some(CachedEndpoints {
+                        expires_at: now + ttl,
+                        endpoints: endpoints.clone(),
+                    });
+
+                    endpoints
+                }
+                Err(
+                    DiscoveryError::TimedOut { .. }
+                    | DiscoveryError::IoError { .. }
+                    | DiscoveryError::ServerFailure { .. }
+                    | DiscoveryError::LookupFailed { .. },
+                ) if stale.is_some() => stale.expect("checked by guard").endpoints,
+                Err(error) => return Err(error),
+            },
+        };
+
+        Ok(rank(&endpoints, &mut rand::thread_rng()))
+    }
+
+    /// Looks up the endpoints, returning them along with how long they can be cached.
+    async fn lookup(&self) -> Result<(Vec<DiscoveredEndpoint>, Duration), DiscoveryError> {
+        let resolver = || dns::resolver(self.config.nameserver, self.config.timeout);
+        let timeout = self.config.timeout;
+
+        let (endpoints, ttls): (Vec<_>, Vec<_>) = match &self.config.query {
+            DiscoveryQuery::Srv { name } => {
+                let records = dns::lookup(&resolver()?, name, RecordType::SRV).await?;
+
+                records
+                    .iter()
+                    .filter_map(|record| srv_endpoint(record, &records))
+                    .unzip()
+            }
+            DiscoveryQuery::Headless { name, port } => {
+                let resolver = resolver()?;
+                let (ipv4, ipv6) = tokio::join!(
+                    dns::lookup(&resolver, name, RecordType::A),
+                    dns::lookup(&resolver, name, RecordType::AAAA)
+                );
+
+                // A service may have addresses of a single family only
+                let lookups = match (ipv4, ipv6) {
+                    (Err(error), Err(_)) => return Err(error),
+                    (ipv4, ipv6) => [ipv4.ok(), ipv6.ok()],
+                };
+
+                lookups
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .filter_map(|record| {
+                        let endpoint = DiscoveredEndpoint {
+                            server_name: name.clone(),
+                            port: *port,
+                            addresses: vec![address(&record)?],
+                            priority: 0,
+                            weight: 1,
+                        };
+
+                        Some((endpoint, ttl(&record)))
+                    })
+                    .unzip()
+            }
+            DiscoveryQuery::Beacon { group } => beacon::listen(*group, timeout)
+                .await?
+                .into_iter()
+                // Servers speaking another protocol version would reject the handshake anyway
+                .filter(|server| server.announcement.protocol_version == PROTOCOL_VERSION)
+                .map(|server| (server.endpoint(), server.announcement.ttl))
+                .unzip(),
+        };
+
+        if endpoints.is_empty() {
+            return Err(DiscoveryError::NoEndpoints { name: self.name() });
+        }
+
+        Ok((endpoints, ttls.into_iter().min().unwrap_or_default()))
+    }
+}
+
+/// Creates the endpoint of an SRV record, with the addresses of its target found among the other records.
+///
+/// Returns the endpoint along with the shortest TTL of the records it is made of, or `None` if the record is
+/// not an SRV record or tells that the service is not available, with `.` as its target.
+fn srv_endpoint(record: &Record, records: &[Record]) -> Option<(DiscoveredEndpoint, Duration)> {
+    let srv = match record.data() {
+        Some(RData::SRV(srv)) if !srv.target().is_root() => srv,
+        _ => return None,
+    };
+
+    let mut srv_ttl = ttl(record);
+    let addresses = records
+        .iter()
+        .filter(|address| address.name() == srv.target())
+        .filter_map(|address| {
+            let ip = self::address(address)?;
+
+            srv_ttl = srv_ttl.min(ttl(address));
+            Some(ip)
+        })
+        .collect();
+
+    let endpoint = DiscoveredEndpoint {
+        server_name: host(srv.target()),
+        port: srv.port(),
+        addresses,
+        priority: srv.priority(),
+        weight: srv.weight(),
+    };
+
+    Some((endpoint, srv_ttl))
+}
+
+/// Gives the address of an A or AAAA record, `None` for records of other types.
+fn address(record: &Record) -> Option<IpAddr> {
+    match record.data()? {
+        RData::A(address) => Some(IpAddr::V4(address.0)),
+        RData::AAAA(address) => Some(IpAddr::V6(address.0)),
+        _ => None,
+    }
+}
+
+/// Gives how long a record may be cached.
+fn ttl(record: &Record) -> Duration {
+    Duration::from_secs(record.ttl().into())
+}
+
+/// Gives a name as a hostname, lowercased and without the trailing dot.
+fn host(name: &Name) -> String {
+    let host = name.to_lowercase().to_ascii();
+
+    host.strip_suffix('.').unwrap_or(&host).to_string()
+}
+
+/// Ranks endpoints as described by RFC 2782.
+///
+/// Lower priorities go first. Within a priority, endpoints are picked at random with a probability
+/// proportional to their weight, those with a zero weight having a small chance to go first.
+///
+/// # Arguments
+/// * `endpoints` - The endpoints to rank.
+/// * `rng` - The source of randomness of the weighted selection.
+///
+/// # Returns
+/// Returns the endpoints in the order they should be tried in.
+pub fn rank(endpoints: &[DiscoveredEndpoint], rng: &mut dyn RngCore) -> Vec<DiscoveredEndpoint> {
+    let mut remaining = endpoints.to_vec();
+    remaining.sort_by_key(|endpoint| endpoint.priority);
+
+    let mut ranked = Vec::with_capacity(remaining.len());
+
+    while let Some(first) = remaining.first() {
+        let priority = first.priority;
+        let count = remaining
+            .iter()
+            .take_while(|endpoint| endpoint.priority == priority)
+            .count();
+
+        let mut group = remaining.drain(..count).collect::<Vec<_>>();
+        group.sort_by_key(|endpoint| endpoint.weight != 0);
+
+        while !group.is_empty() {
+            let total_weight: u32 = group
+                .iter()
+                .map(|endpoint| u32::from(endpoint.weight))
+                .sum();
+            let selected = rng.gen_range(0..=total_weight);
+
+            let mut running_weight = 0;
+            let index = group
+                .iter()
+                .position(|endpoint| {
+                    running_weight += u32::from(endpoint.weight);
+                    running_weight >= selected
+                })
+                .expect("selected weight is at most the total weight");
+
+            ranked.push(group.remove(index));
+        }
+    }
+
+    ranked
+}
+
+/// Connects to the first endpoint accepting the connection, trying them in the given order.
+///
+/// # Arguments
+/// * `endpoints` - The ranked endpoints.
+/// * `connect` - Attempts to connect to a single endpoint.
+///
+/// # Returns
+/// * `Result` - The result of the first successful attempt. If all the attempts fail, the
+///   `ConnectionError::CertificateVerificationFailed` of any of them, or the error of the first attempt otherwise.
+pub async fn failover<T, F, Fut>(
+    endpoints: Vec<DiscoveredEndpoint>,
+    mut connect: F,
+) -> Result<T, ConnectionError>
+where
+    F: FnMut(DiscoveredEndpoint) -> Fut,
+    Fut: Future<Output = Result<T, ConnectionError>>,
+{
+    let mut errors = Vec::new();
+
+    for endpoint in endpoints {
+        match connect(endpoint).await {
+            Ok(value) => return Ok(value),
+            Err(error) => errors.push(error),
+        }
+    }
+
+    Err(most_telling_error(errors))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        net::{Ipv4Addr, Ipv6Addr},
+        str::FromStr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use hickory_resolver::proto::{
+        op::{Message, MessageType, ResponseCode},
+        rr::rdata::SRV,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+    use tokio::net::UdpSocket;
+
+    use super::*;
+
+    const SERVICE: &str = "_pong._udp.pong.default.svc";
+    const HEADLESS: &str = "pong.default.svc";
+
+    /// The answer of the stub nameserver to a question: its response code, answers and additional records.
+    type StubAnswer = (ResponseCode, Vec<Record>, Vec<Record>);
+
+    /// Starts a nameserver answering the questions found in `answers`, returning its address
+    /// and the number of queries it has received.
+    async fn stub_nameserver(
+        answers: HashMap<(String, RecordType), StubAnswer>,
+    ) -> (SocketAddr, Arc<AtomicUsize>) {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let address = socket.local_addr().unwrap();
+        let queries = Arc::new(AtomicUsize::new(0));
+        let counter = queries.clone();
+
+        tokio::spawn(async move {
+            let mut buf = [0; 512];
+
+            while let Ok((read, peer)) = socket.recv_from(&mut buf).await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let query = Message::from_vec(&buf[..read]).unwrap();
+                let question = query.queries()[0].clone();
+
+                let (code, answers, additional) = answers
+                    .get(&(host(question.name()), question.query_type()))
+                    .cloned()
+                    .unwrap_or((ResponseCode::NoError, vec![], vec![]));
+
+                let mut response = Message::new();
+                response
+                    .set_id(query.id())
+                    .set_message_type(MessageType::Response)
+                    .set_recursion_desired(true)
+                    .set_recursion_available(true)
+                    .set_response_code(code)
+                    .add_query(question)
+                    .add_answers(answers)
+                    .add_additionals(additional);
+
+                socket
+                    .send_to(&response.to_vec().unwrap(), peer)
+                    .await
+                    .unwrap();
+            }
+        });
+
+        (address, queries)
+    }
+
+    fn name(name: &str) -> Name {
+        Name::from_str(name).unwrap()
+    }
+
+    fn record(record_name: &str, ttl: u32, data: RData) -> Record {
+        Record::from_rdata(name(record_name), ttl, data)
+    }
+
+    fn srv(priority: u16, weight: u16, target: &str) -> RData {
+        // The root stands for no target at all
+        let target = match target {
+            "" => Name::root(),
+            target => name(target),
+        };
+
+        RData::SRV(SRV::new(priority, weight, 4433, target))
+    }
+
+    fn endpoint(server_name: &str, priority: u16, weight: u16) -> DiscoveredEndpoint {
+        DiscoveredEndpoint {
+            server_name: server_name.to_string(),
+            port: 4433,
+            addresses: vec![],
+            priority,
+            weight,
+        }
+    }
+
+    fn discovery(nameserver: SocketAddr, query: DiscoveryQuery) -> Discovery {
+        Discovery::new(DiscoveryConfig {
+            nameserver: Some(nameserver),
+            timeout: Duration::from_millis(500),
+            ..DiscoveryConfig::new(query)
+        })
+    }
+
+    fn srv_answers(ttl: u32) -> HashMap<(String, RecordType), StubAnswer> {
+        HashMap::from([(
+            (SERVICE.to_string(), RecordType::SRV),
+            (
+                ResponseCode::NoError,
+                vec![
+                    record(SERVICE, ttl, srv(20, 1, "pong-backup.default.svc")),
+                    record(SERVICE, ttl, srv(10, 1, "pong-0.default.svc")),
+                ],
+                vec![record(
+                    "pong-0.default.svc",
+                    ttl,
+                    RData::A(Ipv4Addr::new(10, 0, 0, 7).into()),
+                )],
+            ),
+        )])
+    }
+
+    #[tokio::test]
+    async fn test_should_discover_srv_endpoints_by_priority() {
+        let (nameserver, _) = stub_nameserver(srv_answers(30)).await;
+
+        let endpoints = discovery(
+            nameserver,
+            DiscoveryQuery::Srv {
+                name: SERVICE.to_string(),
+            },
+        )
+        .endpoints()
+        .await
+        .unwrap();
+
+        assert_eq!(
+            endpoints,
+            vec![
+                DiscoveredEndpoint {
+                    addresses: vec![IpAddr::V4(Ipv4Addr::new(10, 0, 0, 7))],
+                    ..endpoint("pong-0.default.svc", 10, 1)
+                },
+                endpoint("pong-backup.default.svc", 20, 1),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_should_discover_headless_service_addresses() {
+        let (nameserver, _) = stub_nameserver(HashMap::from([
+            (
+                (HEADLESS.to_string(), RecordType::A),
+                (
+                    ResponseCode::NoError,
+                    vec![
+                        record(HEADLESS, 30, RData::A(Ipv4Addr::new(10, 0, 0, 7).into())),
+                        record(HEADLESS, 30, RData::A(Ipv4Addr::new(10, 0, 0, 8).into())),
+                    ],
+                    vec![],
+                ),
+            ),
+            (
+                (HEADLESS.to_string(), RecordType::AAAA),
+                (
+                    ResponseCode::NoError,
+                    vec![record(
+                        HEADLESS,
+                        30,
+                        RData::AAAA(Ipv6Addr::LOCALHOST.into()),
+                    )],
+                    vec![],
+                ),
+            ),
+        ]))
+        .await;
+
+        let mut addresses = discovery(
+            nameserver,
+            DiscoveryQuery::Headless {
+                name: HEADLESS.to_string(),
+                port: 4433,
+            },
+        )
+        .endpoints()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|endpoint| {
+            assert_eq!(endpoint.server_name, HEADLESS);
+            endpoint.addresses[0]
+        })
+        .collect::<Vec<_>>();
+        addresses.sort();
+
+        assert_eq!(
+            addresses,
+            vec![
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 7)),
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 8)),
+                IpAddr::V6(Ipv6Addr::LOCALHOST),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_should_cache_endpoints_for_their_ttl() {
+        let query = DiscoveryQuery::Srv {
+            name: SERVICE.to_string(),
+        };
+
+        let (nameserver, queries) = stub_nameserver(srv_answers(60)).await;
+        let cached = discovery(nameserver, query.clone());
+        cached.endpoints().await.unwrap();
+        cached.endpoints().await.unwrap();
+
+        assert_eq!(queries.load(Ordering::SeqCst), 1);
+
+        let (nameserver, queries) = stub_nameserver(srv_answers(0)).await;
+        let expired = discovery(nameserver, query);
+        expired.endpoints().await.unwrap();
+        expired.endpoints().await.unwrap();
+
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_should_serve_stale_endpoints_while_nameserver_is_unreachable() {
+        let (nameserver, _) = stub_nameserver(srv_answers(0)).await;
+        let mut discovery = discovery(
+            nameserver,
+            DiscoveryQuery::Srv {
+                name: SERVICE.to_string(),
+            },
+        );
+        let endpoints = discovery.endpoints().await.unwrap();
+
+        // Nothing answers on the port of the silent socket
+        let silent = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        discovery.config.nameserver = Some(silent.local_addr().unwrap());
+        discovery.config.timeout = Duration::from_millis(50);
+
+        assert_eq!(discovery.endpoints().await.unwrap().len(), endpoints.len());
+
+        discovery.cache = Mutex::new(None);
+        assert_eq!(
+            discovery.endpoints().await,
+            Err(DiscoveryError::TimedOut {
+                name: SERVICE.to_string()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_should_report_missing_service() {
+        let missing = "_missing._udp.svc";
+        let (nameserver, _) = stub_nameserver(HashMap::from([
+            (
+                (missing.to_string(), RecordType::SRV),
+                (ResponseCode::NXDomain, vec![], vec![]),
+            ),
+            (
+                (SERVICE.to_string(), RecordType::SRV),
+                (
+                    ResponseCode::NoError,
+                    vec![record(SERVICE, 30, srv(0, 0, ""))],
+                    vec![],
+                ),
+            ),
+        ]))
+        .await;
+
+        let result = discovery(
+            nameserver,
+            DiscoveryQuery::Srv {
+                name: missing.to_string(),
+            },
+        )
+        .endpoints()
+        .await;
+        assert_eq!(
+            result,
+            Err(DiscoveryError::NameNotFound {
+                name: missing.to_string()
+            })
+        );
+
+        // A single record targetting the root tells that the service is not available
+        let result = discovery(
+            nameserver,
+            DiscoveryQuery::Srv {
+                name: SERVICE.to_string(),
+            },
+        )
+        .endpoints()
+        .await;
+        assert_eq!(
+            result,
+            Err(DiscoveryError::NoEndpoints {
+                name: SERVICE.to_string()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_should_discover_announcing_servers_speaking_same_version() {
+        let group = std::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+            .and_then(|socket| socket.local_addr())
+            .unwrap();
+        let announcement = |port, protocol_version| common::beacon::Announcement {
+            server_name: "localhost".to_string(),
+            host: None,
+            port,
+            certificate_hash: None,
+            protocol_version,
+            ttl: Duration::from_secs(3),
+        };
+
+        let sender = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let announcing = tokio::spawn(async move {
+            loop {
+                for announcement in [
+                    announcement(4433, PROTOCOL_VERSION),
+                    announcement(4434, PROTOCOL_VERSION + 1),
+                ] {
+                    sender.send_to(&announcement.encode(), group).await.unwrap();
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        });
+
+        let endpoints = Discovery::new(DiscoveryConfig {
+            timeout: Duration::from_millis(200),
+            ..DiscoveryConfig::new(DiscoveryQuery::Beacon { group })
+        })
+        .endpoints()
+        .await;
+        announcing.abort();
+
+        assert_eq!(
+            endpoints,
+            Ok(vec![DiscoveredEndpoint {
+                addresses: vec![IpAddr::V4(Ipv4Addr::LOCALHOST)],
+                ..endpoint("localhost", 0, 1)
+            }])
+        );
+    }
+
+    #[test]
+    fn test_should_rank_by_priority_then_weight() {
+        let endpoints = vec![
+            endpoint("backup", 20, 100),
+            endpoint("light", 10, 1),
+            endpoint("heavy", 10, 9),
+        ];
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let mut heavy_first = 0;
+        for _ in 0..1000 {
+            let ranked = rank(&endpoints, &mut rng);
+
+            assert_eq!(ranked[2].server_name, "backup");
+            if ranked[0].server_name == "heavy" {
+                heavy_first += 1;
+            }
+        }
+
+        // The selected weight ranges from 0 to 10 included, 9 of which pick the heavy endpoint
+        assert!((760..=880).contains(&heavy_first), "{}", heavy_first);
+    }
+
+    #[tokio::test]
+    async fn test_should_fail_over_to_next_endpoint() {
+        let endpoints = vec![endpoint("first", 0, 0), endpoint("second", 0, 0)];
+
+        let result = failover(endpoints.clone(), |endpoint| async move {
+            match endpoint.server_name.as_str() {
+                "first" => Err(ConnectionError::TimedOut),
+                name => Ok(name.to_string()),
+            }
+        })
+        .await;
+        assert_eq!(result, Ok("second".to_string()));
+
+        let result: Result<(), _> =
+            failover(endpoints, |_| async { Err(ConnectionError::TimedOut) }).await;
+        assert_eq!(result, Err(ConnectionError::TimedOut));
+    }
+}
@@ -1,4 +1,13 @@
-use common::error::{ConnectionError, StreamError};
+use common::{
+    error::{
+        ConnectionError, DatagramError, HandshakeError, ReadStreamError, RpcError, StreamError,
+        WriteStreamError,
+    },
+    message::{
+        error::{ErrorCode, ErrorMessage},
+        id::MessageId,
+    },
+};
 use thiserror::Error;
 
 /// Represents all the errors that can occur in the Client.
@@ -7,6 +16,9 @@ use thiserror::Error;
 /// * `SetupError`: An error occurred during the setup process.
 /// * `ClientStreamError`: An error occurred during streaming.
 /// * `ConnectionError`: An error occurred during connection setup or maintenance.
+/// * `HandshakeError`: The server has rejected the client or the handshake could not be completed.
+/// * `ErrorResponse`: The server has responded with an error message instead of a response.
+/// * `RpcError`: The parameters of a remote procedure call could not be encoded or its result decoded.
 #[derive(Error, Debug, PartialEq, Clone)]
 pub enum ClientError {
     #[error(transparent)]
@@ -17,17 +29,131 @@ pub enum ClientError {
 
     #[error("Client connection error: {0}")]
     ConnectionError(#[from] ConnectionError),
+
+    #[error("Client handshake error: {0}")]
+    HandshakeError(#[from] HandshakeError),
+
+    #[error("server responded with error {code}: {reason}")]
+    ErrorResponse {
+        code: ErrorCode,
+        reason: String,
+        request_id: Option<MessageId>,
+    },
+
+    #[error(transparent)]
+    RpcError(#[from] RpcError),
+}
+
+impl ClientError {
+    /// Tells whether the error is caused by the loss of the connection, after which the client can reconnect.
+    ///
+    /// That is the case if the connection has been closed by the server or has timed out,
+    /// or if a stream or the datagrams have been cut off by the closed connection.
+    ///
+    /// # Returns
+    /// Returns `true` if the connection has been lost.
+    pub fn is_connection_lost(&self) -> bool {
+        matches!(
+            self,
+            ClientError::ConnectionError(
+                ConnectionError::ClosedByPeer { .. } | ConnectionError::TimedOut
+            ) | ClientError::ClientStreamError(
+                StreamError::ReadError(
+                    ReadStreamError::ConnectionClosed
+                        | ReadStreamError::DatagramError(DatagramError::ConnectionClosed)
+                ) | StreamError::WriteError(
+                    WriteStreamError::ConnectionClosed
+                        | WriteStreamError::DatagramError(DatagramError::ConnectionClosed)
+                )
+            )
+        )
+    }
 }
 
 /// Represents the errors that can occur during client setup.
 ///
 /// Variants:
 /// * `EndpointCreationError`: An error occurred while creating the WebTransport client endpoint.
+/// * `UnsupportedCodecs`: None of the configured codecs is compiled into the client.
+/// * `CertificateLoadError`: The certificate authorities to verify the server certificate with could not be loaded.
+/// * `InvalidCertificateHash`: A pinned certificate hash is not a valid SHA-256 hash.
+/// * `InvalidUrl`: The URL of the server is not a valid WebTransport URL.
+/// * `NoServerUrl`: No server URL is configured to connect to.
 #[derive(Error, Debug, PartialEq, Clone)]
 pub enum ClientSetupError {
     /// Error occurred while creating the WebTransport client endpoint.
     #[error("failed to create WebTransport client endpoint")]
     EndpointCreationError,
+
+    /// None of the configured codecs is compiled into the client.
+    #[error("none of the codecs {codecs:?} is supported")]
+    UnsupportedCodecs { codecs: Vec<String> },
+
+    /// The certificate authorities to verify the server certificate with could not be loaded.
+    #[error("failed to load certificate authorities from {path}: {reason}")]
+    CertificateLoadError { path: String, reason: String },
+
+    /// A pinned certificate hash is not a valid SHA-256 hash.
+    #[error("invalid certificate hash {hash:?}, expected a base64 or hex encoded SHA-256 hash")]
+    InvalidCertificateHash { hash: String },
+
+    /// The URL of the server is not a valid WebTransport URL.
+    #[error("invalid server URL {url:?}: {reason}")]
+    InvalidUrl { url: String, reason: String },
+
+    /// No server URL is configured to connect to.
+    #[error("no server URL configured")]
+    NoServerUrl,
+}
+
+/// Represents the errors that can occur while discovering the endpoints of a service through DNS.
+///
+/// Variants:
+/// * `NoNameserver`: No nameserver is configured, neither explicitly nor in `/etc/resolv.conf`.
+/// * `InvalidName`: The name to look up is not a valid DNS name.
+/// * `IoError`: Sending the query or receiving the response has failed.
+/// * `TimedOut`: The nameserver has not answered in time.
+/// * `NameNotFound`: The name does not exist (`NXDOMAIN`).
+/// * `ServerFailure`: The nameserver has answered with an error code other than `NXDOMAIN`.
+/// * `LookupFailed`: The lookup has failed otherwise, e.g. the response could not be parsed.
+/// * `NoEndpoints`: The name exists but has no record of the looked up type.
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum DiscoveryError {
+    #[error("no nameserver configured")]
+    NoNameserver,
+
+    #[error("invalid DNS name {name:?}")]
+    InvalidName { name: String },
+
+    #[error("DNS I/O error ({kind:?}): {message}")]
+    IoError {
+        kind: std::io::ErrorKind,
+        message: String,
+    },
+
+    #[error("nameserver did not answer in time for {name}")]
+    TimedOut { name: String },
+
+    #[error("{name} does not exist")]
+    NameNotFound { name: String },
+
+    #[error("nameserver failed to resolve {name} with response code {code}")]
+    ServerFailure { name: String, code: u16 },
+
+    #[error("failed to look up {name}: {reason}")]
+    LookupFailed { name: String, reason: String },
+
+    #[error("no endpoints found for {name}")]
+    NoEndpoints { name: String },
+}
+
+impl From<std::io::Error> for DiscoveryError {
+    fn from(error: std::io::Error) -> Self {
+        DiscoveryError::IoError {
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
 }
 
 impl From<wtransport::error::ConnectionError> for ClientError {
@@ -35,3 +161,54 @@ impl From<wtransport::error::ConnectionError> for ClientError {
         ClientError::ConnectionError(ConnectionError::from(error))
     }
 }
+
+impl From<ErrorMessage> for ClientError {
+    fn from(error: ErrorMessage) -> Self {
+        ClientError::ErrorResponse {
+            code: error.code,
+            reason: error.reason,
+            request_id: error.request_id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::message::error::ErrorCode;
+
+    use super::*;
+
+    #[test]
+    fn test_should_detect_connection_loss() {
+        let lost = [
+            ClientError::ConnectionError(ConnectionError::ClosedByPeer {
+                code: 0,
+                reason: vec![],
+            }),
+            ClientError::ConnectionError(ConnectionError::TimedOut),
+            ClientError::ClientStreamError(StreamError::ReadError(
+                ReadStreamError::ConnectionClosed,
+            )),
+            ClientError::ClientStreamError(StreamError::WriteError(
+                WriteStreamError::DatagramError(DatagramError::ConnectionClosed),
+            )),
+        ];
+
+        let not_lost = [
+            ClientError::ConnectionError(ConnectionError::ClosedLocally),
+            ClientError::ClientStreamError(StreamError::ReadError(ReadStreamError::StreamStopped)),
+            ClientError::ErrorResponse {
+                code: ErrorCode::InternalError,
+                reason: "boom".to_string(),
+                request_id: None,
+            },
+        ];
+
+        for error in lost {
+            assert!(error.is_connection_lost(), "{:?}", error);
+        }
+        for error in not_lost {
+            assert!(!error.is_connection_lost(), "{:?}", error);
+        }
+    }
+}
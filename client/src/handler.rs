@@ -1,50 +1,277 @@
 use std::time::Duration;
 
 use common::{
-    error::{DatagramError, ReadStreamError, StreamError, WriteStreamError},
-    message::Message,
-    stream::{read_next_message, write_message},
+    codec::Codec,
+    error::{HandshakeError, ReadStreamError, StreamError, WriteStreamError},
+    message::{
+        error::ErrorCode,
+        hello::{ConnectionParameters, HelloAckMessage, HelloMessage, PROTOCOL_VERSION},
+        id::MessageId,
+        resume::ResumeMessage,
+        Message,
+    },
+    stream::{read_next_message, reset_oversized_stream, write_codec_preamble, write_message},
+    transport::{Connection, RecvStream, SendStream},
 };
-use tokio::time::sleep;
-use wtransport::Connection;
+use tokio::time::{sleep_until, timeout_at, Instant};
 
-use crate::error::ClientError;
+use crate::{
+    error::ClientError,
+    inbox::InboxEntry,
+    pipeline::{InFlightRequests, PipelineConfig},
+    stats::PingStatistics,
+};
+
+/// Performs the protocol handshake over a newly established connection.
+///
+/// # Arguments
+///
+/// * `connection` - The connection to perform the handshake over.
+/// * `hello` - The hello message announcing the client and its capabilities.
+/// * `codec` - The codec the handshake messages are serialized with.
+/// * `resume` - The IDs of the requests the client already has the responses of when resuming the session
+///   of a previous connection, asking the server to replay the other responses it keeps, `None` otherwise.
+///
+/// # Returns
+///
+/// This function returns the server's acknowledgement carrying the negotiated connection parameters along with
+/// the replayed messages, or an `Err(ClientError)` if the server has rejected the client or an error occurs.
+///
+/// The handshake is performed on a dedicated control stream, which has to be the first stream opened on the connection.
+/// The hello message is preceded by a codec preamble, so that the server can read it whatever codec the client prefers.
+/// Once acknowledged, the control stream is finished, right after the resume message if the session is resumed.
+pub async fn perform_handshake<C: Connection>(
+    connection: &C,
+    hello: &HelloMessage,
+    codec: &dyn Codec,
+    resume: Option<Vec<MessageId>>,
+) -> Result<(HelloAckMessage, Vec<Message>), ClientError> {
+    let (mut send_stream, mut recv_stream) = connection.open_bi().await?;
+
+    write_codec_preamble(&mut send_stream, codec)
+        .await
+        .map_err(StreamError::from)?;
+
+    write_message(&mut send_stream, &Message::Hello(hello.clone()), codec)
+        .await
+        .map_err(StreamError::from)?;
+
+    let max_message_size = hello.capabilities.max_message_size;
+
+    let reply = match read_next_message(&mut recv_stream, codec, max_message_size).await {
+        Ok(reply) => reply,
+        Err(error) => return Err(reset_streams_on_error(send_stream, recv_stream, error)),
+    };
+
+    let error = match reply {
+        Message::HelloAck(ack) if ack.protocol_version == PROTOCOL_VERSION => {
+            let Some(received) = resume else {
+                send_stream.finish().await.map_err(StreamError::from)?;
+
+                return Ok((ack, Vec::new()));
+            };
+
+            let resume = Message::Resume(ResumeMessage::new(hello.id, received));
+
+            write_message(&mut send_stream, &resume, codec)
+                .await
+                .map_err(StreamError::from)?;
+
+            send_stream.finish().await.map_err(StreamError::from)?;
+
+            let replayed = match read_next_message(&mut recv_stream, codec, max_message_size).await
+            {
+                Ok(Message::ResumeAck(resume_ack)) => resume_ack.replayed,
+                Ok(other) => {
+                    return Err(ClientError::HandshakeError(
+                        HandshakeError::UnexpectedMessage {
+                            message_type: other.message_type(),
+                        },
+                    ))
+                }
+                Err(error) => return Err(reset_streams_on_error(send_stream, recv_stream, error)),
+            };
+
+            let mut messages = Vec::with_capacity(replayed as usize);
+            for _ in 0..replayed {
+                match read_next_message(&mut recv_stream, codec, max_message_size).await {
+                    Ok(message) => messages.push(message),
+                    Err(error) => {
+                        return Err(reset_streams_on_error(send_stream, recv_stream, error))
+                    }
+                }
+            }
+
+            return Ok((ack, messages));
+        }
+        Message::HelloAck(ack) => HandshakeError::IncompatibleVersion {
+            local: PROTOCOL_VERSION,
+            remote: ack.protocol_version,
+        },
+        Message::HelloReject(reject) => HandshakeError::Rejected {
+            reason: reject.reason,
+        },
+        Message::Error(error) => return Err(error.into()),
+        other => HandshakeError::UnexpectedMessage {
+            message_type: other.message_type(),
+        },
+    };
+
+    Err(ClientError::HandshakeError(error))
+}
+
+/// Converts a read error into a `ClientError`, resetting the streams if the error has left them unusable.
+///
+/// A frame exceeding the maximum message size is not read, so the receiving stream is stopped and the
+/// sending one is reset, letting the server know that no further messages are going to be exchanged on them.
+///
+/// # Arguments
+///
+/// * `send_stream` - The stream the requests are sent on.
+/// * `recv_stream` - The stream the error has occurred on.
+/// * `error` - The error returned while reading from the stream.
+///
+/// # Returns
+///
+/// The `ClientError` to return.
+fn reset_streams_on_error<S: SendStream, R: RecvStream>(
+    send_stream: S,
+    recv_stream: R,
+    error: ReadStreamError,
+) -> ClientError {
+    if let ReadStreamError::FrameTooLarge { .. } = error {
+        reset_oversized_stream(recv_stream);
 
-/// Send messages bidirectionally over a connection.
+        send_stream.reset(ErrorCode::MessageTooLarge.code().into());
+    }
+
+    StreamError::from(error).into()
+}
+
+/// Send messages bidirectionally over a connection, see `send_lock_step`.
 ///
 /// # Arguments
 ///
 /// * `connection` - The connection over which the message is sent.
 /// * `message` - The message to be sent.
 /// * `count_option` - Optional argument to limit the number of times the message is sent. If `None`, the message is sent indefinitely.
-/// * `inbox` - A vector of `Message` objects that will be populated with the responses received from the server.
+/// * `inbox` - A vector of `InboxEntry` objects that will be populated with the responses received from the server and their round-trip times.
+/// * `stats` - The statistics accumulator the sent pings and their round-trip times are recorded into.
+/// * `parameters` - The connection parameters negotiated during the handshake.
 ///
 /// # Returns
 ///
-/// This function returns `Ok(())` if all messages were sent successfully, or an `Err(ClientError)` if an error occurs.
+/// This function returns `Ok(())` if all messages were sent successfully, or an `Err(ClientError)` if an error occurs
+/// or the server responds with an error message.
+pub async fn send_bidirectional<C: Connection>(
+    connection: &C,
+    message: &Message,
+    count_option: Option<u32>,
+    inbox: &mut Vec<InboxEntry>,
+    stats: &mut PingStatistics,
+    parameters: &ConnectionParameters,
+) -> Result<(), ClientError> {
+    let streams = connection.open_bi().await?;
+
+    send_lock_step(streams, message, count_option, inbox, stats, parameters).await
+}
+
+/// Sends messages unidirectionally over a connection, see `send_lock_step`.
 ///
-/// This function sends the message and waits for a response. This cycle is repeated until the sent message count has reached the optional `count_option` limit.
-pub async fn send_bidirectional(
-    connection: &Connection,
+/// # Arguments
+///
+/// * `connection` - The connection over which the message is sent.
+/// * `message` - The message to be sent.
+/// * `count_option` - Optional argument to limit the number of times the message is sent. If `None`, the message is sent indefinitely.
+/// * `inbox` - A vector of `InboxEntry` objects that will be populated with the responses received from the server and their round-trip times.
+/// * `stats` - The statistics accumulator the sent pings and their round-trip times are recorded into.
+/// * `parameters` - The connection parameters negotiated during the handshake.
+///
+/// # Returns
+///
+/// This function returns `Ok(())` if all messages were sent successfully, or an `Err(ClientError)` if an error occurs
+/// or the server responds with an error message.
+pub async fn send_unidirectional<C: Connection>(
+    connection: &C,
     message: &Message,
     count_option: Option<u32>,
-    inbox: &mut Vec<Message>,
+    inbox: &mut Vec<InboxEntry>,
+    stats: &mut PingStatistics,
+    parameters: &ConnectionParameters,
 ) -> Result<(), ClientError> {
-    let (mut send_stream, mut recv_stream) = connection.open_bi().await?;
+    let send_stream = connection.open_uni().await?;
+    let recv_stream = connection.accept_uni().await?;
 
+    send_lock_step(
+        (send_stream, recv_stream),
+        message,
+        count_option,
+        inbox,
+        stats,
+        parameters,
+    )
+    .await
+}
+
+/// Sends messages over a stream, waiting for the response to every message before sending the next one.
+///
+/// # Arguments
+///
+/// * `streams` - The stream to send the messages on and the stream to read the responses from,
+///   either the two halves of a bidirectional stream or a pair of unidirectional ones.
+/// * `message` - The message to be sent.
+/// * `count_option` - Optional argument to limit the number of times the message is sent. If `None`, the message is sent indefinitely.
+/// * `inbox` - A vector of `InboxEntry` objects that will be populated with the responses received from the server and their round-trip times.
+/// * `stats` - The statistics accumulator the sent pings and their round-trip times are recorded into.
+/// * `parameters` - The connection parameters negotiated during the handshake.
+///
+/// # Returns
+///
+/// This function returns `Ok(())` if all messages were sent successfully, or an `Err(ClientError)` if an error occurs
+/// or the server responds with an error message.
+///
+/// This function sends the message and waits for a response. This cycle is repeated until the sent message count has reached the optional `count_option` limit.
+/// Every sent copy of the message gets its own ID.
+pub async fn send_lock_step<S: SendStream, R: RecvStream>(
+    (mut send_stream, mut recv_stream): (S, R),
+    message: &Message,
+    count_option: Option<u32>,
+    inbox: &mut Vec<InboxEntry>,
+    stats: &mut PingStatistics,
+    parameters: &ConnectionParameters,
+) -> Result<(), ClientError> {
     let mut sent_count = 0;
     loop {
-        write_message(&mut send_stream, message)
+        write_message(&mut send_stream, &message.reissue(), parameters.codec)
             .await
             .map_err(StreamError::from)?;
 
-        let response = read_next_message(&mut recv_stream)
-            .await
-            .map_err(StreamError::from)?;
+        stats.record_sent();
 
-        println!("Received response data: {}", response.get_data());
+        let response = match read_next_message(
+            &mut recv_stream,
+            parameters.codec,
+            parameters.max_message_size,
+        )
+        .await
+        {
+            Ok(response) => response,
+            Err(error) => return Err(reset_streams_on_error(send_stream, recv_stream, error)),
+        };
 
-        inbox.push(response);
+        if let Message::Error(error) = response {
+            return Err(error.into());
+        }
+
+        let entry = InboxEntry::received(response);
+
+        if let Some(round_trip_time) = entry.round_trip_time {
+            stats.record_received(round_trip_time);
+        }
+
+        println!("Received response data: {}", entry);
+
+        inbox.push(entry);
 
         sent_count += 1;
 
@@ -58,48 +285,112 @@ pub async fn send_bidirectional(
     Ok(())
 }
 
-/// Sends messages unidirectionally over a connection.
+/// Sends messages over a stream without waiting for their responses, keeping up to `depth` of them in flight.
 ///
 /// # Arguments
 ///
-/// * `connection` - The connection over which the message is sent.
+/// * `streams` - The stream to send the messages on and the stream to read the responses from,
+///   either the two halves of a bidirectional stream or a pair of unidirectional ones.
 /// * `message` - The message to be sent.
 /// * `count_option` - Optional argument to limit the number of times the message is sent. If `None`, the message is sent indefinitely.
-/// * `inbox` - A vector of `Message` objects that will be populated with the responses received from the server.
+/// * `pipeline` - The maximum number of requests in flight and the time each of them is waited for.
+/// * `inbox` - A vector of `InboxEntry` objects that will be populated with the responses received from the server and their round-trip times.
+/// * `stats` - The statistics accumulator the sent pings and their round-trip times are recorded into.
+/// * `parameters` - The connection parameters negotiated during the handshake.
 ///
 /// # Returns
 ///
-/// This function returns `Ok(())` if all messages were sent successfully, or an `Err(ClientError)` if an error occurs.
+/// This function returns `Ok(())` once every sent message has been answered or has timed out, or an `Err(ClientError)`
+/// if an error occurs or the server responds with an error message.
 ///
-/// This function sends the message and waits for a response. This cycle is repeated until the sent message count has reached the optional `count_option` limit.
-pub async fn send_unidirectional(
-    connection: &Connection,
+/// The server may answer out of order, responses are matched with their request through the in-flight table.
+/// Requests left without response past their timeout are given up on, they are accounted as lost,
+/// and a response or an error arriving after that is discarded.
+pub async fn send_pipelined<S: SendStream, R: RecvStream>(
+    (mut send_stream, recv_stream): (S, R),
     message: &Message,
     count_option: Option<u32>,
-    inbox: &mut Vec<Message>,
+    pipeline: &PipelineConfig,
+    inbox: &mut Vec<InboxEntry>,
+    stats: &mut PingStatistics,
+    parameters: &ConnectionParameters,
 ) -> Result<(), ClientError> {
-    let mut send_stream = connection.open_uni().await?;
-    let mut recv_stream = connection.accept_uni().await?;
+    let read = |mut recv_stream: R| async move {
+        let response = read_next_message(
+            &mut recv_stream,
+            parameters.codec,
+            parameters.max_message_size,
+        )
+        .await;
+
+        (recv_stream, response)
+    };
 
+    // The pending read is kept while requests are sent and time out, so that no response is cut in half
+    let mut reading = Box::pin(read(recv_stream));
+    let mut in_flight = InFlightRequests::new(pipeline.request_timeout);
     let mut sent_count = 0;
+
     loop {
-        write_message(&mut send_stream, message)
-            .await
-            .map_err(StreamError::from)?;
+        while in_flight.len() < pipeline.depth.max(1)
+            && !matches!(count_option, Some(count) if sent_count >= count)
+        {
+            let request = message.reissue();
 
-        let response = read_next_message(&mut recv_stream)
-            .await
-            .map_err(StreamError::from)?;
+            write_message(&mut send_stream, &request, parameters.codec)
+                .await
+                .map_err(StreamError::from)?;
 
-        println!("Received response data: {}", response.get_data());
+            in_flight.insert(request.id());
+            stats.record_sent();
 
-        inbox.push(response);
+            sent_count += 1;
+        }
 
-        sent_count += 1;
+        // Nothing is in flight once all the messages have been sent
+        let Some(deadline) = in_flight.next_deadline() else {
+            break;
+        };
 
-        if let Some(count) = count_option {
-            if sent_count >= count {
-                break;
+        tokio::select! {
+            (recv_stream, response) = &mut reading => {
+                let response = match response {
+                    Ok(response) => response,
+                    Err(error) => return Err(reset_streams_on_error(send_stream, recv_stream, error)),
+                };
+
+                let answered = match &response {
+                    Message::Response(response) => in_flight.complete(&response.request_id),
+                    // An error naming no request concerns the whole stream
+                    Message::Error(error) => match &error.request_id {
+                        Some(request_id) => in_flight.complete(request_id),
+                        None => true,
+                    },
+                    _ => false,
+                };
+
+                match response {
+                    Message::Error(error) if answered => return Err(error.into()),
+                    response if answered => {
+                        let entry = InboxEntry::received(response);
+
+                        if let Some(round_trip_time) = entry.round_trip_time {
+                            stats.record_received(round_trip_time);
+                        }
+
+                        println!("Received response data: {}", entry);
+
+                        inbox.push(entry);
+                    }
+                    response => println!("Discarding unexpected or late message {}", response.id()),
+                }
+
+                reading = Box::pin(read(recv_stream));
+            }
+            _ = sleep_until(deadline) => {
+                for request_id in in_flight.expire(Instant::now()) {
+                    println!("Request {} timed out", request_id);
+                }
             }
         }
     }
@@ -114,48 +405,88 @@ pub async fn send_unidirectional(
 /// * `connection` - The connection over which the message is sent.
 /// * `message` - The message to be sent.
 /// * `count_option` - Optional argument to limit the number of times the message is sent. If `None`, the message is sent indefinitely.
-/// * `inbox` - A vector of `Message` objects that will be populated with the responses received from the server.
+/// * `reply_timeout` - The time the reply to a datagram is waited for, after which the message is accounted as lost.
+/// * `inbox` - A vector of `InboxEntry` objects that will be populated with the responses received from the server and their round-trip times.
+/// * `stats` - The statistics accumulator the sent pings and their round-trip times are recorded into.
+/// * `parameters` - The connection parameters negotiated during the handshake.
 ///
 /// # Returns
 ///
-/// This function returns `Ok(())` if all messages were sent successfully, or an `Err(ClientError)` if an error occurs.
+/// This function returns `Ok(())` once every message has been answered or has timed out, or an `Err(ClientError)`
+/// if an error occurs or the server responds with an error message.
 ///
 /// This function sends the message and waits for a response. This cycle is repeated until the sent message count has reached the optional `count_option` limit.
-
-pub async fn send_datagram(
-    connection: &Connection,
+/// Every sent copy of the message gets its own ID, which the reply is matched with: replies to earlier messages,
+/// delayed past their timeout or reordered, are discarded, as are undecodable datagrams and error replies which
+/// do not name the current message.
+pub async fn send_datagram<C: Connection>(
+    connection: &C,
     message: &Message,
     count_option: Option<u32>,
-    inbox: &mut Vec<Message>,
+    reply_timeout: Duration,
+    inbox: &mut Vec<InboxEntry>,
+    stats: &mut PingStatistics,
+    parameters: &ConnectionParameters,
 ) -> Result<(), ClientError> {
     let mut sent_count = 0;
     loop {
-        let datagram = message
-            .as_bytes()
+        let request = message.reissue();
+        let datagram = request
+            .encode(parameters.codec)
             .map_err(|e| StreamError::WriteError(WriteStreamError::from(e)))?;
 
         connection
             .send_datagram(&datagram)
-            .map_err(|e| StreamError::WriteError(WriteStreamError::from(DatagramError::from(e))))?;
+            .map_err(|e| StreamError::WriteError(WriteStreamError::from(e)))?;
+
+        stats.record_sent();
 
         sent_count += 1;
 
-        // Loop as we need to make sure all datagrams are received by the server
-        // and we got all the responses back.
+        let deadline = Instant::now() + reply_timeout;
+
+        // Loop until the reply to this very datagram is received or it is given up on,
+        // as either of them may be lost on the way
         loop {
-            if let Ok(response) = connection.receive_datagram().await {
-                let message = Message::from_bytes(&response)
-                    .map_err(|e| StreamError::ReadError(ReadStreamError::from(e)))?;
+            let received = match timeout_at(deadline, connection.receive_datagram()).await {
+                Ok(received) => received,
+                Err(_) => {
+                    println!("Request {} timed out", request.id());
+                    break;
+                }
+            };
 
-                println!("Received response data: {}", message.get_data());
+            let datagram =
+                received.map_err(|e| StreamError::ReadError(ReadStreamError::from(e)))?;
 
-                inbox.push(message);
+            // A datagram which cannot be decoded is skipped, as the server does
+            let response = match Message::decode(&datagram, parameters.codec) {
+                Ok(response) => response,
+                Err(e) => {
+                    println!("Discarding undecodable datagram: {}", e);
+                    continue;
+                }
+            };
 
-                break;
-            }
+            match response {
+                Message::Response(response) if response.request_id == request.id() => {
+                    let entry = InboxEntry::received(Message::Response(response));
+
+                    if let Some(round_trip_time) = entry.round_trip_time {
+                        stats.record_received(round_trip_time);
+                    }
 
-            // TODO: move this to a configuration variable as magic numbers are evil
-            sleep(Duration::from_millis(100)).await;
+                    println!("Received response data: {}", entry);
+
+                    inbox.push(entry);
+
+                    break;
+                }
+                Message::Error(error) if error.request_id == Some(request.id()) => {
+                    return Err(error.into());
+                }
+                other => println!("Discarding unexpected or late message {}", other.id()),
+            }
         }
 
         if let Some(count) = count_option {
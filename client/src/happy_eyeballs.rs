@@ -0,0 +1,243 @@
+use std::{future::Future, net::SocketAddr, time::Duration};
+
+use common::error::ConnectionError;
+use futures::{stream::FuturesUnordered, StreamExt};
+
+/// How long an attempt is given before the next address is tried alongside it, as recommended by RFC 8305.
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Orders addresses for happy eyeballs, alternating between address families.
+///
+/// The family of the first address, the one preferred by the resolver, goes first. The order of the
+/// addresses within each family is kept, so is the preference of the resolver.
+///
+/// # Arguments
+/// * `addresses` - The resolved addresses, in the order of preference of the resolver.
+///
+/// # Returns
+/// Returns the addresses in the order connection attempts are started in.
+pub fn interleave_families(addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let preferred_ipv6 = !matches!(addresses.first(), Some(address) if address.is_ipv4());
+    let (mut preferred, mut other): (Vec<_>, Vec<_>) = addresses
+        .into_iter()
+        .partition(|address| address.is_ipv6() == preferred_ipv6);
+
+    let mut interleaved = Vec::with_capacity(preferred.len() + other.len());
+    let mut preferred = preferred.drain(..);
+    let mut other = other.drain(..);
+
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => break,
+            (first, second) => interleaved.extend(first.into_iter().chain(second)),
+        }
+    }
+
+    interleaved
+}
+
+/// Races connection attempts to the given addresses, following happy eyeballs (RFC 8305).
+///
+/// Attempts are started in order, each one `attempt_delay` after the previous one, or as soon as the
+/// previous one fails. The first attempt to succeed wins and the pending ones are dropped.
+///
+/// # Arguments
+/// * `addresses` - The addresses to try, in order.
+/// * `attempt_delay` - How long an attempt is given before the next one is started alongside it.
+/// * `connect` - Attempts to connect to a single address.
+///
+/// # Returns
+/// * `Result` - The result of the first successful attempt. If all the attempts fail, the
+///   `ConnectionError::CertificateVerificationFailed` of any of them, as the certificate is not going to be
+///   accepted on another address either, or the error of the first attempt otherwise.
+pub async fn race<T, F, Fut>(
+    addresses: &[SocketAddr],
+    attempt_delay: Duration,
+    mut connect: F,
+) -> Result<T, ConnectionError>
+where
+    F: FnMut(SocketAddr) -> Fut,
+    Fut: Future<Output = Result<T, ConnectionError>>,
+{
+    let mut pending = addresses.iter().copied();
+    let mut attempts = FuturesUnordered::new();
+    let mut errors = Vec::new();
+
+    match pending.next() {
+        Some(address) => attempts.push(connect(address)),
+        None => {
+            return Err(ConnectionError::ConnectFailed {
+                reason: "no address to connect to".to_string(),
+            })
+        }
+    }
+
+    loop {
+        tokio::select! {
+            result = attempts.next() => match result {
+                Some(Ok(value)) => return Ok(value),
+                Some(Err(error)) => {
+                    errors.push(error);
+
+                    match pending.next() {
+                        Some(address) => attempts.push(connect(address)),
+                        None if attempts.is_empty() => break,
+                        None => {}
+                    }
+                }
+                None => break,
+            },
+            _ = tokio::time::sleep(attempt_delay), if pending.len() > 0 => {
+                if let Some(address) = pending.next() {
+                    attempts.push(connect(address));
+                }
+            }
+        }
+    }
+
+    Err(most_telling_error(errors))
+}
+
+/// Picks the error to report out of the errors of failed attempts to connect to the same server.
+///
+/// A rejected certificate is reported first, as it is not going to be accepted on another address either.
+/// Otherwise the error of the first attempt is reported, the one made to the preferred address.
+pub(crate) fn most_telling_error(mut errors: Vec<ConnectionError>) -> ConnectionError {
+    let certificate_error = errors
+        .iter()
+        .position(|error| matches!(error, ConnectionError::CertificateVerificationFailed { .. }));
+
+    if errors.is_empty() {
+        return ConnectionError::ConnectFailed {
+            reason: "no address to connect to".to_string(),
+        };
+    }
+
+    errors.swap_remove(certificate_error.unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use tokio::time::Instant;
+
+    use super::*;
+
+    fn v4(last: u8) -> SocketAddr {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, last)), 4433)
+    }
+
+    fn v6(last: u16) -> SocketAddr {
+        SocketAddr::new(
+            IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, last)),
+            4433,
+        )
+    }
+
+    /// Connects after the given delay, failing if `fails` is set.
+    async fn attempt(
+        address: SocketAddr,
+        delay: Duration,
+        fails: bool,
+    ) -> Result<SocketAddr, ConnectionError> {
+        tokio::time::sleep(delay).await;
+
+        if fails {
+            Err(ConnectionError::ConnectFailed {
+                reason: address.to_string(),
+            })
+        } else {
+            Ok(address)
+        }
+    }
+
+    #[test]
+    fn test_should_interleave_families_starting_with_preferred_one() {
+        assert_eq!(
+            interleave_families(vec![v6(1), v6(2), v6(3), v4(1), v4(2)]),
+            vec![v6(1), v4(1), v6(2), v4(2), v6(3)]
+        );
+        assert_eq!(
+            interleave_families(vec![v4(1), v4(2), v6(1)]),
+            vec![v4(1), v6(1), v4(2)]
+        );
+        assert_eq!(interleave_families(vec![v4(1), v4(2)]), vec![v4(1), v4(2)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_start_next_attempt_after_delay() {
+        let started_at = Instant::now();
+
+        // The first address does not answer, the second one answers within 100 ms
+        let result = race(&[v6(1), v4(1)], CONNECTION_ATTEMPT_DELAY, |address| {
+            let delay = if address == v6(1) {
+                Duration::from_secs(30)
+            } else {
+                Duration::from_millis(100)
+            };
+            attempt(address, delay, false)
+        })
+        .await;
+
+        assert_eq!(result, Ok(v4(1)));
+        assert_eq!(started_at.elapsed(), Duration::from_millis(350));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_start_next_attempt_right_after_failure() {
+        let started_at = Instant::now();
+
+        let result = race(&[v6(1), v4(1)], CONNECTION_ATTEMPT_DELAY, |address| {
+            attempt(address, Duration::from_millis(10), address == v6(1))
+        })
+        .await;
+
+        assert_eq!(result, Ok(v4(1)));
+        assert_eq!(started_at.elapsed(), Duration::from_millis(20));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_prefer_earlier_address_answering_first() {
+        let result = race(&[v6(1), v4(1)], CONNECTION_ATTEMPT_DELAY, |address| {
+            attempt(address, Duration::from_millis(100), false)
+        })
+        .await;
+
+        assert_eq!(result, Ok(v6(1)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_report_certificate_error_when_all_attempts_fail() {
+        let rejected = ConnectionError::CertificateVerificationFailed {
+            reason: "invalid peer certificate: UnknownIssuer".to_string(),
+        };
+
+        let result: Result<(), _> = race(&[v6(1), v4(1), v6(2)], CONNECTION_ATTEMPT_DELAY, {
+            let rejected = rejected.clone();
+            move |address| {
+                let result = if address == v4(1) {
+                    Err(rejected.clone())
+                } else {
+                    Err(ConnectionError::TimedOut)
+                };
+                std::future::ready(result)
+            }
+        })
+        .await;
+
+        assert_eq!(result, Err(rejected));
+
+        let result = race(&[v6(1), v4(1)], CONNECTION_ATTEMPT_DELAY, |address| {
+            attempt(address, Duration::ZERO, true)
+        })
+        .await;
+
+        assert_eq!(
+            result,
+            Err(ConnectionError::ConnectFailed {
+                reason: v6(1).to_string()
+            })
+        );
+    }
+}
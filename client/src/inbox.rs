@@ -0,0 +1,139 @@
+use std::{collections::HashSet, time::Duration};
+
+use common::message::{id::MessageId, Message};
+
+/// Represents a message received by the `PingClient` together with its measured latency.
+///
+/// # Fields
+///
+/// * `message` - The received message.
+/// * `round_trip_time` - The time elapsed between sending the request and receiving this response.
+///   Only set for response messages.
+#[derive(Debug, PartialEq, Clone)]
+pub struct InboxEntry {
+    pub message: Message,
+    pub round_trip_time: Option<Duration>,
+}
+
+impl InboxEntry {
+    /// Creates a new `InboxEntry` for a message that has just been received.
+    ///
+    /// The round-trip time is computed from the request timestamp echoed back by the server,
+    /// so this function has to be called as soon as the message is read.
+    ///
+    /// # Arguments
+    /// * `message` - The received message.
+    ///
+    /// # Returns
+    /// Returns an `InboxEntry` instance.
+    pub fn received(message: Message) -> Self {
+        let round_trip_time = match &message {
+            Message::Response(response) => Some(response.round_trip_time()),
+            _ => None,
+        };
+
+        Self {
+            message,
+            round_trip_time,
+        }
+    }
+}
+
+/// Lists the requests answered by the responses of the inbox.
+///
+/// # Arguments
+/// * `inbox` - The inbox of the client.
+///
+/// # Returns
+/// Returns the IDs of the answered requests, in the order their responses have been received.
+pub fn answered_requests(inbox: &[InboxEntry]) -> Vec<MessageId> {
+    inbox
+        .iter()
+        .filter_map(|entry| match &entry.message {
+            Message::Response(response) => Some(response.request_id),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Adds the responses replayed by the server on session resumption to the inbox.
+///
+/// The server replays the recent responses the client has not told it has, which it may have received nevertheless,
+/// e.g. beyond the IDs a resume message can carry.
+/// Responses are matched by the ID of the request they answer, and only the ones not in the inbox yet are added.
+/// Their round-trip time is not set, as it would include the time spent reconnecting.
+///
+/// # Arguments
+/// * `inbox` - The inbox of the client.
+/// * `replayed` - The messages replayed by the server.
+///
+/// # Returns
+/// Returns the number of responses added to the inbox.
+pub fn merge_replayed(inbox: &mut Vec<InboxEntry>, replayed: Vec<Message>) -> usize {
+    let mut answered: HashSet<_> = answered_requests(inbox).into_iter().collect();
+
+    let count = inbox.len();
+
+    inbox.extend(
+        replayed
+            .into_iter()
+            .filter(|message| match message {
+                Message::Response(response) => answered.insert(response.request_id),
+                _ => false,
+            })
+            .map(|message| InboxEntry {
+                message,
+                round_trip_time: None,
+            }),
+    );
+
+    inbox.len() - count
+}
+
+/// Formats the received message data along with its round-trip time.
+impl std::fmt::Display for InboxEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(self.message.get_data()))?;
+
+        if let Some(round_trip_time) = self.round_trip_time {
+            write!(
+                f,
+                " id={} time={:.3} ms",
+                self.message.id(),
+                round_trip_time.as_secs_f64() * 1000.0
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pong() -> Message {
+        match Message::new_request("Ping!") {
+            Message::Request(request) => Message::new_response(&request, 0, "Pong!"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_should_skip_replayed_responses_already_received() {
+        let received = pong();
+        let missed = pong();
+        let mut inbox = vec![InboxEntry::received(received.clone())];
+
+        // The same response may be replayed again if the connection is lost during resumption
+        let added = merge_replayed(
+            &mut inbox,
+            vec![received.reissue(), missed.clone(), missed.reissue()],
+        );
+
+        assert_eq!(added, 1);
+        assert_eq!(inbox.len(), 2);
+        assert_eq!(inbox[1].message, missed);
+        assert_eq!(inbox[1].round_trip_time, None);
+    }
+}
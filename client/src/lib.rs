@@ -1,3 +1,14 @@
+pub mod balancer;
 pub mod client;
+pub mod discovery;
 pub mod error;
 pub mod handler;
+pub mod happy_eyeballs;
+pub mod inbox;
+pub mod pinning;
+pub mod pipeline;
+pub mod retry;
+pub mod rpc;
+pub mod stats;
+pub mod target;
+pub mod tls;
@@ -0,0 +1,325 @@
+use std::{sync::Arc, time::SystemTime};
+
+use base64::{engine::general_purpose::STANDARD as Base64Engine, Engine};
+use common::utils::gen_certs::MAX_PINNABLE_VALIDITY;
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    Certificate, CertificateError, ServerName,
+};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use time::{Duration, OffsetDateTime};
+use x509_parser::{
+    certificate::X509Certificate,
+    oid_registry::{OID_EC_P256, OID_KEY_TYPE_EC_PUBLIC_KEY},
+    prelude::FromDer,
+    x509::X509Version,
+};
+
+use crate::error::ClientSetupError;
+
+/// Represents the reasons a pinned certificate is rejected for, besides expiry.
+///
+/// Variants:
+/// * `NotPinned`: The hash of the certificate is not one of the pinned hashes.
+/// * `Malformed`: The certificate is not a well-formed X.509v3 certificate.
+/// * `UnsupportedKey`: The certificate key is not an ECDSA P-256 key.
+/// * `ValidityTooLong`: The validity period of the certificate exceeds `MAX_PINNABLE_VALIDITY`.
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum PinnedCertificateError {
+    #[error("the certificate hash is not pinned")]
+    NotPinned,
+
+    #[error("the certificate is not a well-formed X.509v3 certificate")]
+    Malformed,
+
+    #[error("the certificate key is not an ECDSA P-256 key")]
+    UnsupportedKey,
+
+    #[error(
+        "the certificate is valid for {validity}, more than the pinnable {MAX_PINNABLE_VALIDITY}"
+    )]
+    ValidityTooLong { validity: Duration },
+}
+
+/// Verifies server certificates against pinned SHA-256 hashes of their DER encoding.
+///
+/// Follows the semantics of the WebTransport `serverCertificateHashes` option of browsers:
+/// the certificate chain and the server name are not verified, but the certificate has to be an X.509v3
+/// certificate with an ECDSA P-256 key, valid at the time of the connection for at most `MAX_PINNABLE_VALIDITY`.
+/// Short-lived self-signed certificates can be trusted this way without installing a certificate authority.
+pub struct PinnedHashVerifier {
+    hashes: Vec<[u8; 32]>,
+}
+
+impl PinnedHashVerifier {
+    /// Creates a new `PinnedHashVerifier` accepting the certificates with the given hashes.
+    ///
+    /// # Arguments
+    /// * `hashes` - The SHA-256 hashes of the accepted certificates.
+    ///
+    /// # Returns
+    /// Returns a `PinnedHashVerifier` instance.
+    pub fn new(hashes: Vec<[u8; 32]>) -> Self {
+        Self { hashes }
+    }
+}
+
+impl ServerCertVerifier for PinnedHashVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let hash: [u8; 32] = Sha256::digest(&end_entity.0).into();
+        if !self.hashes.contains(&hash) {
+            return Err(rejected(PinnedCertificateError::NotPinned));
+        }
+
+        let certificate = match X509Certificate::from_der(&end_entity.0) {
+            Ok((_, certificate)) if certificate.version() == X509Version::V3 => certificate,
+            _ => return Err(rejected(PinnedCertificateError::Malformed)),
+        };
+
+        if !has_p256_key(&certificate) {
+            return Err(rejected(PinnedCertificateError::UnsupportedKey));
+        }
+
+        let not_before = certificate.validity().not_before.to_datetime();
+        let not_after = certificate.validity().not_after.to_datetime();
+
+        let validity = not_after - not_before;
+        if validity > MAX_PINNABLE_VALIDITY {
+            return Err(rejected(PinnedCertificateError::ValidityTooLong {
+                validity,
+            }));
+        }
+
+        let now = OffsetDateTime::from(now);
+        if now < not_before {
+            return Err(rustls::Error::InvalidCertificate(
+                CertificateError::NotValidYet,
+            ));
+        }
+        if now > not_after {
+            return Err(rustls::Error::InvalidCertificate(CertificateError::Expired));
+        }
+
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Parses a pinned certificate hash.
+///
+/// The hash can be given in base64, as printed by `gen_certs`, or in hex, optionally separated by colons
+/// as printed by `openssl x509 -fingerprint -sha256`.
+///
+/// # Arguments
+/// * `hash` - The SHA-256 hash of a certificate.
+///
+/// # Returns
+/// * `Result` - The hash, or a `ClientSetupError::InvalidCertificateHash` if it is not a valid SHA-256 hash.
+pub fn parse_certificate_hash(hash: &str) -> Result<[u8; 32], ClientSetupError> {
+    let invalid = || ClientSetupError::InvalidCertificateHash {
+        hash: hash.to_string(),
+    };
+
+    let hex = hash.replace(':', "");
+    let bytes = if hex.len() == 64 && hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        (0..hex.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&hex[index..index + 2], 16))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?
+    } else {
+        Base64Engine.decode(hash).map_err(|_| invalid())?
+    };
+
+    bytes.try_into().map_err(|_| invalid())
+}
+
+/// Wraps the reason of a rejected certificate into a TLS error.
+fn rejected(error: PinnedCertificateError) -> rustls::Error {
+    rustls::Error::InvalidCertificate(CertificateError::Other(Arc::new(error)))
+}
+
+/// Tells whether the public key of a certificate is an elliptic curve key on the P-256 curve.
+fn has_p256_key(certificate: &X509Certificate) -> bool {
+    let algorithm = &certificate.public_key().algorithm;
+    let curve = algorithm
+        .parameters
+        .as_ref()
+        .and_then(|parameters| parameters.as_oid().ok());
+
+    algorithm.algorithm == OID_KEY_TYPE_EC_PUBLIC_KEY && curve == Some(OID_EC_P256)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use common::utils::gen_certs::gen_certs;
+
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        let mut path = env::temp_dir();
+        path.push(format!("{}-{}", name, std::process::id()));
+
+        path.into_os_string()
+            .into_string()
+            .expect("failed to construct temporary file path")
+    }
+
+    /// Generates a certificate valid for the given number of days, returning it along with its hash.
+    fn generate_certificate(name: &str, validity_days: i64) -> (Certificate, [u8; 32]) {
+        let cert_path = temp_path(&format!("{}-cert.pem", name));
+        let key_path = temp_path(&format!("{}-key.pem", name));
+
+        let fingerprint =
+            gen_certs(cert_path.clone(), key_path, Duration::days(validity_days)).unwrap();
+        let certificate = pem::parse(fs::read(cert_path).unwrap()).unwrap();
+
+        (
+            Certificate(certificate.contents),
+            parse_certificate_hash(&fingerprint).unwrap(),
+        )
+    }
+
+    fn verify(
+        verifier: &PinnedHashVerifier,
+        certificate: &Certificate,
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        verifier.verify_server_cert(
+            certificate,
+            &[],
+            &ServerName::try_from("example.com").unwrap(),
+            &mut std::iter::empty(),
+            &[],
+            now,
+        )
+    }
+
+    fn rejected_for(result: Result<ServerCertVerified, rustls::Error>) -> String {
+        match result {
+            Err(rustls::Error::InvalidCertificate(error)) => format!("{:?}", error),
+            Err(other) => panic!("Expected an invalid certificate, got {:?}", other),
+            Ok(_) => panic!("Expected the certificate to be rejected"),
+        }
+    }
+
+    #[test]
+    fn test_should_accept_pinned_certificate_regardless_of_name() {
+        let (certificate, hash) = generate_certificate("pinned", 14);
+        let (_, other_hash) = generate_certificate("other", 14);
+
+        let verifier = PinnedHashVerifier::new(vec![other_hash, hash]);
+
+        assert!(verify(&verifier, &certificate, SystemTime::now()).is_ok());
+    }
+
+    #[test]
+    fn test_should_reject_certificate_not_pinned() {
+        let (certificate, _) = generate_certificate("unpinned", 14);
+        let (_, other_hash) = generate_certificate("unpinned-other", 14);
+
+        let verifier = PinnedHashVerifier::new(vec![other_hash]);
+
+        assert_eq!(
+            rejected_for(verify(&verifier, &certificate, SystemTime::now())),
+            format!("Other({:?})", PinnedCertificateError::NotPinned)
+        );
+    }
+
+    #[test]
+    fn test_should_reject_pinned_certificate_valid_for_too_long() {
+        let (certificate, hash) = generate_certificate("long-lived", 30);
+
+        let verifier = PinnedHashVerifier::new(vec![hash]);
+
+        assert!(
+            rejected_for(verify(&verifier, &certificate, SystemTime::now()))
+                .contains("ValidityTooLong")
+        );
+    }
+
+    #[test]
+    fn test_should_reject_expired_pinned_certificate() {
+        let (certificate, hash) = generate_certificate("expired", 1);
+        let later = SystemTime::now() + std::time::Duration::from_secs(2 * 24 * 60 * 60);
+
+        let verifier = PinnedHashVerifier::new(vec![hash]);
+
+        assert_eq!(
+            rejected_for(verify(&verifier, &certificate, later)),
+            format!("{:?}", CertificateError::Expired)
+        );
+    }
+
+    #[test]
+    fn test_should_reject_pinned_certificate_without_p256_key() {
+        let mut params = rcgen::CertificateParams::new(vec!["localhost".to_string()]);
+        params.alg = &rcgen::PKCS_ECDSA_P384_SHA384;
+        params.not_after = OffsetDateTime::now_utc() + Duration::days(7);
+        let certificate = Certificate(
+            rcgen::Certificate::from_params(params)
+                .unwrap()
+                .serialize_der()
+                .unwrap(),
+        );
+        let hash = Sha256::digest(&certificate.0).into();
+
+        let verifier = PinnedHashVerifier::new(vec![hash]);
+
+        assert_eq!(
+            rejected_for(verify(&verifier, &certificate, SystemTime::now())),
+            format!("Other({:?})", PinnedCertificateError::UnsupportedKey)
+        );
+    }
+
+    #[test]
+    fn test_should_reject_malformed_pinned_certificate() {
+        let (certificate, _) = generate_certificate("truncated", 7);
+        let truncated = Certificate(certificate.0[..certificate.0.len() / 2].to_vec());
+        let hash = Sha256::digest(&truncated.0).into();
+
+        let verifier = PinnedHashVerifier::new(vec![hash]);
+
+        assert_eq!(
+            rejected_for(verify(&verifier, &truncated, SystemTime::now())),
+            format!("Other({:?})", PinnedCertificateError::Malformed)
+        );
+    }
+
+    #[test]
+    fn test_should_parse_base64_and_hex_hashes() {
+        let hash: [u8; 32] = core::array::from_fn(|index| index as u8 * 7);
+        let hex = hash
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<_>>();
+
+        assert_eq!(parse_certificate_hash(&Base64Engine.encode(hash)), Ok(hash));
+        assert_eq!(parse_certificate_hash(&hex.concat()), Ok(hash));
+        assert_eq!(parse_certificate_hash(&hex.join(":")), Ok(hash));
+
+        for invalid in [
+            "",
+            "not a hash",
+            &Base64Engine.encode([0; 20]),
+            &hex[1..].concat(),
+        ] {
+            assert_eq!(
+                parse_certificate_hash(invalid),
+                Err(ClientSetupError::InvalidCertificateHash {
+                    hash: invalid.to_string()
+                })
+            );
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::{collections::HashMap, time::Duration};
+
+use common::message::id::MessageId;
+use tokio::time::Instant;
+
+/// Configures the pipelined mode, which keeps several requests in flight on a single stream.
+///
+/// # Fields
+///
+/// * `depth` - The maximum number of requests in flight, further requests are sent as responses arrive.
+/// * `request_timeout` - The time a request is waited for before being given up on and accounted as lost.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PipelineConfig {
+    pub depth: usize,
+    pub request_timeout: Duration,
+}
+
+/// The requests sent on a pipelined stream which are still waiting for their response.
+///
+/// Responses may arrive in any order, they are matched with their request by the `request_id` they carry.
+/// Every request has its own deadline, after which it is given up on and its slot is freed for another one.
+///
+/// # Fields
+///
+/// * `requests` - The deadline of every request in flight, by request ID.
+/// * `timeout` - The time a request is waited for before being given up on.
+#[derive(Debug, Clone)]
+pub struct InFlightRequests {
+    requests: HashMap<MessageId, Instant>,
+    timeout: Duration,
+}
+
+impl InFlightRequests {
+    /// Creates an empty in-flight table.
+    ///
+    /// # Arguments
+    /// * `timeout` - The time a request is waited for before being given up on.
+    ///
+    /// # Returns
+    /// Returns an `InFlightRequests` instance.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            requests: HashMap::new(),
+            timeout,
+        }
+    }
+
+    /// Records that a request has just been sent.
+    ///
+    /// # Arguments
+    /// * `request_id` - The ID of the sent request.
+    pub fn insert(&mut self, request_id: MessageId) {
+        self.requests
+            .insert(request_id, Instant::now() + self.timeout);
+    }
+
+    /// Records that the response to a request has been received.
+    ///
+    /// # Arguments
+    /// * `request_id` - The ID of the request the response answers.
+    ///
+    /// # Returns
+    /// Returns `true` if the request was in flight, `false` if it is unknown or has already timed out.
+    pub fn complete(&mut self, request_id: &MessageId) -> bool {
+        self.requests.remove(request_id).is_some()
+    }
+
+    /// Gives up on the requests whose deadline has passed.
+    ///
+    /// # Arguments
+    /// * `now` - The current instant.
+    ///
+    /// # Returns
+    /// Returns the IDs of the requests which have timed out.
+    pub fn expire(&mut self, now: Instant) -> Vec<MessageId> {
+        let expired: Vec<MessageId> = self
+            .requests
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(request_id, _)| *request_id)
+            .collect();
+
+        for request_id in &expired {
+            self.requests.remove(request_id);
+        }
+
+        expired
+    }
+
+    /// Returns the earliest deadline of the requests in flight, if any.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.requests.values().min().copied()
+    }
+
+    /// Returns the number of requests in flight.
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    /// Returns `true` if no request is in flight.
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_complete_requests_out_of_order_and_expire_the_rest() {
+        let mut in_flight = InFlightRequests::new(Duration::from_secs(1));
+        let first = MessageId::generate();
+        let second = MessageId::generate();
+
+        in_flight.insert(first);
+        tokio::time::advance(Duration::from_millis(500)).await;
+        in_flight.insert(second);
+        let third = MessageId::generate();
+        in_flight.insert(third);
+
+        assert!(in_flight.complete(&second));
+        assert!(!in_flight.complete(&second));
+        assert_eq!(
+            in_flight.next_deadline(),
+            Some(Instant::now() + Duration::from_millis(500))
+        );
+
+        tokio::time::advance(Duration::from_millis(500)).await;
+
+        assert_eq!(in_flight.expire(Instant::now()), vec![first]);
+        assert!(!in_flight.complete(&first));
+        assert_eq!(in_flight.len(), 1);
+        assert!(in_flight.complete(&third));
+        assert!(in_flight.is_empty());
+    }
+}
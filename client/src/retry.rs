@@ -0,0 +1,371 @@
+use std::{future::Future, time::Duration};
+
+use common::error::ConnectionError;
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+use tokio::time::Instant;
+
+/// Decides how long the `PingClient` waits before retrying a failed connection attempt.
+///
+/// Policies only compute delays, how many retries are made and for how long is limited
+/// by `max_retries` and `max_retry_elapsed` of the `PingClientConfig`.
+pub trait RetryPolicy: Send + Sync {
+    /// Computes the delay before the given retry.
+    ///
+    /// # Arguments
+    /// * `retry` - The number of the retry, starting with 1.
+    /// * `previous_delay` - The delay returned for the previous retry, zero before the first one.
+    /// * `rng` - The source of randomness for jittered policies.
+    ///
+    /// # Returns
+    /// Returns the `Duration` to wait before the retry.
+    fn delay(&self, retry: u32, previous_delay: Duration, rng: &mut dyn RngCore) -> Duration;
+}
+
+/// Waits the same amount of time before every retry.
+///
+/// # Fields
+/// * `delay` - The delay before every retry.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FixedDelay {
+    pub delay: Duration,
+}
+
+impl FixedDelay {
+    /// Creates a new `FixedDelay` policy.
+    ///
+    /// # Arguments
+    /// * `delay` - The delay before every retry.
+    ///
+    /// # Returns
+    /// Returns a `FixedDelay` instance.
+    pub fn new(delay: Duration) -> Self {
+        Self { delay }
+    }
+}
+
+impl RetryPolicy for FixedDelay {
+    fn delay(&self, _retry: u32, _previous_delay: Duration, _rng: &mut dyn RngCore) -> Duration {
+        self.delay
+    }
+}
+
+/// Doubles the delay with every retry, starting with `base`, up to `max_delay`.
+///
+/// # Fields
+/// * `base` - The delay before the first retry.
+/// * `max_delay` - The upper bound of the delay.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ExponentialBackoff {
+    pub base: Duration,
+    pub max_delay: Duration,
+}
+
+impl ExponentialBackoff {
+    /// Creates a new `ExponentialBackoff` policy.
+    ///
+    /// # Arguments
+    /// * `base` - The delay before the first retry.
+    /// * `max_delay` - The upper bound of the delay.
+    ///
+    /// # Returns
+    /// Returns an `ExponentialBackoff` instance.
+    pub fn new(base: Duration, max_delay: Duration) -> Self {
+        Self { base, max_delay }
+    }
+
+    /// Computes the capped exponential delay before the given retry.
+    fn capped_delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+
+        self.base.saturating_mul(factor).min(self.max_delay)
+    }
+}
+
+impl RetryPolicy for ExponentialBackoff {
+    fn delay(&self, retry: u32, _previous_delay: Duration, _rng: &mut dyn RngCore) -> Duration {
+        self.capped_delay(retry)
+    }
+}
+
+/// Waits a random delay between zero and the delay of `ExponentialBackoff`.
+///
+/// Spreads the retries of many clients failing at the same time, so they do not hit the server all at once.
+///
+/// # Fields
+/// * `base` - The upper bound of the delay before the first retry.
+/// * `max_delay` - The upper bound of the delay.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FullJitterBackoff {
+    pub base: Duration,
+    pub max_delay: Duration,
+}
+
+impl FullJitterBackoff {
+    /// Creates a new `FullJitterBackoff` policy.
+    ///
+    /// # Arguments
+    /// * `base` - The upper bound of the delay before the first retry.
+    /// * `max_delay` - The upper bound of the delay.
+    ///
+    /// # Returns
+    /// Returns a `FullJitterBackoff` instance.
+    pub fn new(base: Duration, max_delay: Duration) -> Self {
+        Self { base, max_delay }
+    }
+}
+
+impl RetryPolicy for FullJitterBackoff {
+    fn delay(&self, retry: u32, _previous_delay: Duration, rng: &mut dyn RngCore) -> Duration {
+        let upper_bound = ExponentialBackoff::new(self.base, self.max_delay).capped_delay(retry);
+
+        rng.gen_range(Duration::ZERO..=upper_bound)
+    }
+}
+
+/// Waits a random delay between `base` and three times the previous delay, up to `max_delay`.
+///
+/// # Fields
+/// * `base` - The lower bound of every delay.
+/// * `max_delay` - The upper bound of the delay.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DecorrelatedJitterBackoff {
+    pub base: Duration,
+    pub max_delay: Duration,
+}
+
+impl DecorrelatedJitterBackoff {
+    /// Creates a new `DecorrelatedJitterBackoff` policy.
+    ///
+    /// # Arguments
+    /// * `base` - The lower bound of every delay.
+    /// * `max_delay` - The upper bound of the delay.
+    ///
+    /// # Returns
+    /// Returns a `DecorrelatedJitterBackoff` instance.
+    pub fn new(base: Duration, max_delay: Duration) -> Self {
+        Self { base, max_delay }
+    }
+}
+
+impl RetryPolicy for DecorrelatedJitterBackoff {
+    fn delay(&self, _retry: u32, previous_delay: Duration, rng: &mut dyn RngCore) -> Duration {
+        let upper_bound = previous_delay.max(self.base).saturating_mul(3);
+
+        rng.gen_range(self.base..=upper_bound).min(self.max_delay)
+    }
+}
+
+/// Runs `attempt` until it succeeds, retrying failures after the delays of the retry policy.
+///
+/// A rejected server certificate is not going to be accepted on the next attempt, so it is returned right away.
+///
+/// # Arguments
+/// * `attempt` - Makes a single attempt, e.g. connects to the server.
+/// * `policy` - The `RetryPolicy` computing the delays between attempts.
+/// * `max_retries` - Maximum number of retries after the first attempt.
+/// * `max_elapsed` - Maximum time spent retrying, no retry is made if it would start later. `None` for no limit.
+///
+/// # Returns
+/// * `Result` - The result of the first successful attempt, a `ConnectionError::CertificateVerificationFailed`,
+///   or a `ConnectionError::RetriesExhausted` carrying the errors of all the failed attempts.
+pub async fn retry<T, F, Fut>(
+    mut attempt: F,
+    policy: &dyn RetryPolicy,
+    max_retries: u16,
+    max_elapsed: Option<Duration>,
+) -> Result<T, ConnectionError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, ConnectionError>>,
+{
+    let started_at = Instant::now();
+    let mut rng = StdRng::from_entropy();
+    let mut attempts = Vec::new();
+    let mut delay = Duration::ZERO;
+
+    loop {
+        let error = match attempt().await {
+            Ok(value) => return Ok(value),
+            Err(error @ ConnectionError::CertificateVerificationFailed { .. }) => {
+                return Err(error)
+            }
+            Err(error) => error,
+        };
+
+        let retry = attempts.len() as u32 + 1;
+        attempts.push(error);
+
+        if retry > max_retries.into() {
+            return Err(ConnectionError::RetriesExhausted { attempts });
+        }
+
+        delay = policy.delay(retry, delay, &mut rng);
+
+        if let Some(max_elapsed) = max_elapsed {
+            if started_at.elapsed() + delay > max_elapsed {
+                return Err(ConnectionError::RetriesExhausted { attempts });
+            }
+        }
+
+        println!(
+            "connection failed ({}), retrying in {:?}...",
+            attempts[attempts.len() - 1],
+            delay
+        );
+
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: Duration = Duration::from_millis(100);
+    const MAX_DELAY: Duration = Duration::from_secs(1);
+
+    fn delays(policy: &dyn RetryPolicy, retries: u32) -> Vec<Duration> {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut delay = Duration::ZERO;
+
+        (1..=retries)
+            .map(|retry| {
+                delay = policy.delay(retry, delay, &mut rng);
+                delay
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_should_wait_fixed_delay() {
+        let delays = delays(&FixedDelay::new(BASE), 3);
+
+        assert_eq!(delays, vec![BASE; 3]);
+    }
+
+    #[test]
+    fn test_should_double_delay_up_to_max() {
+        let delays = delays(&ExponentialBackoff::new(BASE, MAX_DELAY), 6);
+
+        assert_eq!(
+            delays,
+            [100, 200, 400, 800, 1000, 1000].map(Duration::from_millis)
+        );
+    }
+
+    #[test]
+    fn test_should_jitter_below_exponential_delay() {
+        let delays = delays(&FullJitterBackoff::new(BASE, MAX_DELAY), 40);
+
+        for (retry, delay) in (1..).zip(&delays) {
+            let upper_bound = ExponentialBackoff::new(BASE, MAX_DELAY).capped_delay(retry);
+            assert!(*delay <= upper_bound, "{:?} > {:?}", delay, upper_bound);
+        }
+        assert!(delays.windows(2).any(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn test_should_decorrelate_jitter_within_bounds() {
+        let delays = delays(&DecorrelatedJitterBackoff::new(BASE, MAX_DELAY), 40);
+
+        let mut previous_delay = Duration::ZERO;
+        for delay in delays {
+            assert!(delay >= BASE && delay <= MAX_DELAY, "{:?}", delay);
+            assert!(delay <= previous_delay.max(BASE) * 3, "{:?}", delay);
+            previous_delay = delay;
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_keep_error_of_every_attempt() {
+        let mut attempt = 0;
+
+        let result: Result<(), _> = retry(
+            || {
+                attempt += 1;
+                std::future::ready(Err(ConnectionError::ConnectFailed {
+                    reason: format!("attempt {}", attempt),
+                }))
+            },
+            &FixedDelay::new(BASE),
+            2,
+            None,
+        )
+        .await;
+
+        assert_eq!(
+            result,
+            Err(ConnectionError::RetriesExhausted {
+                attempts: (1..=3)
+                    .map(|attempt| ConnectionError::ConnectFailed {
+                        reason: format!("attempt {}", attempt)
+                    })
+                    .collect()
+            })
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_stop_retrying_after_max_elapsed() {
+        let started_at = Instant::now();
+
+        let result: Result<(), _> = retry(
+            || std::future::ready(Err(ConnectionError::TimedOut)),
+            &ExponentialBackoff::new(BASE, MAX_DELAY),
+            u16::MAX,
+            Some(Duration::from_millis(1000)),
+        )
+        .await;
+
+        // Slept 100 + 200 + 400 ms, the next 800 ms would exceed the limit
+        assert_eq!(started_at.elapsed(), Duration::from_millis(700));
+        match result {
+            Err(ConnectionError::RetriesExhausted { attempts }) => assert_eq!(attempts.len(), 4),
+            other => panic!("Expected exhausted retries, got {:?}", other),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_not_retry_rejected_certificate() {
+        let mut attempts = 0;
+        let error = ConnectionError::CertificateVerificationFailed {
+            reason: "invalid peer certificate: UnknownIssuer".to_string(),
+        };
+
+        let result: Result<(), _> = retry(
+            || {
+                attempts += 1;
+                std::future::ready(Err(error.clone()))
+            },
+            &FixedDelay::new(BASE),
+            3,
+            None,
+        )
+        .await;
+
+        assert_eq!(attempts, 1);
+        assert_eq!(result, Err(error));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_return_first_success() {
+        let mut attempt = 0;
+
+        let result = retry(
+            || {
+                attempt += 1;
+                std::future::ready(if attempt < 3 {
+                    Err(ConnectionError::TimedOut)
+                } else {
+                    Ok(attempt)
+                })
+            },
+            &FixedDelay::new(BASE),
+            3,
+            None,
+        )
+        .await;
+
+        assert_eq!(result, Ok(3));
+    }
+}
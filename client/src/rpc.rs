@@ -0,0 +1,181 @@
+use common::{
+    codec::default_codec,
+    error::{RpcError, StreamError},
+    message::{
+        hello::{Capabilities, ConnectionParameters, HelloMessage},
+        Message,
+    },
+    rpc::{decode_payload, new_call},
+    stream::{read_next_message, write_message},
+    transport::{Connection, SendStream},
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{error::ClientError, handler::perform_handshake};
+
+/// Calls the methods routed by the `Router` of a server over an established connection.
+///
+/// Every call is made on its own bidirectional stream, so that concurrent calls do not wait for each other.
+///
+/// # Fields
+///
+/// * `connection` - The connection to the server, which has completed the handshake.
+/// * `parameters` - The connection parameters negotiated during the handshake.
+pub struct RpcClient<C> {
+    connection: C,
+    parameters: ConnectionParameters,
+}
+
+impl<C: Connection> RpcClient<C> {
+    /// Creates a client calling methods over a connection which has completed the handshake.
+    ///
+    /// # Arguments
+    /// * `connection` - The connection to the server.
+    /// * `parameters` - The connection parameters negotiated during the handshake.
+    ///
+    /// # Returns
+    /// Returns an `RpcClient` instance.
+    pub fn new(connection: C, parameters: ConnectionParameters) -> Self {
+        Self {
+            connection,
+            parameters,
+        }
+    }
+
+    /// Performs the handshake over a newly established connection, with the default capabilities,
+    /// and creates a client calling methods over it.
+    ///
+    /// # Arguments
+    /// * `connection` - The newly established connection.
+    /// * `client_id` - The ID announced to the server.
+    ///
+    /// # Returns
+    /// * `Result` - The `RpcClient`, or a `ClientError` if the handshake has failed.
+    pub async fn handshake(connection: C, client_id: &str) -> Result<Self, ClientError> {
+        let hello = HelloMessage::new(client_id.to_string(), Capabilities::default());
+        let (ack, _) = perform_handshake(&connection, &hello, default_codec(), None).await?;
+        let parameters = ack.parameters()?;
+
+        Ok(Self::new(connection, parameters))
+    }
+
+    /// Calls a method of the server.
+    ///
+    /// The parameters and the result are serialized with the codec negotiated for the connection.
+    ///
+    /// # Arguments
+    /// * `method` - The name of the method.
+    /// * `params` - The parameters of the call.
+    ///
+    /// # Returns
+    /// * `Result` - The result of the call, or a `ClientError`. Calls which the server fails are reported as
+    ///   `ClientError::ErrorResponse`, with the `UnknownMethod` code for methods it does not route and
+    ///   the `InvalidPayload` code for parameters it cannot decode.
+    pub async fn call<P: Serialize, R: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: &P,
+    ) -> Result<R, ClientError> {
+        let codec = self.parameters.codec;
+        let request = new_call(method, params, codec)?;
+
+        let (mut send_stream, mut recv_stream) = self.connection.open_bi().await?;
+
+        write_message(&mut send_stream, &request, codec)
+            .await
+            .map_err(StreamError::from)?;
+        send_stream.finish().await.map_err(StreamError::from)?;
+
+        let reply = read_next_message(&mut recv_stream, codec, self.parameters.max_message_size)
+            .await
+            .map_err(StreamError::from)?;
+
+        match reply {
+            Message::Response(response) => Ok(decode_payload(&response.data, codec)?),
+            Message::Error(error) => Err(error.into()),
+            other => Err(RpcError::DecodingFailed {
+                reason: format!("unexpected {:?} message", other.message_type()),
+            }
+            .into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::{
+        message::{error::ErrorCode, error::ErrorMessage},
+        rpc::{encode_payload, method_of},
+        transport::loopback::{loopback_pair, DatagramConditions},
+    };
+
+    use super::*;
+
+    fn parameters() -> ConnectionParameters {
+        ConnectionParameters {
+            codec: default_codec(),
+            datagrams_supported: true,
+            max_message_size: 1024,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_should_call_method_and_decode_result() {
+        let (client, server) = loopback_pair(DatagramConditions::default());
+        let rpc_client = RpcClient::new(client, parameters());
+
+        let serve = async {
+            for _ in 0..3 {
+                let (mut send_stream, mut recv_stream) = server.accept_bi().await.unwrap();
+                let request = match read_next_message(&mut recv_stream, default_codec(), 1024)
+                    .await
+                    .unwrap()
+                {
+                    Message::Request(request) => request,
+                    other => panic!("Expected a request, got {:?}", other),
+                };
+
+                let reply = match method_of(&request) {
+                    Ok("negate") => {
+                        let value: i64 = decode_payload(&request.data, default_codec()).unwrap();
+                        let payload = encode_payload(&-value, default_codec()).unwrap();
+                        Message::new_response(&request, 0, payload)
+                    }
+                    Ok("greet") => Message::new_response(&request, 0, vec![1]),
+                    _ => Message::Error(ErrorMessage::new(
+                        ErrorCode::UnknownMethod,
+                        "unknown method",
+                        Some(request.id),
+                    )),
+                };
+
+                write_message(&mut send_stream, &reply, default_codec())
+                    .await
+                    .unwrap();
+            }
+        };
+
+        let calls = async {
+            (
+                rpc_client.call::<i64, i64>("negate", &42).await,
+                rpc_client.call::<(), String>("greet", &()).await,
+                rpc_client.call::<(), ()>("unknown", &()).await,
+            )
+        };
+
+        let (_, (negated, greeting, unknown)) = tokio::join!(serve, calls);
+
+        assert_eq!(negated, Ok(-42));
+        assert!(matches!(
+            greeting,
+            Err(ClientError::RpcError(RpcError::DecodingFailed { .. }))
+        ));
+        assert!(matches!(
+            unknown,
+            Err(ClientError::ErrorResponse {
+                code: ErrorCode::UnknownMethod,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,433 @@
+use std::{fmt, time::Duration};
+
+use hdrhistogram::Histogram;
+
+/// The lowest round-trip time (in microseconds) tracked by the histogram.
+const HISTOGRAM_LOWEST_MICROS: u64 = 1;
+
+/// The highest round-trip time (in microseconds) tracked by the histogram. Larger values are clamped.
+const HISTOGRAM_HIGHEST_MICROS: u64 = 60 * 60 * 1_000_000;
+
+/// The number of significant decimal digits kept by the histogram.
+const HISTOGRAM_SIGNIFICANT_DIGITS: u8 = 3;
+
+/// Represents the client reconnecting after losing the connection halfway through.
+///
+/// # Fields
+/// * `sent_before` - The number of pings sent before the connection was lost.
+/// * `reason` - The error the connection was lost with.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ReconnectEvent {
+    pub sent_before: u64,
+    pub reason: String,
+}
+
+/// Accumulates latency statistics of the pings sent by the `PingClient`.
+///
+/// Exact minimum, maximum, average and mean deviation are computed from running sums,
+/// while percentiles are read from an HDR histogram with microsecond resolution.
+#[derive(Debug, Clone)]
+pub struct PingStatistics {
+    sent: u64,
+    received: u64,
+    replayed: u64,
+    reconnects: Vec<ReconnectEvent>,
+    min: Option<Duration>,
+    max: Option<Duration>,
+    sum_nanos: f64,
+    sum_squares_nanos: f64,
+    histogram: Histogram<u64>,
+}
+
+impl PingStatistics {
+    /// Creates an empty `PingStatistics` accumulator.
+    ///
+    /// # Returns
+    /// Returns a `PingStatistics` instance.
+    pub fn new() -> Self {
+        Self {
+            sent: 0,
+            received: 0,
+            replayed: 0,
+            reconnects: Vec::new(),
+            min: None,
+            max: None,
+            sum_nanos: 0.0,
+            sum_squares_nanos: 0.0,
+            histogram: Histogram::new_with_bounds(
+                HISTOGRAM_LOWEST_MICROS,
+                HISTOGRAM_HIGHEST_MICROS,
+                HISTOGRAM_SIGNIFICANT_DIGITS,
+            )
+            .expect("histogram bounds are valid"),
+        }
+    }
+
+    /// Records that a ping has been sent.
+    pub fn record_sent(&mut self) {
+        self.sent += 1;
+    }
+
+    /// Records that a response to a ping has been received.
+    ///
+    /// # Arguments
+    /// * `round_trip_time` - The measured round-trip time of the ping.
+    pub fn record_received(&mut self, round_trip_time: Duration) {
+        let nanos = round_trip_time.as_nanos() as f64;
+
+        self.received += 1;
+        self.min = Some(
+            self.min
+                .map_or(round_trip_time, |min| min.min(round_trip_time)),
+        );
+        self.max = Some(
+            self.max
+                .map_or(round_trip_time, |max| max.max(round_trip_time)),
+        );
+        self.sum_nanos += nanos;
+        self.sum_squares_nanos += nanos * nanos;
+        self.histogram
+            .saturating_record((round_trip_time.as_micros() as u64).max(HISTOGRAM_LOWEST_MICROS));
+    }
+
+    /// Records that a response has been replayed by the server on session resumption.
+    ///
+    /// The response has not been lost, but its round-trip time is unknown.
+    pub fn record_replayed(&mut self) {
+        self.replayed += 1;
+    }
+
+    /// Records that the client has reconnected after losing the connection.
+    ///
+    /// # Arguments
+    /// * `reason` - The error the connection was lost with.
+    pub fn record_reconnect(&mut self, reason: String) {
+        self.reconnects.push(ReconnectEvent {
+            sent_before: self.sent,
+            reason,
+        });
+    }
+
+    /// Adds the statistics accumulated by another accumulator, e.g. the one of a single stream.
+    ///
+    /// # Arguments
+    /// * `other` - The statistics to add.
+    pub fn merge(&mut self, other: &PingStatistics) {
+        self.sent += other.sent;
+        self.received += other.received;
+        self.replayed += other.replayed;
+        self.reconnects.extend(other.reconnects.iter().cloned());
+        self.min = self.min.into_iter().chain(other.min).min();
+        self.max = self.max.into_iter().chain(other.max).max();
+        self.sum_nanos += other.sum_nanos;
+        self.sum_squares_nanos += other.sum_squares_nanos;
+        self.histogram
+            .add(&other.histogram)
+            .expect("histograms share the same bounds");
+    }
+
+    /// Returns the reconnects of the client, in the order they have happened.
+    pub fn reconnects(&self) -> &[ReconnectEvent] {
+        &self.reconnects
+    }
+
+    /// Returns the number of pings sent.
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
+    /// Returns the number of responses received.
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    /// Computes a summary of the statistics accumulated so far.
+    ///
+    /// # Returns
+    /// Returns a `PingSummary` instance.
+    pub fn summary(&self) -> PingSummary {
+        let loss_percent = if self.sent == 0 {
+            0.0
+        } else {
+            self.sent.saturating_sub(self.received + self.replayed) as f64 * 100.0
+                / self.sent as f64
+        };
+
+        let round_trip_times = self.min.zip(self.max).map(|(min, max)| {
+            let count = self.received as f64;
+            let mean = self.sum_nanos / count;
+            // Same formula as `ping(8)` uses for its `mdev` value
+            let mean_deviation = (self.sum_squares_nanos / count - mean * mean)
+                .max(0.0)
+                .sqrt();
+
+            let percentile = |quantile: f64| {
+                Duration::from_micros(self.histogram.value_at_quantile(quantile)).clamp(min, max)
+            };
+
+            RoundTripTimes {
+                min,
+                avg: Duration::from_nanos(mean as u64),
+                max,
+                mdev: Duration::from_nanos(mean_deviation as u64),
+                p50: percentile(0.5),
+                p90: percentile(0.9),
+                p99: percentile(0.99),
+            }
+        });
+
+        PingSummary {
+            sent: self.sent,
+            received: self.received,
+            replayed: self.replayed,
+            reconnects: self.reconnects.len() as u64,
+            loss_percent,
+            round_trip_times,
+        }
+    }
+}
+
+impl Default for PingStatistics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Represents the round-trip time statistics of the received responses.
+///
+/// # Fields
+/// * `min`, `avg`, `max` - The minimum, average and maximum round-trip times.
+/// * `mdev` - The mean deviation of the round-trip times, as reported by `ping(8)`.
+/// * `p50`, `p90`, `p99` - The round-trip time percentiles.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RoundTripTimes {
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+    pub mdev: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+}
+
+/// Represents a snapshot of the `PingStatistics`.
+///
+/// # Fields
+/// * `sent` - The number of pings sent.
+/// * `received` - The number of responses received.
+/// * `replayed` - The number of responses replayed by the server after reconnecting, not included in `received`.
+/// * `reconnects` - The number of times the client has reconnected after losing the connection.
+/// * `loss_percent` - The percentage of pings left without response.
+/// * `round_trip_times` - The round-trip time statistics. `None` if no responses were received.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PingSummary {
+    pub sent: u64,
+    pub received: u64,
+    pub replayed: u64,
+    pub reconnects: u64,
+    pub loss_percent: f64,
+    pub round_trip_times: Option<RoundTripTimes>,
+}
+
+/// Formats the summary the same way `ping(8)` does.
+impl fmt::Display for PingSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} messages transmitted, {} received, ",
+            self.sent, self.received
+        )?;
+
+        // Reported like the errors of `ping(8)`, only if there are any
+        if self.replayed > 0 {
+            write!(f, "+{} replayed, ", self.replayed)?;
+        }
+
+        if self.reconnects > 0 {
+            write!(f, "+{} reconnects, ", self.reconnects)?;
+        }
+
+        // Rounded to a tenth of a percent, whole percentages without decimals as with the `%g` of `ping(8)`
+        let loss = format!("{:.1}", self.loss_percent);
+        write!(
+            f,
+            "{}% message loss",
+            loss.strip_suffix(".0").unwrap_or(&loss)
+        )?;
+
+        if let Some(rtt) = &self.round_trip_times {
+            let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+
+            write!(
+                f,
+                "\nrtt min/avg/max/mdev = {:.3}/{:.3}/{:.3}/{:.3} ms",
+                millis(rtt.min),
+                millis(rtt.avg),
+                millis(rtt.max),
+                millis(rtt.mdev)
+            )?;
+            write!(
+                f,
+                "\nrtt p50/p90/p99 = {:.3}/{:.3}/{:.3} ms",
+                millis(rtt.p50),
+                millis(rtt.p90),
+                millis(rtt.p99)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_summarize_empty_statistics() {
+        let summary = PingStatistics::new().summary();
+
+        assert_eq!(summary.sent, 0);
+        assert_eq!(summary.received, 0);
+        assert_eq!(summary.loss_percent, 0.0);
+        assert_eq!(summary.round_trip_times, None);
+    }
+
+    #[test]
+    fn test_should_compute_loss() {
+        let mut stats = PingStatistics::new();
+
+        for _ in 0..4 {
+            stats.record_sent();
+        }
+        stats.record_received(Duration::from_millis(1));
+
+        let summary = stats.summary();
+
+        assert_eq!(summary.sent, 4);
+        assert_eq!(summary.received, 1);
+        assert_eq!(summary.loss_percent, 75.0);
+    }
+
+    #[test]
+    fn test_should_compute_round_trip_times() {
+        let mut stats = PingStatistics::new();
+
+        for millis in [10, 20, 30] {
+            stats.record_sent();
+            stats.record_received(Duration::from_millis(millis));
+        }
+
+        let rtt = stats.summary().round_trip_times.unwrap();
+
+        assert_eq!(rtt.min, Duration::from_millis(10));
+        assert_eq!(rtt.avg, Duration::from_millis(20));
+        assert_eq!(rtt.max, Duration::from_millis(30));
+        // sqrt(((10 - 20)^2 + 0 + (30 - 20)^2) / 3) ms
+        assert_eq!(rtt.mdev.as_micros(), 8164);
+        assert!(rtt.p50.abs_diff(Duration::from_millis(20)) < Duration::from_micros(20));
+        assert!(rtt.p99.abs_diff(Duration::from_millis(30)) < Duration::from_micros(30));
+    }
+
+    #[test]
+    fn test_should_format_like_ping() {
+        let mut stats = PingStatistics::new();
+        stats.record_sent();
+        stats.record_sent();
+        stats.record_received(Duration::from_millis(1));
+
+        let output = stats.summary().to_string();
+
+        assert_eq!(
+            output,
+            "2 messages transmitted, 1 received, 50% message loss\n\
+             rtt min/avg/max/mdev = 1.000/1.000/1.000/0.000 ms\n\
+             rtt p50/p90/p99 = 1.000/1.000/1.000 ms"
+        );
+    }
+
+    #[test]
+    fn test_should_round_loss_when_formatting() {
+        let mut stats = PingStatistics::new();
+        for _ in 0..3 {
+            stats.record_sent();
+        }
+        stats.record_received(Duration::from_millis(1));
+        stats.record_received(Duration::from_millis(1));
+
+        let output = stats.summary().to_string();
+
+        assert!(
+            output.starts_with("3 messages transmitted, 2 received, 33.3% message loss\n"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn test_should_record_reconnects() {
+        let mut stats = PingStatistics::new();
+        stats.record_sent();
+        stats.record_reconnect("connection timed out".to_string());
+        stats.record_sent();
+        stats.record_received(Duration::from_millis(1));
+
+        assert_eq!(
+            stats.reconnects(),
+            &[ReconnectEvent {
+                sent_before: 1,
+                reason: "connection timed out".to_string()
+            }]
+        );
+        assert!(stats
+            .summary()
+            .to_string()
+            .starts_with("2 messages transmitted, 1 received, +1 reconnects, 50% message loss"));
+    }
+
+    #[test]
+    fn test_should_merge_statistics_of_streams() {
+        let mut first = PingStatistics::new();
+        let mut second = PingStatistics::new();
+        for millis in [10, 30] {
+            first.record_sent();
+            first.record_received(Duration::from_millis(millis));
+        }
+        second.record_sent();
+        second.record_sent();
+        second.record_received(Duration::from_millis(20));
+
+        let mut overall = PingStatistics::new();
+        overall.merge(&first);
+        overall.merge(&second);
+
+        let summary = overall.summary();
+        let rtt = summary.round_trip_times.unwrap();
+
+        assert_eq!(summary.sent, 4);
+        assert_eq!(summary.received, 3);
+        assert_eq!(summary.loss_percent, 25.0);
+        assert_eq!(rtt.min, Duration::from_millis(10));
+        assert_eq!(rtt.avg, Duration::from_millis(20));
+        assert_eq!(rtt.max, Duration::from_millis(30));
+        assert!(rtt.p50.abs_diff(Duration::from_millis(20)) < Duration::from_micros(20));
+    }
+
+    #[test]
+    fn test_should_not_account_replayed_responses_as_lost() {
+        let mut stats = PingStatistics::new();
+        stats.record_sent();
+        stats.record_sent();
+        stats.record_received(Duration::from_millis(1));
+        stats.record_replayed();
+
+        let summary = stats.summary();
+
+        assert_eq!(summary.received, 1);
+        assert_eq!(summary.replayed, 1);
+        assert_eq!(summary.loss_percent, 0.0);
+        assert!(summary
+            .to_string()
+            .starts_with("2 messages transmitted, 1 received, +1 replayed, 0% message loss"));
+    }
+}
@@ -0,0 +1,294 @@
+use std::{
+    fs,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    Certificate, ClientConfig, RootCertStore, ServerName,
+};
+
+use crate::{
+    error::ClientSetupError,
+    pinning::{parse_certificate_hash, PinnedHashVerifier},
+};
+
+/// The ALPN protocol WebTransport sessions are established over.
+const ALPN_HTTP3: &[u8] = b"h3";
+
+/// Represents how the `PingClient` verifies the certificate presented by the server.
+///
+/// * `SystemRoots` - The certificate has to be issued by a certificate authority trusted by the operating system.
+/// * `CustomCa` - The certificate has to be issued by one of the certificate authorities of a PEM file.
+///   A self-signed server certificate can be trusted by using it as the certificate authority.
+/// * `PinnedHashes` - The SHA-256 hash of the certificate has to be one of `hashes`, given in base64 or hex,
+///   following the WebTransport `serverCertificateHashes` semantics, see `PinnedHashVerifier`.
+/// * `Insecure` - Any certificate is accepted, leaving the connection open to man-in-the-middle attacks.
+///   Only meant for local testing, a warning is printed whenever it is used.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub enum CertificateVerification {
+    #[default]
+    SystemRoots,
+    CustomCa {
+        ca_path: String,
+    },
+    PinnedHashes {
+        hashes: Vec<String>,
+    },
+    Insecure,
+}
+
+/// Verifies server certificates with another verifier, remembering why the last verification has failed.
+///
+/// The QUIC stack only reports a failed handshake, the remembered reason allows to tell a rejected certificate
+/// apart from other connection failures. A `RecordingVerifier` is meant for a single connection attempt,
+/// attempts made concurrently would otherwise take each other's failures.
+pub struct RecordingVerifier {
+    inner: Arc<dyn ServerCertVerifier>,
+    last_failure: Mutex<Option<String>>,
+}
+
+impl RecordingVerifier {
+    /// Creates a new `RecordingVerifier` delegating the verification to `inner`.
+    ///
+    /// # Arguments
+    /// * `inner` - The verifier accepting or rejecting the certificates.
+    ///
+    /// # Returns
+    /// Returns a `RecordingVerifier` instance.
+    pub fn new(inner: Arc<dyn ServerCertVerifier>) -> Self {
+        Self {
+            inner,
+            last_failure: Mutex::new(None),
+        }
+    }
+
+    /// Takes the reason of the last failed verification, if any.
+    ///
+    /// # Returns
+    /// Returns the reason the last certificate has been rejected for, `None` if no certificate has been rejected since.
+    pub fn take_failure(&self) -> Option<String> {
+        self.last_failure
+            .lock()
+            .expect("verifier lock poisoned")
+            .take()
+    }
+}
+
+impl ServerCertVerifier for RecordingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        self.inner
+            .verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                scts,
+                ocsp_response,
+                now,
+            )
+            .inspect_err(|error| {
+                *self.last_failure.lock().expect("verifier lock poisoned") =
+                    Some(error.to_string());
+            })
+    }
+}
+
+/// Creates the certificate verifier of a verification mode.
+///
+/// # Arguments
+/// * `verification` - The certificate verification mode.
+///
+/// # Returns
+/// * `Result` - The verifier to use, `None` if certificates are not verified,
+///   or a `ClientSetupError` if the trusted roots cannot be loaded or a pinned hash is invalid.
+pub fn certificate_verifier(
+    verification: &CertificateVerification,
+) -> Result<Option<Arc<dyn ServerCertVerifier>>, ClientSetupError> {
+    let roots = match verification {
+        CertificateVerification::SystemRoots => system_roots()?,
+        CertificateVerification::CustomCa { ca_path } => custom_roots(ca_path)?,
+        CertificateVerification::PinnedHashes { hashes } => {
+            let hashes = hashes
+                .iter()
+                .map(|hash| parse_certificate_hash(hash))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            return Ok(Some(Arc::new(PinnedHashVerifier::new(hashes))));
+        }
+        CertificateVerification::Insecure => {
+            eprintln!(
+                "WARNING: server certificate verification is disabled, \
+                 the connection is open to man-in-the-middle attacks!"
+            );
+
+            return Ok(None);
+        }
+    };
+
+    Ok(Some(Arc::new(WebPkiVerifier::new(roots, None))))
+}
+
+/// Builds the TLS configuration of the client verifying server certificates with the given verifier.
+///
+/// # Arguments
+/// * `verifier` - The certificate verifier.
+///
+/// # Returns
+/// Returns the `rustls::ClientConfig` to establish connections with.
+pub fn tls_config(verifier: Arc<RecordingVerifier>) -> ClientConfig {
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(verifier)
+        .with_no_client_auth();
+
+    config.alpn_protocols = vec![ALPN_HTTP3.to_vec()];
+
+    config
+}
+
+/// Loads the certificate authorities trusted by the operating system.
+fn system_roots() -> Result<RootCertStore, ClientSetupError> {
+    let certificates = rustls_native_certs::load_native_certs().map_err(|error| {
+        ClientSetupError::CertificateLoadError {
+            path: "system trust store".to_string(),
+            reason: error.to_string(),
+        }
+    })?;
+
+    let mut roots = RootCertStore::empty();
+    let (valid, _) = roots.add_parsable_certificates(
+        &certificates
+            .into_iter()
+            .map(|certificate| certificate.0)
+            .collect::<Vec<_>>(),
+    );
+
+    if valid == 0 {
+        return Err(ClientSetupError::CertificateLoadError {
+            path: "system trust store".to_string(),
+            reason: "no trusted certificate found".to_string(),
+        });
+    }
+
+    Ok(roots)
+}
+
+/// Loads the certificate authorities of a PEM file.
+fn custom_roots(ca_path: &str) -> Result<RootCertStore, ClientSetupError> {
+    let load_error = |reason: String| ClientSetupError::CertificateLoadError {
+        path: ca_path.to_string(),
+        reason,
+    };
+
+    let pem = fs::read(ca_path).map_err(|error| load_error(error.to_string()))?;
+    let sections = pem::parse_many(pem).map_err(|error| load_error(error.to_string()))?;
+
+    let mut roots = RootCertStore::empty();
+    for section in sections.into_iter().filter(|pem| pem.tag == "CERTIFICATE") {
+        roots
+            .add(&Certificate(section.contents))
+            .map_err(|error| load_error(error.to_string()))?;
+    }
+
+    if roots.is_empty() {
+        return Err(load_error("no certificate found".to_string()));
+    }
+
+    Ok(roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use common::utils::gen_certs::gen_certs;
+    use time::Duration;
+
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        let mut path = env::temp_dir();
+        path.push(format!("{}-{}", name, std::process::id()));
+
+        path.into_os_string()
+            .into_string()
+            .expect("failed to construct temporary file path")
+    }
+
+    #[test]
+    fn test_should_load_custom_ca() {
+        let cert_path = temp_path("tls-ca-cert.pem");
+        let key_path = temp_path("tls-ca-key.pem");
+        gen_certs(cert_path.clone(), key_path.clone(), Duration::days(14)).unwrap();
+
+        let verifier =
+            certificate_verifier(&CertificateVerification::CustomCa { ca_path: cert_path })
+                .unwrap();
+
+        assert!(verifier.is_some());
+    }
+
+    #[test]
+    fn test_should_record_rejected_certificate() {
+        let trusted_path = temp_path("tls-trusted-cert.pem");
+        let untrusted_path = temp_path("tls-untrusted-cert.pem");
+        let key_path = temp_path("tls-cert-key.pem");
+        gen_certs(trusted_path.clone(), key_path.clone(), Duration::days(14)).unwrap();
+        gen_certs(untrusted_path.clone(), key_path, Duration::days(14)).unwrap();
+
+        let inner: Arc<dyn ServerCertVerifier> = Arc::new(WebPkiVerifier::new(
+            custom_roots(&trusted_path).unwrap(),
+            None,
+        ));
+        let verifier = RecordingVerifier::new(inner.clone());
+        let concurrent_attempt = RecordingVerifier::new(inner);
+        let untrusted = pem::parse(fs::read(untrusted_path).unwrap()).unwrap();
+
+        let result = verifier.verify_server_cert(
+            &Certificate(untrusted.contents),
+            &[],
+            &ServerName::try_from("localhost").unwrap(),
+            &mut std::iter::empty(),
+            &[],
+            SystemTime::now(),
+        );
+
+        assert!(result.is_err());
+        assert_eq!(concurrent_attempt.take_failure(), None);
+        assert!(verifier.take_failure().is_some());
+        assert_eq!(verifier.take_failure(), None);
+    }
+
+    #[test]
+    fn test_should_reject_missing_or_invalid_ca() {
+        let invalid_path = temp_path("tls-invalid-ca.pem");
+        fs::write(&invalid_path, "not a certificate").unwrap();
+
+        for ca_path in [temp_path("tls-missing-ca.pem"), invalid_path] {
+            let error = certificate_verifier(&CertificateVerification::CustomCa {
+                ca_path: ca_path.clone(),
+            })
+            .err();
+
+            assert!(
+                matches!(error, Some(ClientSetupError::CertificateLoadError { path, .. }) if path == ca_path)
+            );
+        }
+    }
+
+    #[test]
+    fn test_should_not_verify_insecure_connections() {
+        let verifier = certificate_verifier(&CertificateVerification::Insecure).unwrap();
+
+        assert!(verifier.is_none());
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4},
+    time::Duration,
+};
+
+use crate::error::AnnouncementError;
+
+/// The multicast group servers announce themselves to by default, within the organization-local scope.
+pub const DEFAULT_BEACON_GROUP: SocketAddr =
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(239, 255, 80, 78), 4434));
+
+/// The maximum size of an announcement in bytes, small enough for a single unfragmented datagram.
+pub const MAX_ANNOUNCEMENT_SIZE: usize = 1024;
+
+/// The first line of every announcement, telling it apart from other datagrams sent to the group.
+const HEADER: &str = "pong-announcement";
+
+/// Struct representing the announcement a server periodically sends to let clients find it on the local network.
+///
+/// Announcements are sent as UTF-8 text, a header line followed by `key=value` lines in the manner of DNS-SD
+/// TXT records. Unknown keys are ignored, so that fields can be added without breaking older clients.
+///
+/// # Fields
+///
+/// * `server_name` - The name the certificate of the server is issued for.
+/// * `host` - The address the server is bound to, `None` if it listens on all the addresses of the host.
+///   Clients then connect to the address the announcement is received from.
+/// * `port` - The port the server listens on.
+/// * `certificate_hash` - The base64 encoded SHA-256 hash of the certificate of the server, if known.
+/// * `protocol_version` - The protocol version spoken by the server.
+/// * `ttl` - How long the announcement is valid, a few announcement intervals so that a lost datagram
+///   does not make the server disappear.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Announcement {
+    pub server_name: String,
+    pub host: Option<IpAddr>,
+    pub port: u16,
+    pub certificate_hash: Option<String>,
+    pub protocol_version: u16,
+    pub ttl: Duration,
+}
+
+impl Announcement {
+    /// Encodes the announcement into the payload of a datagram.
+    ///
+    /// # Returns
+    ///
+    /// The encoded announcement.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut lines = vec![
+            HEADER.to_string(),
+            format!("version={}", self.protocol_version),
+            format!("name={}", self.server_name),
+            format!("port={}", self.port),
+            format!("ttl={}", self.ttl.as_secs()),
+        ];
+
+        if let Some(host) = self.host {
+            lines.push(format!("host={}", host));
+        }
+        if let Some(certificate_hash) = &self.certificate_hash {
+            lines.push(format!("cert-sha256={}", certificate_hash));
+        }
+
+        lines.join("\n").into_bytes()
+    }
+
+    /// Decodes an announcement from the payload of a datagram.
+    ///
+    /// # Parameters
+    ///
+    /// * `payload` - The payload of the received datagram.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the decoded announcement, or an `AnnouncementError` if the payload is not a valid one.
+    pub fn decode(payload: &[u8]) -> Result<Self, AnnouncementError> {
+        let text =
+            std::str::from_utf8(payload).map_err(|_| AnnouncementError::NotAnAnnouncement)?;
+        let mut lines = text.lines();
+
+        if lines.next() != Some(HEADER) {
+            return Err(AnnouncementError::NotAnAnnouncement);
+        }
+
+        let fields = lines
+            .filter_map(|line| line.split_once('='))
+            .collect::<Vec<_>>();
+        let field = |key: &str| {
+            fields
+                .iter()
+                .find(|(field, _)| *field == key)
+                .map(|(_, value)| *value)
+        };
+        let required = |key: &str| {
+            field(key).ok_or_else(|| AnnouncementError::MissingField {
+                field: key.to_string(),
+            })
+        };
+
+        let server_name = required("name")?;
+        if server_name.is_empty() {
+            return Err(AnnouncementError::InvalidField {
+                field: "name".to_string(),
+                value: server_name.to_string(),
+            });
+        }
+
+        Ok(Self {
+            server_name: server_name.to_string(),
+            host: field("host").map(|host| parse("host", host)).transpose()?,
+            port: parse("port", required("port")?)?,
+            certificate_hash: field("cert-sha256").map(str::to_string),
+            protocol_version: parse("version", required("version")?)?,
+            ttl: Duration::from_secs(parse("ttl", required("ttl")?)?),
+        })
+    }
+}
+
+/// Parses the value of an announcement field.
+fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, AnnouncementError> {
+    value.parse().map_err(|_| AnnouncementError::InvalidField {
+        field: key.to_string(),
+        value: value.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use super::*;
+
+    fn announcement() -> Announcement {
+        Announcement {
+            server_name: "localhost".to_string(),
+            host: None,
+            port: 4433,
+            certificate_hash: None,
+            protocol_version: 1,
+            ttl: Duration::from_secs(15),
+        }
+    }
+
+    #[test]
+    fn test_should_decode_encoded_announcement() {
+        let minimal = announcement();
+        let complete = Announcement {
+            host: Some(IpAddr::V6(Ipv6Addr::LOCALHOST)),
+            certificate_hash: Some("4DzE3ytVQ5d9x8GqvOHcOcTvEVPRt7JXuzb2n2SsO4k=".to_string()),
+            ..announcement()
+        };
+
+        for announcement in [minimal, complete] {
+            assert_eq!(
+                Announcement::decode(&announcement.encode()),
+                Ok(announcement)
+            );
+        }
+    }
+
+    #[test]
+    fn test_should_ignore_unknown_fields() {
+        let payload =
+            b"pong-announcement\nversion=1\nname=localhost\nport=4433\nttl=15\nregion=eu-west";
+
+        assert_eq!(Announcement::decode(payload), Ok(announcement()));
+    }
+
+    #[test]
+    fn test_should_reject_invalid_announcements() {
+        let cases = [
+            (
+                &b"M-SEARCH * HTTP/1.1\r\n"[..],
+                AnnouncementError::NotAnAnnouncement,
+            ),
+            (
+                b"pong-announcement\nversion=1\nname=localhost\nttl=15",
+                AnnouncementError::MissingField {
+                    field: "port".to_string(),
+                },
+            ),
+            (
+                b"pong-announcement\nversion=1\nname=localhost\nport=99999\nttl=15",
+                AnnouncementError::InvalidField {
+                    field: "port".to_string(),
+                    value: "99999".to_string(),
+                },
+            ),
+        ];
+
+        for (payload, expected) in cases {
+            assert_eq!(Announcement::decode(payload), Err(expected));
+        }
+    }
+}
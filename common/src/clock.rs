@@ -0,0 +1,28 @@
+use std::{sync::OnceLock, time::Instant};
+
+/// The instant the process wide monotonic clock counts from.
+static CLOCK_START: OnceLock<Instant> = OnceLock::new();
+
+/// Reads the process wide monotonic clock.
+///
+/// The value is the number of nanoseconds elapsed since the clock has been read for the first time
+/// in the current process. It never goes backwards, but is only meaningful to the process that produced it,
+/// so timestamps of different peers must not be compared with each other.
+///
+/// # Returns
+///
+/// The current clock reading in nanoseconds.
+pub fn monotonic_nanos() -> u64 {
+    CLOCK_START.get_or_init(Instant::now).elapsed().as_nanos() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_should_never_go_backwards() {
+        let first = super::monotonic_nanos();
+        let second = super::monotonic_nanos();
+
+        assert!(second >= first);
+    }
+}
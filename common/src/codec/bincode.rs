@@ -1,9 +1,36 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::Codec;
 use crate::{error::SerializationError, message::Message};
 
+/// The numeric identifier of the bincode codec.
+pub const ID: u8 = 0;
+
+/// Serializes messages with bincode. This is the default codec.
+pub struct BincodeCodec;
+
+impl Codec for BincodeCodec {
+    fn id(&self) -> u8 {
+        ID
+    }
+
+    fn name(&self) -> &'static str {
+        "bincode"
+    }
+
+    fn encode(&self, message: &Message) -> Result<Vec<u8>, SerializationError> {
+        serialize_message(message)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Message, SerializationError> {
+        deserialize_message(bytes)
+    }
+}
+
 /// Serializes a Message into a Vec<u8>.
 ///
 /// This function takes a reference to a Message struct as an argument, serializes it into a byte vector using
-/// bincode (altough it is changeable), and returns the resulting byte vector. If serialization fails, it returns a SerializationError.
+/// bincode, and returns the resulting byte vector. If serialization fails, it returns a SerializationError.
 ///
 /// # Parameters
 ///
@@ -16,15 +43,15 @@ use crate::{error::SerializationError, message::Message};
 /// * `Ok` - Contains a Vec<u8> representing the serialized form of the Message.
 /// * `Err` - Contains a `SerializationError` indicating that serialization has failed.
 pub fn serialize_message(message: &Message) -> Result<Vec<u8>, SerializationError> {
-    bincode::serialize(&message).map_err(|_| SerializationError::SerializationFailed {
-        message: message.clone(),
+    to_vec(message).map_err(|_| SerializationError::SerializationFailed {
+        message: Box::new(message.clone()),
     })
 }
 
 /// Deserializes a Vec<u8> into a Message.
 ///
 /// This function takes a byte slice as an argument, attempts to deserialize it into a Message struct using
-/// bincode (altough it is changeable), and returns the resulting Message. If deserialization fails, it returns a SerializationError.
+/// bincode, and returns the resulting Message. If deserialization fails, it returns a SerializationError.
 ///
 /// # Parameters
 ///
@@ -37,52 +64,46 @@ pub fn serialize_message(message: &Message) -> Result<Vec<u8>, SerializationErro
 /// * `Ok` - Contains the deserialized Message.
 /// * `Err` - Contains a `SerializationError` indicating that deserialization has failed.
 pub fn deserialize_message(bytes: &[u8]) -> Result<Message, SerializationError> {
-    bincode::deserialize(bytes).map_err(|_| SerializationError::DeserializationFailed {
+    from_slice(bytes).map_err(|_| SerializationError::DeserializationFailed {
         bytes: bytes.to_vec(),
     })
 }
 
+/// Serializes any value with bincode.
+pub fn to_vec<T: Serialize>(value: &T) -> Result<Vec<u8>, ::bincode::Error> {
+    ::bincode::serialize(value)
+}
+
+/// Deserializes any value with bincode.
+pub fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, ::bincode::Error> {
+    ::bincode::deserialize(bytes)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::message::Message;
+    use crate::message::tests::{fixed_request, FIXED_REQUEST_BYTES};
 
     #[test]
     fn test_should_serialize_message() {
-        let message = Message::new_request("Ping!".to_string());
+        let message = fixed_request();
 
         let serialized_message = serialize_message(&message).unwrap();
 
-        assert_eq!(
-            serialized_message,
-            vec![
-                0, 0, 0, 0, 32, 0, 0, 0, 0, 0, 0, 0, 112, 120, 96, 80, 200, 192, 175, 162, 219,
-                199, 236, 67, 228, 162, 39, 80, 11, 85, 93, 87, 250, 130, 196, 232, 191, 100, 195,
-                97, 47, 201, 85, 57, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 80, 105, 110, 103, 33,
-            ]
-        );
+        assert_eq!(serialized_message, FIXED_REQUEST_BYTES);
     }
 
     #[test]
     fn test_should_deserialize_message() {
-        let serialized_message = vec![
-            0, 0, 0, 0, 32, 0, 0, 0, 0, 0, 0, 0, 112, 120, 96, 80, 200, 192, 175, 162, 219, 199,
-            236, 67, 228, 162, 39, 80, 11, 85, 93, 87, 250, 130, 196, 232, 191, 100, 195, 97, 47,
-            201, 85, 57, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 80, 105, 110, 103, 33,
-        ];
-
-        let message = deserialize_message(&serialized_message).unwrap();
+        let message = deserialize_message(&FIXED_REQUEST_BYTES).unwrap();
 
-        assert_eq!(message, Message::new_request("Ping!".to_string()));
+        assert_eq!(message, fixed_request());
     }
 
     #[test]
     fn test_return_an_error_in_case_deserialization_fails() {
-        let serialized_message = vec![
-            1, 0, 0, 0, 32, 0, 0, 0, 0, 0, 0, 0, 112, 120, 96, 80, 200, 192, 175, 162, 219, 199,
-            236, 67, 228, 162, 39, 80, 11, 85, 93, 87, 250, 130, 196, 232, 191, 100, 195, 97, 47,
-            201, 85, 57, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 80, 105, 110, 103, 33,
-        ];
+        let mut serialized_message = FIXED_REQUEST_BYTES.to_vec();
+        serialized_message[0] = 255;
 
         match deserialize_message(&serialized_message) {
             Ok(_) => panic!("Should return an error"),
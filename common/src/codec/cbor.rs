@@ -0,0 +1,48 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::Codec;
+use crate::{error::SerializationError, message::Message};
+
+/// The numeric identifier of the CBOR codec.
+pub const ID: u8 = 2;
+
+/// Serializes messages as CBOR (RFC 8949).
+pub struct CborCodec;
+
+impl Codec for CborCodec {
+    fn id(&self) -> u8 {
+        ID
+    }
+
+    fn name(&self) -> &'static str {
+        "cbor"
+    }
+
+    fn encode(&self, message: &Message) -> Result<Vec<u8>, SerializationError> {
+        to_vec(message).map_err(|_| SerializationError::SerializationFailed {
+            message: Box::new(message.clone()),
+        })
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Message, SerializationError> {
+        from_slice(bytes).map_err(|_| SerializationError::DeserializationFailed {
+            bytes: bytes.to_vec(),
+        })
+    }
+}
+
+/// Serializes any value as CBOR.
+pub fn to_vec<T: Serialize>(value: &T) -> Result<Vec<u8>, ciborium::ser::Error<std::io::Error>> {
+    let mut bytes = Vec::new();
+
+    ciborium::ser::into_writer(value, &mut bytes)?;
+
+    Ok(bytes)
+}
+
+/// Deserializes any value from CBOR.
+pub fn from_slice<T: DeserializeOwned>(
+    bytes: &[u8],
+) -> Result<T, ciborium::de::Error<std::io::Error>> {
+    ciborium::de::from_reader(bytes)
+}
@@ -0,0 +1,61 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::Codec;
+use crate::{error::SerializationError, message::Message};
+
+/// The numeric identifier of the JSON codec.
+pub const ID: u8 = 1;
+
+/// Serializes messages as JSON.
+///
+/// Message IDs are represented as hex strings, which makes the format easy to consume
+/// from browsers and scripting languages.
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn id(&self) -> u8 {
+        ID
+    }
+
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn encode(&self, message: &Message) -> Result<Vec<u8>, SerializationError> {
+        to_vec(message).map_err(|_| SerializationError::SerializationFailed {
+            message: Box::new(message.clone()),
+        })
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Message, SerializationError> {
+        from_slice(bytes).map_err(|_| SerializationError::DeserializationFailed {
+            bytes: bytes.to_vec(),
+        })
+    }
+}
+
+/// Serializes any value as JSON.
+pub fn to_vec<T: Serialize>(value: &T) -> Result<Vec<u8>, serde_json::Error> {
+    serde_json::to_vec(value)
+}
+
+/// Deserializes any value from JSON.
+pub fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, serde_json::Error> {
+    serde_json::from_slice(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::tests::fixed_request;
+
+    #[test]
+    fn test_should_serialize_message() {
+        let serialized_message = JsonCodec.encode(&fixed_request()).unwrap();
+
+        assert_eq!(
+            String::from_utf8(serialized_message).unwrap(),
+            r#"{"Request":{"id":"0102030405060708090a0b0c0d0e0f10","message_type":"Request","data":[80,105,110,103,33],"headers":{},"digest":null,"sent_at":42}}"#
+        );
+    }
+}
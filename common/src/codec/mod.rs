@@ -0,0 +1,244 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{error::SerializationError, message::Message};
+
+#[cfg(feature = "bincode")]
+pub mod bincode;
+#[cfg(feature = "cbor")]
+pub mod cbor;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
+
+#[cfg(not(any(
+    feature = "bincode",
+    feature = "json",
+    feature = "cbor",
+    feature = "msgpack"
+)))]
+compile_error!(
+    "at least one of the `bincode`, `json`, `cbor` or `msgpack` features must be enabled"
+);
+
+/// Converts a `Message` into its wire representation and back.
+///
+/// Every codec has a stable numeric `id`, used in the handshake preamble, and a `name`,
+/// used to announce the supported codecs in the handshake messages.
+pub trait Codec: Send + Sync {
+    /// The stable numeric identifier of the codec.
+    fn id(&self) -> u8;
+
+    /// The name of the codec announced during the handshake.
+    fn name(&self) -> &'static str;
+
+    /// Serializes a `Message` into bytes.
+    ///
+    /// # Parameters
+    ///
+    /// * `message` - A reference to the `Message` that needs to be serialized.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is:
+    ///
+    /// * `Ok` - Contains a `Vec<u8>` representing the serialized form of the `Message`.
+    /// * `Err` - Contains a `SerializationError` indicating that serialization has failed.
+    fn encode(&self, message: &Message) -> Result<Vec<u8>, SerializationError>;
+
+    /// Deserializes bytes into a `Message`.
+    ///
+    /// # Parameters
+    ///
+    /// * `bytes` - A slice of bytes that needs to be deserialized into a `Message`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is:
+    ///
+    /// * `Ok` - Contains the deserialized `Message`.
+    /// * `Err` - Contains a `SerializationError` indicating that deserialization has failed.
+    fn decode(&self, bytes: &[u8]) -> Result<Message, SerializationError>;
+}
+
+impl std::fmt::Debug for dyn Codec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// All the codecs compiled into the crate, the default one first.
+const CODECS: &[&dyn Codec] = &[
+    #[cfg(feature = "bincode")]
+    &bincode::BincodeCodec,
+    #[cfg(feature = "json")]
+    &json::JsonCodec,
+    #[cfg(feature = "cbor")]
+    &cbor::CborCodec,
+    #[cfg(feature = "msgpack")]
+    &msgpack::MessagePackCodec,
+];
+
+/// Gets the codec used when no other codec has been negotiated.
+///
+/// That is bincode, unless its feature is disabled.
+pub fn default_codec() -> &'static dyn Codec {
+    CODECS[0]
+}
+
+/// Gets all the codecs compiled into the crate, the default one first.
+pub fn supported_codecs() -> &'static [&'static dyn Codec] {
+    CODECS
+}
+
+/// Gets the names of all the codecs compiled into the crate, the default one first.
+pub fn supported_codec_names() -> Vec<String> {
+    CODECS
+        .iter()
+        .map(|codec| codec.name().to_string())
+        .collect()
+}
+
+/// Looks up a compiled in codec by its name.
+///
+/// # Parameters
+///
+/// * `name` - The name of the codec.
+///
+/// # Returns
+///
+/// The codec, or `None` if it is unknown or its feature is disabled.
+pub fn codec_by_name(name: &str) -> Option<&'static dyn Codec> {
+    CODECS.iter().copied().find(|codec| codec.name() == name)
+}
+
+/// Looks up a compiled in codec by its numeric identifier.
+///
+/// # Parameters
+///
+/// * `id` - The numeric identifier of the codec.
+///
+/// # Returns
+///
+/// The codec, or `None` if it is unknown or its feature is disabled.
+pub fn codec_by_id(id: u8) -> Option<&'static dyn Codec> {
+    CODECS.iter().copied().find(|codec| codec.id() == id)
+}
+
+/// Serializes any value the way a compiled in codec serializes messages, e.g. the payload of an RPC call.
+///
+/// The `Codec` trait only (de)serializes messages, the codec is therefore looked up by its numeric identifier.
+///
+/// # Parameters
+///
+/// * `value` - The value to serialize.
+/// * `codec` - The codec to serialize the value with.
+///
+/// # Returns
+///
+/// A `Result` containing the bytes, or the reason the value cannot be serialized, e.g. the codec not being
+/// compiled into the crate.
+pub fn encode_value<T: Serialize>(value: &T, codec: &dyn Codec) -> Result<Vec<u8>, String> {
+    match codec.id() {
+        #[cfg(feature = "bincode")]
+        bincode::ID => bincode::to_vec(value).map_err(|error| error.to_string()),
+        #[cfg(feature = "json")]
+        json::ID => json::to_vec(value).map_err(|error| error.to_string()),
+        #[cfg(feature = "cbor")]
+        cbor::ID => cbor::to_vec(value).map_err(|error| error.to_string()),
+        #[cfg(feature = "msgpack")]
+        msgpack::ID => msgpack::to_vec(value).map_err(|error| error.to_string()),
+        _ => Err(format!("codec {} cannot serialize values", codec.name())),
+    }
+}
+
+/// Deserializes any value the way a compiled in codec deserializes messages.
+///
+/// # Parameters
+///
+/// * `bytes` - The bytes to deserialize.
+/// * `codec` - The codec the bytes have been serialized with.
+///
+/// # Returns
+///
+/// A `Result` containing the value, or the reason the bytes do not hold one.
+pub fn decode_value<T: DeserializeOwned>(bytes: &[u8], codec: &dyn Codec) -> Result<T, String> {
+    match codec.id() {
+        #[cfg(feature = "bincode")]
+        bincode::ID => bincode::from_slice(bytes).map_err(|error| error.to_string()),
+        #[cfg(feature = "json")]
+        json::ID => json::from_slice(bytes).map_err(|error| error.to_string()),
+        #[cfg(feature = "cbor")]
+        cbor::ID => cbor::from_slice(bytes).map_err(|error| error.to_string()),
+        #[cfg(feature = "msgpack")]
+        msgpack::ID => msgpack::from_slice(bytes).map_err(|error| error.to_string()),
+        _ => Err(format!("codec {} cannot deserialize values", codec.name())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{headers::HeaderValue, request::RequestMessage};
+
+    fn sample_messages() -> Vec<Message> {
+        let request = RequestMessage::new(vec![0, 1, 254, 255]);
+        let response = Message::new_response(&request, 7, "Pong!").with_header("tag", vec![1, 2]);
+
+        vec![
+            Message::Request(request).with_header("trace-id", HeaderValue::from("abc")),
+            response,
+        ]
+    }
+
+    #[test]
+    fn test_every_codec_should_round_trip_messages() {
+        for codec in supported_codecs() {
+            for message in sample_messages() {
+                let bytes = codec.encode(&message).unwrap();
+
+                assert_eq!(codec.decode(&bytes).unwrap(), message, "codec {:?}", codec);
+            }
+        }
+    }
+
+    #[test]
+    fn test_every_codec_should_reject_garbage() {
+        for codec in supported_codecs() {
+            assert!(
+                codec.decode(&[0xff, 0x00, 0x13]).is_err(),
+                "codec {:?}",
+                codec
+            );
+        }
+    }
+
+    #[test]
+    fn test_every_codec_should_encode_values_like_messages() {
+        for codec in supported_codecs() {
+            for message in sample_messages() {
+                let bytes = encode_value(&message, *codec).unwrap();
+
+                assert_eq!(bytes, codec.encode(&message).unwrap(), "codec {:?}", codec);
+                assert_eq!(
+                    decode_value::<Message>(&bytes, *codec).unwrap(),
+                    message,
+                    "codec {:?}",
+                    codec
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_should_look_up_codecs() {
+        for codec in supported_codecs() {
+            assert_eq!(codec_by_name(codec.name()).unwrap().id(), codec.id());
+            assert_eq!(codec_by_id(codec.id()).unwrap().name(), codec.name());
+        }
+
+        assert!(codec_by_name("xml").is_none());
+        assert!(codec_by_id(u8::MAX).is_none());
+        assert_eq!(supported_codec_names()[0], default_codec().name());
+    }
+}
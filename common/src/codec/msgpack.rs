@@ -0,0 +1,45 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::Codec;
+use crate::{error::SerializationError, message::Message};
+
+/// The numeric identifier of the MessagePack codec.
+pub const ID: u8 = 3;
+
+/// Serializes messages as MessagePack.
+///
+/// Structs are encoded as maps keyed by field names rather than as positional arrays,
+/// so that consumers do not depend on the field order.
+pub struct MessagePackCodec;
+
+impl Codec for MessagePackCodec {
+    fn id(&self) -> u8 {
+        ID
+    }
+
+    fn name(&self) -> &'static str {
+        "msgpack"
+    }
+
+    fn encode(&self, message: &Message) -> Result<Vec<u8>, SerializationError> {
+        to_vec(message).map_err(|_| SerializationError::SerializationFailed {
+            message: Box::new(message.clone()),
+        })
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Message, SerializationError> {
+        from_slice(bytes).map_err(|_| SerializationError::DeserializationFailed {
+            bytes: bytes.to_vec(),
+        })
+    }
+}
+
+/// Serializes any value as MessagePack, structs as maps keyed by field names like messages.
+pub fn to_vec<T: Serialize>(value: &T) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+    rmp_serde::to_vec_named(value)
+}
+
+/// Deserializes any value from MessagePack.
+pub fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, rmp_serde::decode::Error> {
+    rmp_serde::from_slice(bytes)
+}
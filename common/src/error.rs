@@ -1,6 +1,6 @@
 use thiserror::Error;
 
-use crate::message::Message;
+use crate::message::{error::ErrorCode, Message, MessageType};
 
 /// Enumerates potential errors that can occur during stream operations in the client.
 ///
@@ -29,12 +29,25 @@ pub enum StreamError {
 ///   could be read.
 /// - `DataDeserializationFailed`: Errors occurred during deserialization of data from the stream.
 /// - `DatagramError`: Errors specific to Datagram operations during its read from the stream.
+/// - `UnsupportedCodec`: The peer has announced a codec which is unknown or not compiled in.
+/// - `FrameTooLarge`: The peer has announced a frame larger than the configured maximum frame size.
+///   The frame is not read, so the stream has to be reset.
+/// - `IoError`: An I/O error occurred while reading from an `AsyncRead` source.
 #[derive(Error, Debug, PartialEq, Clone)]
 pub enum ReadStreamError {
     #[error("connection closed before reading enough bytes")]
     ConnectionClosed,
     #[error("stream have stopped before reading enough bytes")]
     StreamStopped,
+    #[error("unsupported codec with id {id}")]
+    UnsupportedCodec { id: u8 },
+    #[error("frame of {size} bytes exceeds the maximum frame size of {max_size} bytes")]
+    FrameTooLarge { size: u64, max_size: u64 },
+    #[error("I/O error ({kind:?}): {message}")]
+    IoError {
+        kind: std::io::ErrorKind,
+        message: String,
+    },
     #[error("failed to deserialize underlying data: {0}")]
     DataDeserializationFailed(#[from] SerializationError),
     #[error(transparent)]
@@ -52,12 +65,18 @@ pub enum ReadStreamError {
 ///   could be written.
 /// - `DataSerializationFailed`: Errors occurred during serialization of data before writing to the stream.
 /// - `DatagramError`: Errors specific to Datagram operations during writing to the stream.
+/// - `IoError`: An I/O error occurred while writing to an `AsyncWrite` sink.
 #[derive(Error, Debug, PartialEq, Clone)]
 pub enum WriteStreamError {
     #[error("connection closed before writing enough bytes")]
     ConnectionClosed,
     #[error("stream have stopped before writing enough bytes")]
     StreamStopped,
+    #[error("I/O error ({kind:?}): {message}")]
+    IoError {
+        kind: std::io::ErrorKind,
+        message: String,
+    },
     #[error("failed to serialize underlying data: {0}")]
     DataSerializationFailed(#[from] SerializationError),
     #[error(transparent)]
@@ -75,7 +94,13 @@ pub enum WriteStreamError {
 /// - `TimedOut`: This error indicates that the connection operation exceeded its allocated time.
 /// - `HTTP3`: This error is specific to HTTP3 protocol errors. It includes a code and a reason string.
 /// - `QuicError`: This error is specific to QUIC protocol errors.
-/// - `MaxRetriesReached`: This error indicates that the maximum number of retry attempts has been reached.
+/// - `ConnectFailed`: This error indicates that connecting could not even be started, e.g. due to an invalid
+///   server name.
+/// - `ResolutionFailed`: This error indicates that the hostname of the server could not be resolved.
+/// - `CertificateVerificationFailed`: This error indicates that the certificate presented by the peer
+///   has been rejected. It carries the reason of the rejection.
+/// - `RetriesExhausted`: This error indicates that the connection could not be established within the retry limits.
+///   It carries the error of every failed attempt in order.
 #[derive(Error, Debug, PartialEq, Clone)]
 pub enum ConnectionError {
     #[error("connection closed by peer with code {code:?}: {reason:?}")]
@@ -93,8 +118,17 @@ pub enum ConnectionError {
     #[error("QUIC protocol error")]
     QuicError,
 
-    #[error("maximum retries ({retry_count:?}) reached")]
-    MaxRetriesReached { retry_count: u16 },
+    #[error("failed to start connecting: {reason}")]
+    ConnectFailed { reason: String },
+
+    #[error("failed to resolve {host}: {reason}")]
+    ResolutionFailed { host: String, reason: String },
+
+    #[error("certificate verification failed: {reason}")]
+    CertificateVerificationFailed { reason: String },
+
+    #[error("giving up after {} failed attempts{}", attempts.len(), last_attempt_error(attempts))]
+    RetriesExhausted { attempts: Vec<ConnectionError> },
 }
 
 /// Represents the potential errors that can occur while handling datagrams.
@@ -132,12 +166,135 @@ pub enum DatagramError {
 #[derive(Error, Debug, PartialEq, Clone)]
 pub enum SerializationError {
     #[error("serialization failed")]
-    SerializationFailed { message: Message },
+    SerializationFailed { message: Box<Message> },
 
     #[error("deserialization failed")]
     DeserializationFailed { bytes: Vec<u8> },
 }
 
+/// Enumerates potential errors that can occur during the protocol handshake.
+///
+/// `HandshakeError` includes the reasons peers may fail to agree on the connection parameters.
+/// The variants include:
+///
+/// - `IncompatibleVersion`: The peers speak different protocol versions.
+/// - `NoCommonCodec`: None of the codecs offered by the client is supported by the server.
+/// - `DatagramsUnsupported`: Datagrams are required but not supported by both peers.
+/// - `Rejected`: The server has rejected the handshake for the given reason.
+/// - `UnexpectedMessage`: A message other than the expected handshake message has been received.
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum HandshakeError {
+    #[error("incompatible protocol version {remote}, expected {local}")]
+    IncompatibleVersion { local: u16, remote: u16 },
+
+    #[error("none of the offered codecs {offered:?} is supported")]
+    NoCommonCodec { offered: Vec<String> },
+
+    #[error("datagrams are not supported by both peers")]
+    DatagramsUnsupported,
+
+    #[error("handshake rejected by peer: {reason}")]
+    Rejected { reason: String },
+
+    #[error("unexpected {message_type:?} message during handshake")]
+    UnexpectedMessage { message_type: MessageType },
+}
+
+/// Enumerates potential errors that can occur while parsing a `MessageId` from its hex representation.
+///
+/// The `MessageIdParseError` enum includes two variants:
+///
+/// - `InvalidLength`: The input does not have the length of a hex encoded `MessageId`.
+/// - `InvalidCharacter`: The input contains a character which is not a hex digit.
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum MessageIdParseError {
+    #[error("invalid message id length {length}, expected 32 hex characters")]
+    InvalidLength { length: usize },
+
+    #[error("invalid hex character {character:?} at position {index}")]
+    InvalidCharacter { character: char, index: usize },
+}
+
+/// Enumerates potential errors that can occur while decoding a server announcement.
+///
+/// The `AnnouncementError` enum includes three variants:
+///
+/// - `NotAnAnnouncement`: The datagram does not start with the announcement header, it has been sent by
+///   another application using the same group.
+/// - `MissingField`: A required field is missing from the announcement.
+/// - `InvalidField`: A field does not hold a valid value.
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum AnnouncementError {
+    #[error("the datagram is not a server announcement")]
+    NotAnAnnouncement,
+
+    #[error("missing announcement field {field:?}")]
+    MissingField { field: String },
+
+    #[error("invalid value {value:?} of announcement field {field:?}")]
+    InvalidField { field: String, value: String },
+}
+
+/// Enumerates potential errors that can occur while making or serving a remote procedure call.
+///
+/// The `RpcError` enum includes the following variants:
+///
+/// - `MissingMethod`: The request does not name the method it calls.
+/// - `UnknownMethod`: The called method is not routed by the server.
+/// - `EncodingFailed`: The parameters or the result of the call could not be serialized.
+/// - `DecodingFailed`: The payload could not be deserialized into the parameters or the result of the call.
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum RpcError {
+    #[error("the request does not name a method")]
+    MissingMethod,
+
+    #[error("unknown method {method:?}")]
+    UnknownMethod { method: String },
+
+    #[error("failed to encode payload: {reason}")]
+    EncodingFailed { reason: String },
+
+    #[error("failed to decode payload: {reason}")]
+    DecodingFailed { reason: String },
+}
+
+impl RpcError {
+    /// Gets the code of the error message a failed call is answered with.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            RpcError::MissingMethod | RpcError::UnknownMethod { .. } => ErrorCode::UnknownMethod,
+            RpcError::EncodingFailed { .. } => ErrorCode::InternalError,
+            RpcError::DecodingFailed { .. } => ErrorCode::InvalidPayload,
+        }
+    }
+}
+
+/// Formats the error of the last failed connection attempt for `ConnectionError::RetriesExhausted`.
+fn last_attempt_error(attempts: &[ConnectionError]) -> String {
+    attempts
+        .last()
+        .map(|error| format!(", last error: {}", error))
+        .unwrap_or_default()
+}
+
+impl From<std::io::Error> for ReadStreamError {
+    fn from(error: std::io::Error) -> Self {
+        ReadStreamError::IoError {
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<std::io::Error> for WriteStreamError {
+    fn from(error: std::io::Error) -> Self {
+        WriteStreamError::IoError {
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+}
+
 impl From<wtransport::error::StreamError> for ReadStreamError {
     fn from(error: wtransport::error::StreamError) -> Self {
         match error {
@@ -1,6 +1,10 @@
+pub mod beacon;
+pub mod clock;
+pub mod codec;
 pub mod error;
 pub mod hash;
 pub mod message;
-pub mod serialization;
+pub mod rpc;
 pub mod stream;
+pub mod transport;
 pub mod utils;
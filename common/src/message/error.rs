@@ -0,0 +1,144 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::{id::MessageId, MessageType};
+
+/// An enumeration of the stable error codes carried by an `ErrorMessage`.
+///
+/// The codes are part of the wire protocol and must never be reassigned. The `ErrorCode` enum includes the following variants:
+///
+/// - `MalformedMessage` (1): The received frame could not be deserialized into a message.
+/// - `MessageTooLarge` (2): The received message exceeds the negotiated maximum message size.
+/// - `UnsupportedMessage` (3): The received message is valid but cannot be handled by the peer.
+/// - `InternalError` (4): The peer failed to process a valid message.
+/// - `UnknownMethod` (5): The RPC method named by the request is missing or not routed by the peer.
+/// - `InvalidPayload` (6): The payload of the RPC request cannot be decoded into the parameters of its method.
+/// - `Unknown`: A code not known to this version of the protocol.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Copy, Clone)]
+#[serde(from = "u16", into = "u16")]
+pub enum ErrorCode {
+    MalformedMessage,
+    MessageTooLarge,
+    UnsupportedMessage,
+    InternalError,
+    UnknownMethod,
+    InvalidPayload,
+    Unknown(u16),
+}
+
+impl ErrorCode {
+    /// Gets the numeric value of the code.
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::MalformedMessage => 1,
+            Self::MessageTooLarge => 2,
+            Self::UnsupportedMessage => 3,
+            Self::InternalError => 4,
+            Self::UnknownMethod => 5,
+            Self::InvalidPayload => 6,
+            Self::Unknown(code) => *code,
+        }
+    }
+}
+
+impl From<u16> for ErrorCode {
+    fn from(code: u16) -> Self {
+        match code {
+            1 => Self::MalformedMessage,
+            2 => Self::MessageTooLarge,
+            3 => Self::UnsupportedMessage,
+            4 => Self::InternalError,
+            5 => Self::UnknownMethod,
+            6 => Self::InvalidPayload,
+            code => Self::Unknown(code),
+        }
+    }
+}
+
+impl From<ErrorCode> for u16 {
+    fn from(code: ErrorCode) -> Self {
+        code.code()
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({:?})", self.code(), self)
+    }
+}
+
+/// Struct representing an Error Message.
+///
+/// Sent back instead of a response when a received message cannot be processed.
+///
+/// # Fields
+///
+/// * `id` - A globally unique identifier of this message.
+/// * `message_type` - Enum specifying the type of the message.
+/// * `code` - The stable error code.
+/// * `reason` - A human readable description of the error.
+/// * `request_id` - The ID of the offending message, if it could be read.
+///
+/// # Serialization
+///
+/// This struct can be serialized and deserialized with Serde.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ErrorMessage {
+    pub id: MessageId,
+    pub message_type: MessageType,
+    pub code: ErrorCode,
+    pub reason: String,
+    pub request_id: Option<MessageId>,
+}
+
+impl ErrorMessage {
+    /// Constructs a new `ErrorMessage`.
+    ///
+    /// # Parameters
+    ///
+    /// * `code` - The stable error code.
+    /// * `reason` - A human readable description of the error.
+    /// * `request_id` - The ID of the offending message, if known.
+    ///
+    /// # Returns
+    ///
+    /// An instance of `ErrorMessage`.
+    pub fn new(code: ErrorCode, reason: impl Into<String>, request_id: Option<MessageId>) -> Self {
+        Self {
+            id: MessageId::generate(),
+            message_type: MessageType::Error,
+            code,
+            reason: reason.into(),
+            request_id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes_should_be_stable() {
+        for code in 0..10 {
+            assert_eq!(ErrorCode::from(code).code(), code);
+        }
+
+        assert_eq!(ErrorCode::MalformedMessage.code(), 1);
+        assert_eq!(ErrorCode::MessageTooLarge.code(), 2);
+        assert_eq!(ErrorCode::UnsupportedMessage.code(), 3);
+        assert_eq!(ErrorCode::InternalError.code(), 4);
+        assert_eq!(ErrorCode::UnknownMethod.code(), 5);
+        assert_eq!(ErrorCode::InvalidPayload.code(), 6);
+        assert_eq!(ErrorCode::from(1000), ErrorCode::Unknown(1000));
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn test_error_code_should_serialize_as_number() {
+        let bytes = bincode::serialize(&ErrorCode::MessageTooLarge).unwrap();
+
+        assert_eq!(bytes, vec![2, 0]);
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Metadata attached to a message, e.g. trace IDs or tags.
+///
+/// A sorted map is used so that the serialized form of a message does not depend on the insertion order.
+pub type Headers = BTreeMap<String, HeaderValue>;
+
+/// An enumeration of the possible values of a message header.
+///
+/// The `HeaderValue` enum includes two variants:
+///
+/// - `Text`: A UTF-8 string value.
+/// - `Binary`: An arbitrary binary value.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub enum HeaderValue {
+    Text(String),
+    Binary(#[serde(with = "serde_bytes")] Vec<u8>),
+}
+
+impl HeaderValue {
+    /// Gets the value as a string.
+    ///
+    /// # Returns
+    ///
+    /// The string value, or `None` for binary values.
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Self::Text(text) => Some(text),
+            Self::Binary(_) => None,
+        }
+    }
+
+    /// Gets the raw bytes of the value. Strings are returned as their UTF-8 representation.
+    ///
+    /// # Returns
+    ///
+    /// The bytes of the value.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Text(text) => text.as_bytes(),
+            Self::Binary(bytes) => bytes,
+        }
+    }
+}
+
+impl From<&str> for HeaderValue {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_string())
+    }
+}
+
+impl From<String> for HeaderValue {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl From<Vec<u8>> for HeaderValue {
+    fn from(value: Vec<u8>) -> Self {
+        Self::Binary(value)
+    }
+}
+
+impl From<&[u8]> for HeaderValue {
+    fn from(value: &[u8]) -> Self {
+        Self::Binary(value.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_convert_text_and_binary_values() {
+        let text = HeaderValue::from("trace");
+        let binary = HeaderValue::from(vec![0xde, 0xad]);
+
+        assert_eq!(text.as_text(), Some("trace"));
+        assert_eq!(text.as_bytes(), b"trace");
+        assert_eq!(binary.as_text(), None);
+        assert_eq!(binary.as_bytes(), &[0xde, 0xad]);
+    }
+}
@@ -0,0 +1,324 @@
+use serde::{Deserialize, Serialize};
+
+use super::{id::MessageId, MessageType};
+use crate::{
+    codec::{self, Codec},
+    error::HandshakeError,
+};
+
+/// The version of the wire protocol implemented by this crate.
+///
+/// Peers are only compatible if they speak the very same protocol version.
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// The default maximum size of a single message in bytes.
+pub const DEFAULT_MAX_MESSAGE_SIZE: u64 = 1024 * 1024;
+
+/// Struct representing the capabilities a peer announces during the handshake.
+///
+/// # Fields
+///
+/// * `codecs` - Names of the supported codecs in the order of preference.
+/// * `datagrams_supported` - Whether the peer is able to exchange datagrams.
+/// * `max_message_size` - The maximum size of a single message (in bytes) the peer accepts.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Capabilities {
+    pub codecs: Vec<String>,
+    pub datagrams_supported: bool,
+    pub max_message_size: u64,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            codecs: codec::supported_codec_names(),
+            datagrams_supported: true,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        }
+    }
+}
+
+/// Struct representing the connection parameters both peers have agreed on during the handshake.
+///
+/// # Fields
+///
+/// * `codec` - The codec all the messages following the handshake are serialized with.
+/// * `datagrams_supported` - Whether datagrams can be exchanged.
+/// * `max_message_size` - The maximum size of a single message in bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionParameters {
+    pub codec: &'static dyn Codec,
+    pub datagrams_supported: bool,
+    pub max_message_size: u64,
+}
+
+/// Struct representing a Hello Message.
+///
+/// This is the first message sent by the client on a newly established connection.
+///
+/// # Fields
+///
+/// * `id` - A globally unique identifier of this message.
+/// * `message_type` - Enum specifying the type of the message.
+/// * `protocol_version` - The protocol version spoken by the client.
+/// * `client_id` - A stable identifier of the client.
+/// * `capabilities` - The capabilities of the client.
+///
+/// # Serialization
+///
+/// This struct can be serialized and deserialized with Serde.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct HelloMessage {
+    pub id: MessageId,
+    pub message_type: MessageType,
+    pub protocol_version: u16,
+    pub client_id: String,
+    pub capabilities: Capabilities,
+}
+
+/// Struct representing a HelloAck Message.
+///
+/// Sent by the server in response to an acceptable `HelloMessage`. Carries the parameters
+/// both peers have to use for the rest of the connection.
+///
+/// # Fields
+///
+/// * `id` - A globally unique identifier of this message.
+/// * `message_type` - Enum specifying the type of the message.
+/// * `hello_id` - The ID of the hello message this acknowledgement is for.
+/// * `protocol_version` - The protocol version spoken by the server.
+/// * `codec` - The name of the negotiated codec.
+/// * `datagrams_supported` - Whether both peers are able to exchange datagrams.
+/// * `max_message_size` - The maximum size of a single message (in bytes) both peers accept.
+///
+/// # Serialization
+///
+/// This struct can be serialized and deserialized with Serde.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct HelloAckMessage {
+    pub id: MessageId,
+    pub message_type: MessageType,
+    pub hello_id: MessageId,
+    pub protocol_version: u16,
+    pub codec: String,
+    pub datagrams_supported: bool,
+    pub max_message_size: u64,
+}
+
+/// Struct representing a HelloReject Message.
+///
+/// Sent by the server in response to a `HelloMessage` of an incompatible peer, right before closing the connection.
+///
+/// # Fields
+///
+/// * `id` - A globally unique identifier of this message.
+/// * `message_type` - Enum specifying the type of the message.
+/// * `hello_id` - The ID of the rejected hello message.
+/// * `protocol_version` - The protocol version spoken by the server.
+/// * `reason` - A human readable reason of the rejection.
+///
+/// # Serialization
+///
+/// This struct can be serialized and deserialized with Serde.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct HelloRejectMessage {
+    pub id: MessageId,
+    pub message_type: MessageType,
+    pub hello_id: MessageId,
+    pub protocol_version: u16,
+    pub reason: String,
+}
+
+impl HelloMessage {
+    /// Constructs a new `HelloMessage` for the current protocol version.
+    ///
+    /// # Parameters
+    ///
+    /// * `client_id` - A stable identifier of the client.
+    /// * `capabilities` - The capabilities of the client.
+    ///
+    /// # Returns
+    ///
+    /// An instance of `HelloMessage`.
+    pub fn new(client_id: String, capabilities: Capabilities) -> Self {
+        Self {
+            id: MessageId::generate(),
+            message_type: MessageType::Hello,
+            protocol_version: PROTOCOL_VERSION,
+            client_id,
+            capabilities,
+        }
+    }
+
+    /// Negotiates the connection parameters with the local capabilities.
+    ///
+    /// The first codec of the client's preference list supported locally is chosen,
+    /// datagrams are only enabled if both peers support them and the smaller of both message size limits is used.
+    ///
+    /// # Parameters
+    ///
+    /// * `local` - The capabilities of the accepting peer.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is:
+    ///
+    /// * `Ok` - Contains the `HelloAckMessage` to send back.
+    /// * `Err` - Contains a `HandshakeError` describing why the peers are incompatible.
+    pub fn negotiate(&self, local: &Capabilities) -> Result<HelloAckMessage, HandshakeError> {
+        if self.protocol_version != PROTOCOL_VERSION {
+            return Err(HandshakeError::IncompatibleVersion {
+                local: PROTOCOL_VERSION,
+                remote: self.protocol_version,
+            });
+        }
+
+        let codec = self
+            .capabilities
+            .codecs
+            .iter()
+            .find(|codec| local.codecs.contains(codec))
+            .ok_or_else(|| HandshakeError::NoCommonCodec {
+                offered: self.capabilities.codecs.clone(),
+            })?;
+
+        Ok(HelloAckMessage {
+            id: MessageId::generate(),
+            message_type: MessageType::HelloAck,
+            hello_id: self.id,
+            protocol_version: PROTOCOL_VERSION,
+            codec: codec.clone(),
+            datagrams_supported: self.capabilities.datagrams_supported && local.datagrams_supported,
+            max_message_size: self
+                .capabilities
+                .max_message_size
+                .min(local.max_message_size),
+        })
+    }
+
+    /// Constructs the `HelloRejectMessage` for this hello.
+    ///
+    /// # Parameters
+    ///
+    /// * `error` - The reason of the rejection.
+    ///
+    /// # Returns
+    ///
+    /// An instance of `HelloRejectMessage`.
+    pub fn reject(&self, error: &HandshakeError) -> HelloRejectMessage {
+        HelloRejectMessage {
+            id: MessageId::generate(),
+            message_type: MessageType::HelloReject,
+            hello_id: self.id,
+            protocol_version: PROTOCOL_VERSION,
+            reason: error.to_string(),
+        }
+    }
+}
+
+impl HelloAckMessage {
+    /// Gets the connection parameters carried by the acknowledgement.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is:
+    ///
+    /// * `Ok` - Contains the `ConnectionParameters`.
+    /// * `Err` - Contains a `HandshakeError` if the negotiated codec is not compiled in.
+    pub fn parameters(&self) -> Result<ConnectionParameters, HandshakeError> {
+        let codec =
+            codec::codec_by_name(&self.codec).ok_or_else(|| HandshakeError::NoCommonCodec {
+                offered: vec![self.codec.clone()],
+            })?;
+
+        Ok(ConnectionParameters {
+            codec,
+            datagrams_supported: self.datagrams_supported,
+            max_message_size: self.max_message_size,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities(
+        codecs: &[&str],
+        datagrams_supported: bool,
+        max_message_size: u64,
+    ) -> Capabilities {
+        Capabilities {
+            codecs: codecs.iter().map(|codec| codec.to_string()).collect(),
+            datagrams_supported,
+            max_message_size,
+        }
+    }
+
+    #[test]
+    fn test_should_negotiate_common_parameters() {
+        let hello = HelloMessage::new(
+            "client".to_string(),
+            capabilities(&["json", "bincode"], true, 2048),
+        );
+
+        let ack = hello
+            .negotiate(&capabilities(&["bincode", "json"], false, 1024))
+            .unwrap();
+
+        assert_eq!(ack.hello_id, hello.id);
+        assert_eq!(ack.protocol_version, PROTOCOL_VERSION);
+        assert_eq!(ack.codec, "json");
+        assert!(!ack.datagrams_supported);
+        assert_eq!(ack.max_message_size, 1024);
+    }
+
+    #[test]
+    fn test_should_resolve_negotiated_parameters() {
+        let hello = HelloMessage::new("client".to_string(), Capabilities::default());
+
+        let parameters = hello
+            .negotiate(&Capabilities::default())
+            .unwrap()
+            .parameters()
+            .unwrap();
+
+        assert_eq!(parameters.codec.name(), codec::default_codec().name());
+        assert!(parameters.datagrams_supported);
+        assert_eq!(parameters.max_message_size, DEFAULT_MAX_MESSAGE_SIZE);
+    }
+
+    #[test]
+    fn test_should_reject_incompatible_version() {
+        let mut hello = HelloMessage::new("client".to_string(), Capabilities::default());
+        hello.protocol_version = PROTOCOL_VERSION + 1;
+
+        let error = hello.negotiate(&Capabilities::default()).unwrap_err();
+
+        assert_eq!(
+            error,
+            HandshakeError::IncompatibleVersion {
+                local: PROTOCOL_VERSION,
+                remote: PROTOCOL_VERSION + 1
+            }
+        );
+        assert_eq!(hello.reject(&error).reason, error.to_string());
+    }
+
+    #[test]
+    fn test_should_reject_peer_without_common_codec() {
+        let hello = HelloMessage::new(
+            "client".to_string(),
+            capabilities(&["protobuf"], true, DEFAULT_MAX_MESSAGE_SIZE),
+        );
+
+        let error = hello.negotiate(&Capabilities::default()).unwrap_err();
+
+        assert_eq!(
+            error,
+            HandshakeError::NoCommonCodec {
+                offered: vec!["protobuf".to_string()]
+            }
+        );
+    }
+}
@@ -1,31 +1,195 @@
-use crate::hash;
+use std::{
+    fmt,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-/// Generates a unique identifier for a given data set.
-/// Should produce same id for the same data.
-///
-/// This function takes a byte slice as an argument, applies a hash function to it,
-/// and returns the resulting hash as a unique identifier in the form of a byte vector.
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::MessageIdParseError;
+
+/// The length of a `MessageId` in bytes.
+pub const MESSAGE_ID_LENGTH: usize = 16;
+
+/// The length of the timestamp part of a `MessageId` in bytes.
+const TIMESTAMP_LENGTH: usize = 6;
+
+/// The length of the random part of a `MessageId` in bytes.
+const RANDOMNESS_LENGTH: usize = MESSAGE_ID_LENGTH - TIMESTAMP_LENGTH;
+
+/// A globally unique, time ordered message identifier.
 ///
-/// # Parameters
+/// The layout follows ULID: the first 48 bits hold the big-endian number of milliseconds
+/// since the Unix epoch and the remaining 80 bits are random. Identifiers generated in
+/// different milliseconds therefore sort by their creation time.
 ///
-/// * `data` - A slice of bytes that needs to be hashed to create a unique identifier.
+/// The identifier is displayed and parsed as a 32 characters long lowercase hex string.
 ///
-/// # Returns
+/// # Serialization
 ///
-/// A vector of bytes that represent a unique identifier generated from the input data.
-pub fn generate_id(data: &[u8]) -> Vec<u8> {
-    hash::hash(data)
+/// Binary formats get the raw 16 bytes, human readable formats (e.g. JSON) get the hex string.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub struct MessageId([u8; MESSAGE_ID_LENGTH]);
+
+impl MessageId {
+    /// Generates a new unique identifier using the current system time.
+    ///
+    /// # Returns
+    ///
+    /// A new `MessageId`.
+    pub fn generate() -> Self {
+        let timestamp_millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or_default();
+
+        Self::from_parts(timestamp_millis, rand::random())
+    }
+
+    /// Constructs an identifier from its timestamp and random parts.
+    ///
+    /// Only the lower 48 bits of the timestamp are used.
+    ///
+    /// # Parameters
+    ///
+    /// * `timestamp_millis` - Milliseconds since the Unix epoch.
+    /// * `randomness` - The random part of the identifier.
+    ///
+    /// # Returns
+    ///
+    /// A new `MessageId`.
+    pub fn from_parts(timestamp_millis: u64, randomness: [u8; RANDOMNESS_LENGTH]) -> Self {
+        let mut bytes = [0; MESSAGE_ID_LENGTH];
+
+        bytes[..TIMESTAMP_LENGTH].copy_from_slice(&timestamp_millis.to_be_bytes()[2..]);
+        bytes[TIMESTAMP_LENGTH..].copy_from_slice(&randomness);
+
+        Self(bytes)
+    }
+
+    /// Constructs an identifier from its raw byte representation.
+    pub const fn from_bytes(bytes: [u8; MESSAGE_ID_LENGTH]) -> Self {
+        Self(bytes)
+    }
+
+    /// Gets the raw byte representation of the identifier.
+    pub fn as_bytes(&self) -> &[u8; MESSAGE_ID_LENGTH] {
+        &self.0
+    }
+
+    /// Gets the creation time of the identifier in milliseconds since the Unix epoch.
+    pub fn timestamp_millis(&self) -> u64 {
+        let mut timestamp_bytes = [0; 8];
+        timestamp_bytes[8 - TIMESTAMP_LENGTH..].copy_from_slice(&self.0[..TIMESTAMP_LENGTH]);
+
+        u64::from_be_bytes(timestamp_bytes)
+    }
+}
+
+impl fmt::Display for MessageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for MessageId {
+    type Err = MessageIdParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != MESSAGE_ID_LENGTH * 2 {
+            return Err(MessageIdParseError::InvalidLength { length: s.len() });
+        }
+
+        let mut bytes = [0; MESSAGE_ID_LENGTH];
+
+        for (index, character) in s.chars().enumerate() {
+            let nibble = character
+                .to_digit(16)
+                .ok_or(MessageIdParseError::InvalidCharacter { character, index })?
+                as u8;
+
+            bytes[index / 2] |= if index % 2 == 0 { nibble << 4 } else { nibble };
+        }
+
+        Ok(Self(bytes))
+    }
+}
+
+impl Serialize for MessageId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            self.0.serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MessageId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let s = String::deserialize(deserializer)?;
+            s.parse().map_err(de::Error::custom)
+        } else {
+            <[u8; MESSAGE_ID_LENGTH]>::deserialize(deserializer).map(Self)
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generates_unique_ids() {
+        let id1 = MessageId::generate();
+        let id2 = MessageId::generate();
+
+        assert_ne!(id1, id2);
+    }
+
+    #[test]
+    fn test_ids_are_ordered_by_time() {
+        let earlier = MessageId::from_parts(1_000, [0xff; RANDOMNESS_LENGTH]);
+        let later = MessageId::from_parts(1_001, [0; RANDOMNESS_LENGTH]);
+
+        assert!(earlier < later);
+        assert_eq!(earlier.timestamp_millis(), 1_000);
+        assert_eq!(later.timestamp_millis(), 1_001);
+    }
+
     #[test]
-    fn test_generates_same_id_for_same_input() {
-        let data = "The answer is - 42".as_bytes();
+    fn test_should_display_and_parse_hex() {
+        let id = MessageId::from_parts(0x0102_0304_0506, [0xab; RANDOMNESS_LENGTH]);
 
-        let id1 = super::generate_id(data);
-        let id2 = super::generate_id(data);
+        let hex = id.to_string();
 
-        assert_eq!(id1, id2);
+        assert_eq!(hex, "010203040506abababababababababab");
+        assert_eq!(hex.parse::<MessageId>().unwrap(), id);
+        assert_eq!(
+            "010203040506ABABABABABABABABABAB"
+                .parse::<MessageId>()
+                .unwrap(),
+            id
+        );
+    }
+
+    #[test]
+    fn test_should_return_error_for_invalid_hex() {
+        assert_eq!(
+            "0102".parse::<MessageId>(),
+            Err(MessageIdParseError::InvalidLength { length: 4 })
+        );
+        assert_eq!(
+            "010203040506abababababababababaz".parse::<MessageId>(),
+            Err(MessageIdParseError::InvalidCharacter {
+                character: 'z',
+                index: 31
+            })
+        );
     }
 }
@@ -1,37 +1,74 @@
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    clock,
+    codec::{default_codec, Codec},
     error::SerializationError,
-    serialization::{deserialize_message, serialize_message},
 };
 
+use self::{
+    headers::{HeaderValue, Headers},
+    id::MessageId,
+};
+
+/// Headers of the messages which do not carry any.
+static NO_HEADERS: Headers = Headers::new();
+
+pub mod error;
+pub mod headers;
+pub mod hello;
 pub mod id;
 pub mod request;
 pub mod response;
+pub mod resume;
 
 /// An enumeration of the possible types of messages that can be sent or received in the system.
 ///
-/// The `Message` enum includes two variants:
+/// The `Message` enum includes the following variants:
 ///
 /// - `Request`: This variant wraps a `RequestMessage`, which represents a request from the client.
 /// - `Response`: This variant wraps a `ResponseMessage`, which represents a response from the server.
+/// - `Hello`: This variant wraps a `HelloMessage`, which opens the handshake of the client.
+/// - `HelloAck`: This variant wraps a `HelloAckMessage`, which completes the handshake.
+/// - `HelloReject`: This variant wraps a `HelloRejectMessage`, which rejects an incompatible client.
+/// - `Error`: This variant wraps an `ErrorMessage`, which is sent back instead of a response to a message that cannot be processed.
+/// - `Resume`: This variant wraps a `ResumeMessage`, which asks for the responses not received before reconnecting.
+/// - `ResumeAck`: This variant wraps a `ResumeAckMessage`, which announces the replayed responses.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum Message {
     Request(request::RequestMessage),
     Response(response::ResponseMessage),
+    Hello(hello::HelloMessage),
+    HelloAck(hello::HelloAckMessage),
+    HelloReject(hello::HelloRejectMessage),
+    Error(error::ErrorMessage),
+    Resume(resume::ResumeMessage),
+    ResumeAck(resume::ResumeAckMessage),
 }
 
 /// A representation of the different types of messages that can be part of a `Message`.
 ///
-/// The `MessageType` enum includes two variants:
+/// The `MessageType` enum includes the following variants:
 ///
 /// - `Request`: Represents a request message.
 /// - `Response`: Represents a response message.
+/// - `Hello`: Represents a hello message.
+/// - `HelloAck`: Represents a hello acknowledgement message.
+/// - `HelloReject`: Represents a hello rejection message.
+/// - `Error`: Represents an error message.
+/// - `Resume`: Represents a session resumption message.
+/// - `ResumeAck`: Represents a session resumption acknowledgement message.
 #[repr(u8)]
 #[derive(Serialize, Deserialize, Debug, PartialEq, Copy, Clone)]
 pub enum MessageType {
     Request = 0,
     Response = 1,
+    Hello = 2,
+    HelloAck = 3,
+    HelloReject = 4,
+    Error = 5,
+    Resume = 6,
+    ResumeAck = 7,
 }
 
 impl Message {
@@ -39,24 +76,97 @@ impl Message {
     ///
     /// # Returns
     ///
-    /// An underlying message type data as `String`.
-    pub fn get_data(&self) -> String {
+    /// An underlying message type binary data. Handshake, resumption and error messages carry no data.
+    pub fn get_data(&self) -> &[u8] {
+        match self {
+            Self::Request(request) => &request.data,
+            Self::Response(response) => &response.data,
+            Self::Hello(_)
+            | Self::HelloAck(_)
+            | Self::HelloReject(_)
+            | Self::Resume(_)
+            | Self::ResumeAck(_)
+            | Self::Error(_) => &[],
+        }
+    }
+
+    /// Gets the headers of the underlying message type.
+    ///
+    /// # Returns
+    ///
+    /// An underlying message type headers. Handshake, resumption and error messages carry no headers.
+    pub fn get_headers(&self) -> &Headers {
+        match self {
+            Self::Request(request) => &request.headers,
+            Self::Response(response) => &response.headers,
+            Self::Hello(_)
+            | Self::HelloAck(_)
+            | Self::HelloReject(_)
+            | Self::Resume(_)
+            | Self::ResumeAck(_)
+            | Self::Error(_) => &NO_HEADERS,
+        }
+    }
+
+    /// Attaches a header to the underlying message type, replacing the previous value of the same key.
+    ///
+    /// Handshake, resumption and error messages carry no headers, so they are returned unchanged.
+    ///
+    /// # Parameters
+    ///
+    /// * `key` - The header name.
+    /// * `value` - The header value, either a string or binary data.
+    ///
+    /// # Returns
+    ///
+    /// The `Message` with the header attached.
+    pub fn with_header(mut self, key: impl Into<String>, value: impl Into<HeaderValue>) -> Self {
+        match &mut self {
+            Self::Request(request) => {
+                request.headers.insert(key.into(), value.into());
+            }
+            Self::Response(response) => {
+                response.headers.insert(key.into(), value.into());
+            }
+            Self::Hello(_)
+            | Self::HelloAck(_)
+            | Self::HelloReject(_)
+            | Self::Resume(_)
+            | Self::ResumeAck(_)
+            | Self::Error(_) => {}
+        }
+
+        self
+    }
+
+    /// Gets the type of the underlying message.
+    ///
+    /// # Returns
+    ///
+    /// An underlying message type.
+    pub fn message_type(&self) -> MessageType {
         match self {
-            Self::Request(request) => request.data.clone(),
-            Self::Response(response) => response.data.clone(),
+            Self::Request(request) => request.message_type,
+            Self::Response(response) => response.message_type,
+            Self::Hello(hello) => hello.message_type,
+            Self::HelloAck(hello_ack) => hello_ack.message_type,
+            Self::HelloReject(hello_reject) => hello_reject.message_type,
+            Self::Resume(resume) => resume.message_type,
+            Self::ResumeAck(resume_ack) => resume_ack.message_type,
+            Self::Error(error) => error.message_type,
         }
     }
 
-    /// Gest the message as it's byte representation.
+    /// Gest the message as it's byte representation using the default codec.
     ///
     /// # Returns
     ///
     /// Message as it's byte representaton in for of `Vec<u8>`.
     pub fn as_bytes(&self) -> Result<Vec<u8>, SerializationError> {
-        serialize_message(self)
+        self.encode(default_codec())
     }
 
-    /// Constructs a new `Message` from it's byte representation.
+    /// Constructs a new `Message` from it's byte representation using the default codec.
     ///
     /// # Parameters
     ///
@@ -66,12 +176,39 @@ impl Message {
     ///
     /// An instance of `Message`.
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, SerializationError> {
-        deserialize_message(bytes)
+        Self::decode(bytes, default_codec())
+    }
+
+    /// Gets the message as it's byte representation using the given codec.
+    ///
+    /// # Parameters
+    ///
+    /// * `codec` - The codec to serialize the message with.
+    ///
+    /// # Returns
+    ///
+    /// Message as it's byte representaton in for of `Vec<u8>`.
+    pub fn encode(&self, codec: &dyn Codec) -> Result<Vec<u8>, SerializationError> {
+        codec.encode(self)
+    }
+
+    /// Constructs a new `Message` from it's byte representation using the given codec.
+    ///
+    /// # Parameters
+    ///
+    /// * `bytes` - The byte representation of the message.
+    /// * `codec` - The codec the message has been serialized with.
+    ///
+    /// # Returns
+    ///
+    /// An instance of `Message`.
+    pub fn decode(bytes: &[u8], codec: &dyn Codec) -> Result<Self, SerializationError> {
+        codec.decode(bytes)
     }
 
     /// Constructs a new `RequestMessage`.
     ///
-    /// This function takes a string as the message content, assigns a `MessageType::Request` to the `message_type`,
+    /// This function takes the binary message content, assigns a `MessageType::Request` to the `message_type`,
     ///
     /// # Parameters
     ///
@@ -80,48 +217,119 @@ impl Message {
     /// # Returns
     ///
     /// An instance of `RequestMessage`.
-    pub fn new_request(data: String) -> Self {
+    pub fn new_request(data: impl Into<Vec<u8>>) -> Self {
         Self::Request(request::RequestMessage::new(data))
     }
 
     /// Constructs a new `ResponseMessage`.
     ///
-    /// This function takes the request ID and a string as the message content, assigns a `MessageType::Response` to the `message_type`,
+    /// This function takes the request and the binary message content, assigns a `MessageType::Response` to the `message_type`,
     ///
     /// # Parameters
     ///
-    /// * `request_id` - The ID of the request this response is for.
+    /// * `request` - The request this response is for.
+    /// * `received_at` - The monotonic clock reading taken when the request was received.
+    /// * `data` - The content of the message.
     ///
     /// # Returns
     ///
     /// An instance of `ResponseMessage`.
-    pub fn new_response(request_id: &[u8], data: String) -> Self {
-        Self::Response(response::ResponseMessage::new(request_id, data))
+    pub fn new_response(
+        request: &request::RequestMessage,
+        received_at: u64,
+        data: impl Into<Vec<u8>>,
+    ) -> Self {
+        Self::Response(response::ResponseMessage::new(request, received_at, data))
+    }
+
+    /// Gets the ID of the underlying message type.
+    ///
+    /// # Returns
+    ///
+    /// An underlying message type ID.
+    pub fn id(&self) -> MessageId {
+        match self {
+            Self::Request(request) => request.id,
+            Self::Response(response) => response.id,
+            Self::Hello(hello) => hello.id,
+            Self::HelloAck(hello_ack) => hello_ack.id,
+            Self::HelloReject(hello_reject) => hello_reject.id,
+            Self::Resume(resume) => resume.id,
+            Self::ResumeAck(resume_ack) => resume_ack.id,
+            Self::Error(error) => error.id,
+        }
+    }
+
+    /// Constructs a copy of the message with a freshly generated ID and send timestamp.
+    ///
+    /// Used to send the same content several times while keeping every sent message distinguishable.
+    ///
+    /// # Returns
+    ///
+    /// A copy of the `Message` with a new ID.
+    pub fn reissue(&self) -> Self {
+        let mut message = self.clone();
+
+        match &mut message {
+            Self::Request(request) => {
+                request.id = MessageId::generate();
+                request.sent_at = clock::monotonic_nanos();
+            }
+            Self::Response(response) => {
+                response.id = MessageId::generate();
+                response.sent_at = clock::monotonic_nanos();
+            }
+            Self::Hello(hello) => hello.id = MessageId::generate(),
+            Self::HelloAck(hello_ack) => hello_ack.id = MessageId::generate(),
+            Self::HelloReject(hello_reject) => hello_reject.id = MessageId::generate(),
+            Self::Resume(resume) => resume.id = MessageId::generate(),
+            Self::ResumeAck(resume_ack) => resume_ack.id = MessageId::generate(),
+            Self::Error(error) => error.id = MessageId::generate(),
+        }
+
+        message
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
 
-    use crate::message::id::generate_id;
+    use crate::hash::hash;
+
+    pub(crate) fn fixed_request() -> Message {
+        Message::Request(request::RequestMessage {
+            id: MessageId::from_bytes([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]),
+            message_type: MessageType::Request,
+            data: b"Ping!".to_vec(),
+            headers: Headers::new(),
+            digest: None,
+            sent_at: 42,
+        })
+    }
+
+    pub(crate) const FIXED_REQUEST_BYTES: [u8; 54] = [
+        0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 0, 0, 0, 0, 5, 0, 0, 0,
+        0, 0, 0, 0, 80, 105, 110, 103, 33, 0, 0, 0, 0, 0, 0, 0, 0, 0, 42, 0, 0, 0, 0, 0, 0, 0,
+    ];
 
     mod new_response {
         use super::*;
 
         #[test]
         fn test_should_have_correct_type_and_id() {
-            let request_id = vec![1, 2, 3, 4];
-            let text = "Ping!".to_string();
+            let request = request::RequestMessage::new("Ping!".to_string());
+            let text = "Pong!".to_string();
 
-            let message = Message::new_response(&request_id, text.clone());
+            let message = Message::new_response(&request, 0, text.clone());
 
             match message {
                 Message::Response(response) => {
-                    assert_eq!(response.request_id, request_id);
+                    assert_eq!(response.request_id, request.id);
+                    assert_eq!(response.request_sent_at, request.sent_at);
                     assert_eq!(response.message_type, MessageType::Response);
-                    assert_eq!(response.data, text);
-                    assert_eq!(response.id, generate_id(text.as_bytes()));
+                    assert_eq!(response.data, text.as_bytes());
+                    assert_eq!(response.digest, Some(hash(text.as_bytes())));
                 }
                 _ => panic!("Message should be a response"),
             }
@@ -140,51 +348,92 @@ mod tests {
             match message {
                 Message::Request(request) => {
                     assert_eq!(request.message_type, MessageType::Request);
-                    assert_eq!(request.data, text);
-                    assert_eq!(request.id, generate_id(text.as_bytes()));
+                    assert_eq!(request.data, text.as_bytes());
+                    assert_eq!(request.digest, Some(hash(text.as_bytes())));
                 }
                 _ => panic!("Message should be a request"),
             }
         }
     }
 
+    mod with_header {
+        use super::*;
+
+        #[test]
+        fn test_should_attach_text_and_binary_headers() {
+            let message = Message::new_request(vec![0, 159, 146, 150])
+                .with_header("trace-id", "abc")
+                .with_header("tag", vec![1, 2, 3]);
+
+            assert_eq!(message.get_data(), &[0, 159, 146, 150]);
+            assert_eq!(
+                message.get_headers().get("trace-id"),
+                Some(&HeaderValue::Text("abc".to_string()))
+            );
+            assert_eq!(
+                message.get_headers().get("tag"),
+                Some(&HeaderValue::Binary(vec![1, 2, 3]))
+            );
+        }
+
+        #[test]
+        fn test_should_survive_serialization_round_trip() {
+            let message = Message::new_request(vec![255, 0, 1]).with_header("trace-id", "abc");
+
+            let bytes = message.as_bytes().unwrap();
+
+            assert_eq!(Message::from_bytes(&bytes).unwrap(), message);
+        }
+    }
+
+    mod reissue {
+        use super::*;
+
+        #[test]
+        fn test_should_keep_data_and_change_id() {
+            let message = Message::new_request("Ping!".to_string());
+
+            let reissued = message.reissue();
+
+            assert_ne!(reissued.id(), message.id());
+            assert_eq!(reissued.get_data(), message.get_data());
+        }
+
+        #[test]
+        fn test_should_restamp_send_time() {
+            let message = fixed_request();
+            let before = clock::monotonic_nanos();
+
+            match message.reissue() {
+                Message::Request(request) => assert!(request.sent_at >= before),
+                _ => panic!("Message should be a request"),
+            }
+        }
+    }
+
+    #[cfg(feature = "bincode")]
     mod as_bytes {
         use super::*;
 
         #[test]
         fn test_should_return_correct_bytes() {
-            let text = "Ping!".to_string();
-            let message = Message::new_request(text);
+            let message = fixed_request();
 
             let bytes = message.as_bytes().unwrap();
 
-            assert_eq!(
-                bytes,
-                vec![
-                    0, 0, 0, 0, 32, 0, 0, 0, 0, 0, 0, 0, 112, 120, 96, 80, 200, 192, 175, 162, 219,
-                    199, 236, 67, 228, 162, 39, 80, 11, 85, 93, 87, 250, 130, 196, 232, 191, 100,
-                    195, 97, 47, 201, 85, 57, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 80, 105, 110,
-                    103, 33
-                ]
-            );
+            assert_eq!(bytes, FIXED_REQUEST_BYTES);
         }
     }
 
+    #[cfg(feature = "bincode")]
     mod from_bytes {
         use super::*;
 
         #[test]
         fn test_should_return_correct_message() {
-            let bytes = vec![
-                0, 0, 0, 0, 32, 0, 0, 0, 0, 0, 0, 0, 112, 120, 96, 80, 200, 192, 175, 162, 219,
-                199, 236, 67, 228, 162, 39, 80, 11, 85, 93, 87, 250, 130, 196, 232, 191, 100, 195,
-                97, 47, 201, 85, 57, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 80, 105, 110, 103, 33,
-            ];
-            let text = "Ping!".to_string();
-
-            let message = Message::from_bytes(&bytes).unwrap();
+            let message = Message::from_bytes(&FIXED_REQUEST_BYTES).unwrap();
 
-            assert_eq!(message, Message::new_request(text));
+            assert_eq!(message, fixed_request());
         }
 
         #[test]
@@ -1,35 +1,45 @@
 use serde::{Deserialize, Serialize};
 
-use super::{id::generate_id, MessageType};
+use super::{headers::Headers, id::MessageId, MessageType};
+use crate::{clock, hash};
 
 /// Struct representing a Request Message.
 ///
 /// This struct encapsulates the data for a request message in the application.
-/// Each request message has an identifier `id`, a type `message_type`, and the message `data`.
+/// Each request message has an identifier `id`, a type `message_type`, the message `data`,
+/// its `headers`, an optional content `digest` and the `sent_at` timestamp.
 ///
 /// # Fields
 ///
-/// * `id` - A vector of bytes that uniquely identifies this message.
+/// * `id` - A globally unique identifier of this message.
 /// * `message_type` - Enum specifying the type of the message.
-/// * `data` - The content of the request message.
+/// * `data` - The binary content of the request message.
+/// * `headers` - The metadata attached to the message.
+/// * `digest` - An optional hash of the message content.
+/// * `sent_at` - The sender's monotonic clock reading (in nanoseconds) at the time the message was created.
 ///
-/// The `id` is automatically generated based on the message content when a new `RequestMessage` is created.
+/// The `id` is freshly generated, the `digest` is computed from the message content
+/// and `sent_at` is stamped when a new `RequestMessage` is created.
 ///
 /// # Serialization
 ///
 /// This struct can be serialized and deserialized with Serde.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct RequestMessage {
-    pub id: Vec<u8>,
+    pub id: MessageId,
     pub message_type: MessageType,
-    pub data: String,
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+    pub headers: Headers,
+    pub digest: Option<Vec<u8>>,
+    pub sent_at: u64,
 }
 
 impl RequestMessage {
     /// Constructs a new `RequestMessage`.
     ///
-    /// This function takes a string as the message content, assigns a `MessageType::Request` to the `message_type`,
-    /// generates an ID based on the message content, and returns a new instance of `RequestMessage`.
+    /// This function takes the binary message content, assigns a `MessageType::Request` to the `message_type`,
+    /// generates a unique ID, computes the digest of the message content, stamps the send time and returns a new instance of `RequestMessage`.
     ///
     /// # Parameters
     ///
@@ -38,11 +48,16 @@ impl RequestMessage {
     /// # Returns
     ///
     /// An instance of `RequestMessage`.
-    pub fn new(data: String) -> Self {
+    pub fn new(data: impl Into<Vec<u8>>) -> Self {
+        let data = data.into();
+
         Self {
-            id: generate_id(data.as_bytes()),
+            id: MessageId::generate(),
             message_type: MessageType::Request,
+            digest: Some(hash::hash(&data)),
             data,
+            headers: Headers::new(),
+            sent_at: clock::monotonic_nanos(),
         }
     }
 }
@@ -52,13 +67,21 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_should_have_correct_type_and_id() {
+    fn test_should_have_correct_type_and_digest() {
         let text = "Ping!".to_string();
 
         let message = RequestMessage::new(text.clone());
 
-        assert_eq!(message.data, text);
+        assert_eq!(message.data, text.as_bytes());
         assert_eq!(message.message_type, MessageType::Request);
-        assert_eq!(message.id, generate_id(text.as_bytes()));
+        assert_eq!(message.digest, Some(hash::hash(text.as_bytes())));
+    }
+
+    #[test]
+    fn test_should_have_unique_id_for_same_data() {
+        let message1 = RequestMessage::new("Ping!".to_string());
+        let message2 = RequestMessage::new("Ping!".to_string());
+
+        assert_ne!(message1.id, message2.id);
     }
 }
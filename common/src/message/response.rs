@@ -1,54 +1,100 @@
+use std::time::Duration;
+
 use serde::{Deserialize, Serialize};
 
-use super::{id::generate_id, MessageType};
+use super::{headers::Headers, id::MessageId, request::RequestMessage, MessageType};
+use crate::{clock, hash};
 
 /// Struct representing a Response Message.
 ///
 /// This struct encapsulates the data for a response message in the application.
-/// Each response message has an identifier `id`, the request's ID `request_id`, a type `message_type`, and the message `data`.
+/// Each response message has an identifier `id`, the request's ID `request_id`, a type `message_type`, the message `data`,
+/// its `headers`, an optional content `digest` and the timestamps required to measure latency.
 ///
 /// # Fields
 ///
-/// * `id` - A vector of bytes that uniquely identifies this message.
+/// * `id` - A globally unique identifier of this message.
 /// * `request_id` - The ID of the request this response is for.
 /// * `message_type` - Enum specifying the type of the message.
-/// * `data` - The content of the response message.
+/// * `data` - The binary content of the response message.
+/// * `headers` - The metadata attached to the message.
+/// * `digest` - An optional hash of the message content.
+/// * `request_sent_at` - The `sent_at` timestamp of the request echoed back to its sender.
+/// * `received_at` - The responder's monotonic clock reading (in nanoseconds) at the time the request was received.
+/// * `sent_at` - The responder's monotonic clock reading (in nanoseconds) at the time the response was created.
 ///
-/// The `id` is automatically generated based on the message content when a new `ResponseMessage` is created.
+/// The `id` is freshly generated, the `digest` is computed from the message content
+/// and `sent_at` is stamped when a new `ResponseMessage` is created.
 ///
 /// # Serialization
 ///
 /// This struct can be serialized and deserialized with Serde.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct ResponseMessage {
-    pub id: Vec<u8>,
-    pub request_id: Vec<u8>,
+    pub id: MessageId,
+    pub request_id: MessageId,
     pub message_type: MessageType,
-    pub data: String,
+    #[serde(with = "serde_bytes")]
+    pub data: Vec<u8>,
+    pub headers: Headers,
+    pub digest: Option<Vec<u8>>,
+    pub request_sent_at: u64,
+    pub received_at: u64,
+    pub sent_at: u64,
 }
 
 impl ResponseMessage {
     /// Constructs a new `ResponseMessage`.
     ///
-    /// This function takes the request ID and a string as the message content, assigns a `MessageType::Response` to the `message_type`,
-    /// generates an ID based on the message content, and returns a new instance of `ResponseMessage`.
+    /// This function takes the request and the binary message content, assigns a `MessageType::Response` to the `message_type`,
+    /// generates a unique ID, computes the digest of the message content, echoes the request timestamp, stamps the send time
+    /// and returns a new instance of `ResponseMessage`.
     ///
     /// # Parameters
     ///
-    /// * `request_id` - The ID of the request this response is for.
+    /// * `request` - The request this response is for.
+    /// * `received_at` - The monotonic clock reading taken when the request was received.
     /// * `data` - The content of the message.
     ///
     /// # Returns
     ///
     /// An instance of `ResponseMessage`.
-    pub fn new(request_id: &[u8], data: String) -> Self {
+    pub fn new(request: &RequestMessage, received_at: u64, data: impl Into<Vec<u8>>) -> Self {
+        let data = data.into();
+
         Self {
-            id: generate_id(data.as_bytes()),
-            request_id: request_id.to_vec(),
+            id: MessageId::generate(),
+            request_id: request.id,
             message_type: MessageType::Response,
+            digest: Some(hash::hash(&data)),
             data,
+            headers: Headers::new(),
+            request_sent_at: request.sent_at,
+            received_at,
+            sent_at: clock::monotonic_nanos(),
         }
     }
+
+    /// Gets the time the responder spent between receiving the request and creating the response.
+    ///
+    /// # Returns
+    ///
+    /// The processing time as `Duration`.
+    pub fn processing_time(&self) -> Duration {
+        Duration::from_nanos(self.sent_at.saturating_sub(self.received_at))
+    }
+
+    /// Gets the round-trip time of the request this response is for.
+    ///
+    /// Must be called by the sender of the request, since the request timestamp is only
+    /// meaningful to the clock that produced it.
+    ///
+    /// # Returns
+    ///
+    /// The round-trip time as `Duration`.
+    pub fn round_trip_time(&self) -> Duration {
+        Duration::from_nanos(clock::monotonic_nanos().saturating_sub(self.request_sent_at))
+    }
 }
 
 #[cfg(test)]
@@ -56,15 +102,29 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_should_have_correct_type_and_id() {
-        let request_id = vec![1, 2, 3, 4];
-        let text = "Ping!".to_string();
+    fn test_should_have_correct_type_and_digest() {
+        let request = RequestMessage::new("Ping!".to_string());
+        let text = "Pong!".to_string();
 
-        let message = ResponseMessage::new(&request_id, text.clone());
+        let message = ResponseMessage::new(&request, 0, text.clone());
 
-        assert_eq!(message.request_id, request_id);
+        assert_eq!(message.request_id, request.id);
         assert_eq!(message.message_type, MessageType::Response);
-        assert_eq!(message.data, text);
-        assert_eq!(message.id, generate_id(text.as_bytes()));
+        assert_eq!(message.data, text.as_bytes());
+        assert_eq!(message.digest, Some(hash::hash(text.as_bytes())));
+        assert_ne!(message.id, request.id);
+    }
+
+    #[test]
+    fn test_should_echo_request_timestamp() {
+        let request = RequestMessage::new("Ping!".to_string());
+        let received_at = clock::monotonic_nanos();
+
+        let message = ResponseMessage::new(&request, received_at, "Pong!".to_string());
+
+        assert_eq!(message.request_sent_at, request.sent_at);
+        assert_eq!(message.received_at, received_at);
+        assert!(message.sent_at >= received_at);
+        assert!(message.round_trip_time() >= message.processing_time());
     }
 }
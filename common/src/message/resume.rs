@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+
+use super::{id::MessageId, MessageType};
+
+/// The maximum number of request IDs a resume message tells the responses of, the most recent ones.
+///
+/// Older responses have most likely left the window of the server, or are skipped by the client if replayed.
+pub const MAX_RESUME_RECEIVED: usize = 1024;
+
+/// Struct representing a Resume Message.
+///
+/// Sent by a reconnecting client on the control stream right after the handshake has been acknowledged,
+/// asking the server to replay the recent responses it may not have received over the previous connection.
+/// The client is identified by the `client_id` of its hello message, and tells the requests it already has
+/// the responses of, so that the server only replays the other ones.
+///
+/// # Fields
+///
+/// * `id` - A globally unique identifier of this message.
+/// * `message_type` - Enum specifying the type of the message.
+/// * `hello_id` - The ID of the hello message opening the resumed session.
+/// * `received` - The IDs of the requests the client already has the responses of, at most `MAX_RESUME_RECEIVED`.
+///
+/// # Serialization
+///
+/// This struct can be serialized and deserialized with Serde.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ResumeMessage {
+    pub id: MessageId,
+    pub message_type: MessageType,
+    pub hello_id: MessageId,
+    pub received: Vec<MessageId>,
+}
+
+/// Struct representing a ResumeAck Message.
+///
+/// Sent by the server in response to a `ResumeMessage`. The announced number of replayed responses
+/// follows on the control stream, each one carrying the `request_id` of the request it answers.
+///
+/// # Fields
+///
+/// * `id` - A globally unique identifier of this message.
+/// * `message_type` - Enum specifying the type of the message.
+/// * `resume_id` - The ID of the resume message this acknowledgement is for.
+/// * `replayed` - The number of responses replayed after this acknowledgement.
+///
+/// # Serialization
+///
+/// This struct can be serialized and deserialized with Serde.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ResumeAckMessage {
+    pub id: MessageId,
+    pub message_type: MessageType,
+    pub resume_id: MessageId,
+    pub replayed: u32,
+}
+
+impl ResumeMessage {
+    /// Constructs a new `ResumeMessage`.
+    ///
+    /// Only the `MAX_RESUME_RECEIVED` last IDs of `received` are kept.
+    ///
+    /// # Parameters
+    ///
+    /// * `hello_id` - The ID of the hello message opening the resumed session.
+    /// * `received` - The IDs of the requests the client already has the responses of, the oldest first.
+    ///
+    /// # Returns
+    ///
+    /// An instance of `ResumeMessage`.
+    pub fn new(hello_id: MessageId, mut received: Vec<MessageId>) -> Self {
+        received.drain(..received.len().saturating_sub(MAX_RESUME_RECEIVED));
+
+        Self {
+            id: MessageId::generate(),
+            message_type: MessageType::Resume,
+            hello_id,
+            received,
+        }
+    }
+
+    /// Constructs the `ResumeAckMessage` for this resume message.
+    ///
+    /// # Parameters
+    ///
+    /// * `replayed` - The number of responses replayed after the acknowledgement.
+    ///
+    /// # Returns
+    ///
+    /// An instance of `ResumeAckMessage`.
+    pub fn acknowledge(&self, replayed: u32) -> ResumeAckMessage {
+        ResumeAckMessage {
+            id: MessageId::generate(),
+            message_type: MessageType::ResumeAck,
+            resume_id: self.id,
+            replayed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_acknowledge_resume() {
+        let hello_id = MessageId::generate();
+        let received = vec![MessageId::generate(), MessageId::generate()];
+        let resume = ResumeMessage::new(hello_id, received.clone());
+
+        let ack = resume.acknowledge(3);
+
+        assert_eq!(resume.message_type, MessageType::Resume);
+        assert_eq!(resume.hello_id, hello_id);
+        assert_eq!(resume.received, received);
+        assert_eq!(ack.message_type, MessageType::ResumeAck);
+        assert_eq!(ack.resume_id, resume.id);
+        assert_eq!(ack.replayed, 3);
+    }
+
+    #[test]
+    fn test_should_keep_most_recent_received_ids() {
+        let received: Vec<_> = (0..MAX_RESUME_RECEIVED + 2)
+            .map(|_| MessageId::generate())
+            .collect();
+
+        let resume = ResumeMessage::new(MessageId::generate(), received.clone());
+
+        assert_eq!(resume.received, received[2..]);
+    }
+}
@@ -0,0 +1,134 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    codec::{decode_value, encode_value, Codec},
+    error::RpcError,
+    message::{request::RequestMessage, Message},
+};
+
+/// The header of a request naming the method it calls.
+pub const METHOD_HEADER: &str = "rpc-method";
+
+/// Builds the request calling a remote method.
+///
+/// The parameters are carried in the payload of the request, serialized with the codec negotiated
+/// for the connection, so that they are as readable as the messages around them.
+///
+/// # Parameters
+///
+/// * `method` - The name of the called method.
+/// * `params` - The parameters of the call.
+/// * `codec` - The codec negotiated for the connection.
+///
+/// # Returns
+///
+/// A `Result` containing the request, or an `RpcError::EncodingFailed` if the parameters cannot be serialized.
+pub fn new_call<P: Serialize>(
+    method: &str,
+    params: &P,
+    codec: &dyn Codec,
+) -> Result<Message, RpcError> {
+    let payload = encode_payload(params, codec)?;
+
+    Ok(Message::new_request(payload).with_header(METHOD_HEADER, method))
+}
+
+/// Gets the name of the method called by a request.
+///
+/// # Parameters
+///
+/// * `request` - The received request.
+///
+/// # Returns
+///
+/// A `Result` containing the name of the method, or an `RpcError::MissingMethod` if the request does not name one.
+pub fn method_of(request: &RequestMessage) -> Result<&str, RpcError> {
+    request
+        .headers
+        .get(METHOD_HEADER)
+        .and_then(|method| method.as_text())
+        .ok_or(RpcError::MissingMethod)
+}
+
+/// Serializes the parameters or the result of a call with the given codec.
+///
+/// # Parameters
+///
+/// * `value` - The value to serialize.
+/// * `codec` - The codec negotiated for the connection.
+///
+/// # Returns
+///
+/// A `Result` containing the payload, or an `RpcError::EncodingFailed` if the value cannot be serialized.
+pub fn encode_payload<T: Serialize>(value: &T, codec: &dyn Codec) -> Result<Vec<u8>, RpcError> {
+    encode_value(value, codec).map_err(|reason| RpcError::EncodingFailed { reason })
+}
+
+/// Deserializes the parameters or the result of a call with the given codec.
+///
+/// # Parameters
+///
+/// * `payload` - The payload of the received message.
+/// * `codec` - The codec negotiated for the connection.
+///
+/// # Returns
+///
+/// A `Result` containing the value, or an `RpcError::DecodingFailed` if the payload does not hold one.
+pub fn decode_payload<T: DeserializeOwned>(
+    payload: &[u8],
+    codec: &dyn Codec,
+) -> Result<T, RpcError> {
+    decode_value(payload, codec).map_err(|reason| RpcError::DecodingFailed { reason })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::codec::{default_codec, supported_codecs};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Add {
+        a: i64,
+        b: i64,
+    }
+
+    #[test]
+    fn test_should_decode_encoded_payload_with_every_codec() {
+        let params = Add { a: 40, b: 2 };
+
+        for codec in supported_codecs() {
+            let payload = encode_payload(&params, *codec).unwrap();
+
+            assert_eq!(
+                decode_payload::<Add>(&payload, *codec),
+                Ok(Add { a: 40, b: 2 })
+            );
+        }
+    }
+
+    #[test]
+    fn test_should_name_called_method() {
+        let call = new_call("add", &Add { a: 1, b: 2 }, default_codec()).unwrap();
+
+        match call {
+            Message::Request(request) => assert_eq!(method_of(&request), Ok("add")),
+            other => panic!("Expected a request, got {:?}", other),
+        }
+        assert_eq!(
+            method_of(&RequestMessage::new("Ping!")),
+            Err(RpcError::MissingMethod)
+        );
+    }
+
+    #[test]
+    fn test_should_reject_payload_of_other_type() {
+        let payload = encode_payload(&1u8, default_codec()).unwrap();
+
+        assert!(matches!(
+            decode_payload::<Add>(&payload, default_codec()),
+            Err(RpcError::DecodingFailed { .. })
+        ));
+    }
+}
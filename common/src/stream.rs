@@ -1,8 +1,11 @@
-use wtransport::{RecvStream, SendStream};
+use bytes::{Buf, BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
 
 use crate::{
+    codec::{codec_by_id, Codec},
     error::{ReadStreamError, WriteStreamError},
-    message::Message,
+    message::{error::ErrorCode, Message},
+    transport::{RecvStream, SendStream},
 };
 
 /// Read an exact number of bytes from a stream.
@@ -23,46 +26,136 @@ use crate::{
 ///
 /// * `Ok` - If the entire buffer is filled.
 /// * `Err` - If an error occurs during reading from the stream.
-pub async fn read_exact(stream: &mut RecvStream, buf: &mut [u8]) -> Result<(), ReadStreamError> {
+pub async fn read_exact<R: RecvStream>(
+    stream: &mut R,
+    buf: &mut [u8],
+) -> Result<(), ReadStreamError> {
     let mut read = 0;
     while read < buf.len() {
         match stream.read(&mut buf[read..]).await {
             Ok(Some(n)) => read += n,
             Ok(None) => return Err(ReadStreamError::StreamStopped),
-            Err(e) => return Err(e.into()),
+            Err(e) => return Err(e),
         }
     }
     Ok(())
 }
 
-/// Reads the next message from a stream.
+/// Reads the next length-prefixed frame from a stream.
 ///
-/// This function reads bytes from the stream and attempts to deserialize them into a `Message`.
+/// This function reads the 8 bytes big-endian length of the frame followed by the frame itself.
+/// The frame content is returned as is, so that the stream stays usable even if the frame cannot be deserialized.
+///
+/// The announced length is checked against `max_frame_size` before anything is allocated.
+/// A frame which is too large is not read, which leaves the stream in the middle of a frame:
+/// the caller has to reset the stream, see `reset_oversized_stream`.
 ///
 /// # Parameters
 ///
-/// * `stream` - A mutable reference to the stream from which the message is to be read.
+/// * `stream` - A mutable reference to the stream from which the frame is to be read.
+/// * `max_frame_size` - The maximum accepted frame length in bytes.
 ///
 /// # Returns
 ///
 /// A `Result` which is:
 ///
-/// * `Ok` - Contains the `Message` read from the stream.
-/// * `Err` - If an error occurs during reading from the stream or deserializing the message.
-pub async fn read_next_message(stream: &mut RecvStream) -> Result<Message, ReadStreamError> {
+/// * `Ok` - Contains the bytes of the frame read from the stream.
+/// * `Err` - If an error occurs during reading from the stream or the frame is too large.
+pub async fn read_next_frame<R: RecvStream>(
+    stream: &mut R,
+    max_frame_size: u64,
+) -> Result<Vec<u8>, ReadStreamError> {
     let mut bytes_to_read_buffer: [u8; 8] = [0; 8];
     read_exact(stream, &mut bytes_to_read_buffer).await?;
 
     let bytes_to_read = u64::from_be_bytes(bytes_to_read_buffer);
 
+    if bytes_to_read > max_frame_size {
+        return Err(ReadStreamError::FrameTooLarge {
+            size: bytes_to_read,
+            max_size: max_frame_size,
+        });
+    }
+
     let mut msg_bytes = vec![0; bytes_to_read as usize];
     read_exact(stream, &mut msg_bytes).await?;
 
-    let message = Message::from_bytes(&msg_bytes)?;
+    Ok(msg_bytes)
+}
+
+/// Reads the next message from a stream.
+///
+/// This function reads bytes from the stream and attempts to deserialize them into a `Message`.
+///
+/// # Parameters
+///
+/// * `stream` - A mutable reference to the stream from which the message is to be read.
+/// * `codec` - The codec the message has been serialized with.
+/// * `max_frame_size` - The maximum accepted size of the serialized message in bytes.
+///
+/// # Returns
+///
+/// A `Result` which is:
+///
+/// * `Ok` - Contains the `Message` read from the stream.
+/// * `Err` - If an error occurs during reading from the stream, the message is too large or cannot be deserialized.
+pub async fn read_next_message<R: RecvStream>(
+    stream: &mut R,
+    codec: &dyn Codec,
+    max_frame_size: u64,
+) -> Result<Message, ReadStreamError> {
+    let msg_bytes = read_next_frame(stream, max_frame_size).await?;
+
+    let message = Message::decode(&msg_bytes, codec)?;
 
     Ok(message)
 }
 
+/// Reads the codec preamble from a stream.
+///
+/// The preamble is a single byte holding the ID of the codec the handshake messages following it are serialized with.
+///
+/// # Parameters
+///
+/// * `stream` - A mutable reference to the stream from which the preamble is to be read.
+///
+/// # Returns
+///
+/// A `Result` which is:
+///
+/// * `Ok` - Contains the codec announced by the peer.
+/// * `Err` - If an error occurs during reading from the stream or the codec is not supported.
+pub async fn read_codec_preamble<R: RecvStream>(
+    stream: &mut R,
+) -> Result<&'static dyn Codec, ReadStreamError> {
+    let mut codec_id: [u8; 1] = [0; 1];
+    read_exact(stream, &mut codec_id).await?;
+
+    codec_by_id(codec_id[0]).ok_or(ReadStreamError::UnsupportedCodec { id: codec_id[0] })
+}
+
+/// Writes the codec preamble to a stream.
+///
+/// # Parameters
+///
+/// * `stream` - A mutable reference to the stream into which the preamble is to be written.
+/// * `codec` - The codec the handshake messages are serialized with.
+///
+/// # Returns
+///
+/// A `Result` which is:
+///
+/// * `Ok` - If the preamble is written.
+/// * `Err` - If an error occurs during writing to the stream.
+pub async fn write_codec_preamble<S: SendStream>(
+    stream: &mut S,
+    codec: &dyn Codec,
+) -> Result<(), WriteStreamError> {
+    stream.write_all(&[codec.id()]).await?;
+
+    Ok(())
+}
+
 /// Writes a message to a stream.
 ///
 /// This function serializes the provided `Message` into bytes and then writes them to the stream.
@@ -71,6 +164,7 @@ pub async fn read_next_message(stream: &mut RecvStream) -> Result<Message, ReadS
 ///
 /// * `stream` - A mutable reference to the stream into which the message is to be written.
 /// * `message` - A reference to the `Message` that is to be written to the stream.
+/// * `codec` - The codec to serialize the message with.
 ///
 /// # Returns
 ///
@@ -78,14 +172,344 @@ pub async fn read_next_message(stream: &mut RecvStream) -> Result<Message, ReadS
 ///
 /// * `Ok` - If the entire message is written.
 /// * `Err` - If an error occurs during writing to the stream or serializing the message.
-pub async fn write_message(
-    stream: &mut SendStream,
+pub async fn write_message<S: SendStream>(
+    stream: &mut S,
     message: &Message,
+    codec: &dyn Codec,
 ) -> Result<(), WriteStreamError> {
-    let msg_bytes = message.as_bytes()?;
+    let msg_bytes = message.encode(codec)?;
 
-    stream.write(&msg_bytes.len().to_be_bytes()).await?;
+    stream
+        .write_all(&(msg_bytes.len() as u64).to_be_bytes())
+        .await?;
     stream.write_all(&msg_bytes).await?;
 
     Ok(())
 }
+
+/// Resets a receiving stream which is left in the middle of an oversized frame.
+///
+/// The peer is asked to stop sending with the `MessageTooLarge` error code, so that the rest of the frame
+/// is discarded instead of being buffered.
+///
+/// # Parameters
+///
+/// * `stream` - The stream the oversized frame has been announced on.
+pub fn reset_oversized_stream<R: RecvStream>(stream: R) {
+    stream.stop(ErrorCode::MessageTooLarge.code().into());
+}
+
+/// The size of the big-endian length prefix of every frame.
+const FRAME_LENGTH_SIZE: usize = 8;
+
+/// A `tokio_util` codec for the length-prefixed message frames.
+///
+/// It produces and accepts the same bytes as `write_message` and `read_next_message`, so that messages can be
+/// exchanged over any `AsyncRead`/`AsyncWrite`, e.g. a TCP stream or a capture file, with `FramedRead`,
+/// `FramedWrite` or `Framed`.
+///
+/// # Fields
+///
+/// * `codec` - The codec the messages are serialized with.
+/// * `max_frame_size` - The maximum accepted frame length in bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct MessageFrameCodec {
+    codec: &'static dyn Codec,
+    max_frame_size: u64,
+}
+
+impl MessageFrameCodec {
+    /// Constructs a new `MessageFrameCodec`.
+    ///
+    /// # Parameters
+    ///
+    /// * `codec` - The codec the messages are serialized with.
+    /// * `max_frame_size` - The maximum accepted frame length in bytes.
+    ///
+    /// # Returns
+    ///
+    /// An instance of `MessageFrameCodec`.
+    pub fn new(codec: &'static dyn Codec, max_frame_size: u64) -> Self {
+        Self {
+            codec,
+            max_frame_size,
+        }
+    }
+}
+
+impl Decoder for MessageFrameCodec {
+    type Item = Message;
+    type Error = ReadStreamError;
+
+    /// Decodes the next message once its whole frame is buffered.
+    ///
+    /// The announced length is checked against the maximum frame size as soon as the length prefix is buffered,
+    /// so that no memory is reserved for an oversized frame.
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Message>, ReadStreamError> {
+        if src.len() < FRAME_LENGTH_SIZE {
+            return Ok(None);
+        }
+
+        let mut length_bytes = [0; FRAME_LENGTH_SIZE];
+        length_bytes.copy_from_slice(&src[..FRAME_LENGTH_SIZE]);
+        let frame_size = u64::from_be_bytes(length_bytes);
+
+        if frame_size > self.max_frame_size {
+            return Err(ReadStreamError::FrameTooLarge {
+                size: frame_size,
+                max_size: self.max_frame_size,
+            });
+        }
+
+        // Cannot overflow as the frame size is bounded by the maximum frame size
+        let frame_end = FRAME_LENGTH_SIZE + frame_size as usize;
+
+        if src.len() < frame_end {
+            src.reserve(frame_end - src.len());
+            return Ok(None);
+        }
+
+        src.advance(FRAME_LENGTH_SIZE);
+        let frame = src.split_to(frame_size as usize);
+
+        Ok(Some(Message::decode(&frame, self.codec)?))
+    }
+
+    /// Decodes the last message, failing if the source ends in the middle of a frame.
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Message>, ReadStreamError> {
+        match self.decode(src)? {
+            Some(message) => Ok(Some(message)),
+            None if src.is_empty() => Ok(None),
+            None => Err(ReadStreamError::StreamStopped),
+        }
+    }
+}
+
+impl Encoder<&Message> for MessageFrameCodec {
+    type Error = WriteStreamError;
+
+    fn encode(&mut self, message: &Message, dst: &mut BytesMut) -> Result<(), WriteStreamError> {
+        let msg_bytes = message.encode(self.codec)?;
+
+        dst.reserve(FRAME_LENGTH_SIZE + msg_bytes.len());
+        dst.put_u64(msg_bytes.len() as u64);
+        dst.put_slice(&msg_bytes);
+
+        Ok(())
+    }
+}
+
+impl Encoder<Message> for MessageFrameCodec {
+    type Error = WriteStreamError;
+
+    fn encode(&mut self, message: Message, dst: &mut BytesMut) -> Result<(), WriteStreamError> {
+        self.encode(&message, dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use futures::{SinkExt, StreamExt};
+    use tokio_util::codec::{FramedRead, FramedWrite};
+
+    use super::*;
+    use crate::codec::default_codec;
+
+    /// A sending stream collecting the written bytes.
+    #[derive(Default)]
+    struct BufferSendStream(Vec<u8>);
+
+    #[async_trait]
+    impl SendStream for BufferSendStream {
+        async fn write_all(&mut self, buf: &[u8]) -> Result<(), WriteStreamError> {
+            self.0.extend_from_slice(buf);
+            Ok(())
+        }
+
+        async fn finish(&mut self) -> Result<(), WriteStreamError> {
+            Ok(())
+        }
+
+        fn reset(self, _code: u32) {}
+    }
+
+    /// A receiving stream handing out the given bytes in small chunks, as a network would.
+    struct ChunkedRecvStream {
+        bytes: Vec<u8>,
+        position: usize,
+    }
+
+    impl ChunkedRecvStream {
+        const CHUNK_SIZE: usize = 3;
+
+        fn new(bytes: Vec<u8>) -> Self {
+            Self { bytes, position: 0 }
+        }
+    }
+
+    #[async_trait]
+    impl RecvStream for ChunkedRecvStream {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, ReadStreamError> {
+            if self.position == self.bytes.len() {
+                return Ok(None);
+            }
+
+            let end = (self.position + Self::CHUNK_SIZE)
+                .min(self.bytes.len())
+                .min(self.position + buf.len());
+            let chunk = &self.bytes[self.position..end];
+
+            buf[..chunk.len()].copy_from_slice(chunk);
+            self.position = end;
+
+            Ok(Some(chunk.len()))
+        }
+
+        fn stop(self, _code: u32) {}
+    }
+
+    #[tokio::test]
+    async fn test_should_write_and_read_message() {
+        let message = Message::new_request("Ping!");
+
+        let mut send_stream = BufferSendStream::default();
+        write_codec_preamble(&mut send_stream, default_codec())
+            .await
+            .unwrap();
+        write_message(&mut send_stream, &message, default_codec())
+            .await
+            .unwrap();
+
+        let mut recv_stream = ChunkedRecvStream::new(send_stream.0);
+        let codec = read_codec_preamble(&mut recv_stream).await.unwrap();
+        let received = read_next_message(&mut recv_stream, codec, 1024)
+            .await
+            .unwrap();
+
+        assert_eq!(codec.id(), default_codec().id());
+        assert_eq!(received, message);
+    }
+
+    #[tokio::test]
+    async fn test_should_reject_frame_too_large_before_reading_it() {
+        let mut bytes = u64::MAX.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&[0; 16]);
+
+        let mut recv_stream = ChunkedRecvStream::new(bytes);
+
+        assert_eq!(
+            read_next_frame(&mut recv_stream, 1024).await,
+            Err(ReadStreamError::FrameTooLarge {
+                size: u64::MAX,
+                max_size: 1024
+            })
+        );
+        assert_eq!(recv_stream.position, 8);
+    }
+
+    #[tokio::test]
+    async fn test_should_return_error_for_truncated_frame() {
+        let mut bytes = 16u64.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&[0; 4]);
+
+        let mut recv_stream = ChunkedRecvStream::new(bytes);
+
+        assert_eq!(
+            read_next_frame(&mut recv_stream, 1024).await,
+            Err(ReadStreamError::StreamStopped)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_should_return_error_for_unsupported_codec() {
+        let mut recv_stream = ChunkedRecvStream::new(vec![255]);
+
+        assert_eq!(
+            read_codec_preamble(&mut recv_stream).await.err(),
+            Some(ReadStreamError::UnsupportedCodec { id: 255 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_frame_codec_should_match_write_message() {
+        let message = Message::new_request("Ping!");
+
+        let mut send_stream = BufferSendStream::default();
+        write_message(&mut send_stream, &message, default_codec())
+            .await
+            .unwrap();
+
+        let mut encoded = BytesMut::new();
+        MessageFrameCodec::new(default_codec(), 1024)
+            .encode(&message, &mut encoded)
+            .unwrap();
+
+        assert_eq!(&encoded[..], &send_stream.0[..]);
+    }
+
+    #[tokio::test]
+    async fn test_frame_codec_should_write_and_read_messages() {
+        let messages = vec![
+            Message::new_request("Ping!"),
+            Message::new_request(vec![0; 512]),
+        ];
+
+        let mut sink = FramedWrite::new(Vec::new(), MessageFrameCodec::new(default_codec(), 1024));
+        for message in &messages {
+            sink.send(message).await.unwrap();
+        }
+
+        let bytes = sink.into_inner();
+        let stream = FramedRead::new(&bytes[..], MessageFrameCodec::new(default_codec(), 1024));
+        let received: Vec<Message> = stream.map(Result::unwrap).collect().await;
+
+        assert_eq!(received, messages);
+    }
+
+    #[test]
+    fn test_frame_codec_should_wait_for_partial_frames() {
+        let message = Message::new_request("Ping!");
+        let mut codec = MessageFrameCodec::new(default_codec(), 1024);
+
+        let mut encoded = BytesMut::new();
+        codec.encode(&message, &mut encoded).unwrap();
+
+        let mut src = BytesMut::new();
+        for byte in &encoded[..encoded.len() - 1] {
+            src.put_u8(*byte);
+            assert_eq!(codec.decode(&mut src).unwrap(), None);
+        }
+
+        src.put_u8(encoded[encoded.len() - 1]);
+        assert_eq!(codec.decode(&mut src).unwrap(), Some(message));
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn test_frame_codec_should_reject_frame_too_large() {
+        let mut codec = MessageFrameCodec::new(default_codec(), 1024);
+        let mut src = BytesMut::from(&u64::MAX.to_be_bytes()[..]);
+
+        assert_eq!(
+            codec.decode(&mut src),
+            Err(ReadStreamError::FrameTooLarge {
+                size: u64::MAX,
+                max_size: 1024
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_frame_codec_should_return_error_for_truncated_source() {
+        let mut bytes = 16u64.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&[0; 4]);
+
+        let mut stream = FramedRead::new(&bytes[..], MessageFrameCodec::new(default_codec(), 1024));
+
+        assert_eq!(
+            stream.next().await,
+            Some(Err(ReadStreamError::StreamStopped))
+        );
+    }
+}
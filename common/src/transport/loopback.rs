@@ -0,0 +1,577 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokio::{
+    sync::{
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        Mutex,
+    },
+    time::{self, Instant},
+};
+
+use crate::error::{ConnectionError, DatagramError, ReadStreamError, WriteStreamError};
+
+/// The address both ends of an in-process connection report for their peer, as they are not bound to any port.
+pub const LOOPBACK_ADDRESS: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0));
+
+/// Conditions applied to the datagrams sent over a loopback connection.
+///
+/// Streams are always reliable and ordered, as they are over QUIC.
+/// The random decisions are taken with a generator seeded with `seed`, so that a test sees the same
+/// losses and reorderings on every run.
+///
+/// # Fields
+///
+/// * `latency` - The delay after which a datagram is delivered.
+/// * `loss_probability` - The probability, between 0 and 1, of a datagram being dropped.
+/// * `reorder_probability` - The probability, between 0 and 1, of a datagram being delayed by `reorder_delay`
+///   on top of `latency`, so that the datagrams sent right after it overtake it.
+/// * `reorder_delay` - The additional delay of the reordered datagrams.
+/// * `seed` - The seed of the random generator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatagramConditions {
+    pub latency: Duration,
+    pub loss_probability: f64,
+    pub reorder_probability: f64,
+    pub reorder_delay: Duration,
+    pub seed: u64,
+}
+
+impl Default for DatagramConditions {
+    /// Creates the conditions of a perfect channel: no latency, no loss and no reordering.
+    fn default() -> Self {
+        Self {
+            latency: Duration::ZERO,
+            loss_probability: 0.0,
+            reorder_probability: 0.0,
+            reorder_delay: Duration::from_millis(10),
+            seed: 0,
+        }
+    }
+}
+
+/// Events carried from the sending half of a loopback stream to its receiving half.
+enum StreamEvent {
+    Data(Vec<u8>),
+    Finish,
+    Reset,
+}
+
+/// The sending half of a loopback stream.
+pub struct LoopbackSendStream {
+    events: UnboundedSender<StreamEvent>,
+    stopped: Arc<AtomicBool>,
+}
+
+/// The receiving half of a loopback stream.
+pub struct LoopbackRecvStream {
+    events: UnboundedReceiver<StreamEvent>,
+    stopped: Arc<AtomicBool>,
+    pending: Vec<u8>,
+    finished: bool,
+}
+
+/// Creates the two halves of a loopback stream.
+fn stream() -> (LoopbackSendStream, LoopbackRecvStream) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let stopped = Arc::new(AtomicBool::new(false));
+
+    (
+        LoopbackSendStream {
+            events: sender,
+            stopped: stopped.clone(),
+        },
+        LoopbackRecvStream {
+            events: receiver,
+            stopped,
+            pending: Vec::new(),
+            finished: false,
+        },
+    )
+}
+
+impl LoopbackSendStream {
+    /// Sends an event to the receiving half.
+    ///
+    /// The receiving half is gone either because it has been stopped, or because the peer has dropped it
+    /// along with its connection.
+    fn send(&self, event: StreamEvent) -> Result<(), WriteStreamError> {
+        self.events.send(event).map_err(|_| {
+            if self.stopped.load(Ordering::SeqCst) {
+                WriteStreamError::StreamStopped
+            } else {
+                WriteStreamError::ConnectionClosed
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl super::SendStream for LoopbackSendStream {
+    async fn write_all(&mut self, buf: &[u8]) -> Result<(), WriteStreamError> {
+        self.send(StreamEvent::Data(buf.to_vec()))
+    }
+
+    async fn finish(&mut self) -> Result<(), WriteStreamError> {
+        self.send(StreamEvent::Finish)
+    }
+
+    fn reset(self, _code: u32) {
+        self.send(StreamEvent::Reset).ok();
+    }
+}
+
+#[async_trait]
+impl super::RecvStream for LoopbackRecvStream {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, ReadStreamError> {
+        while self.pending.is_empty() {
+            if self.finished {
+                return Ok(None);
+            }
+
+            match self.events.recv().await {
+                Some(StreamEvent::Data(data)) => self.pending = data,
+                Some(StreamEvent::Finish) => self.finished = true,
+                Some(StreamEvent::Reset) => return Err(ReadStreamError::StreamStopped),
+                None => return Err(ReadStreamError::ConnectionClosed),
+            }
+        }
+
+        let read = buf.len().min(self.pending.len());
+        buf[..read].copy_from_slice(&self.pending[..read]);
+        self.pending.drain(..read);
+
+        Ok(Some(read))
+    }
+
+    fn stop(self, _code: u32) {
+        // Dropping the receiver makes every further write of the peer fail
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+/// A datagram waiting for its delivery time.
+///
+/// Datagrams due at the same time are delivered in the order they have been sent.
+type ScheduledDatagram = Reverse<(Instant, u64, Vec<u8>)>;
+
+/// The receiving side of the datagram channel.
+struct DatagramInbox {
+    datagrams: UnboundedReceiver<ScheduledDatagram>,
+    scheduled: BinaryHeap<ScheduledDatagram>,
+}
+
+/// The sending side of the datagram channel.
+struct DatagramOutbox {
+    rng: StdRng,
+    sequence: u64,
+}
+
+/// One end of an in-process connection, created with `loopback_pair`.
+///
+/// Streams opened on one end are accepted on the other one, datagrams sent from one end are received
+/// on the other one under the `DatagramConditions` of the pair. Dropping one end closes the connection
+/// for the other one.
+pub struct LoopbackConnection {
+    conditions: DatagramConditions,
+    bi_streams_to_peer: UnboundedSender<(LoopbackSendStream, LoopbackRecvStream)>,
+    bi_streams_from_peer: Mutex<UnboundedReceiver<(LoopbackSendStream, LoopbackRecvStream)>>,
+    uni_streams_to_peer: UnboundedSender<LoopbackRecvStream>,
+    uni_streams_from_peer: Mutex<UnboundedReceiver<LoopbackRecvStream>>,
+    datagrams_to_peer: UnboundedSender<ScheduledDatagram>,
+    outbox: std::sync::Mutex<DatagramOutbox>,
+    inbox: Mutex<DatagramInbox>,
+}
+
+/// Creates both ends of an in-process connection.
+///
+/// # Parameters
+///
+/// * `conditions` - The conditions applied to the datagrams sent in both directions.
+///
+/// # Returns
+///
+/// The two ends of the connection, e.g. the client one and the server one.
+///
+/// # Panics
+///
+/// Panics if `loss_probability` or `reorder_probability` is not between 0 and 1.
+pub fn loopback_pair(conditions: DatagramConditions) -> (LoopbackConnection, LoopbackConnection) {
+    for (name, probability) in [
+        ("loss_probability", conditions.loss_probability),
+        ("reorder_probability", conditions.reorder_probability),
+    ] {
+        assert!(
+            (0.0..=1.0).contains(&probability),
+            "{} must be between 0 and 1, got {}",
+            name,
+            probability
+        );
+    }
+
+    let (first_bi, second_bi_from_peer) = mpsc::unbounded_channel();
+    let (second_bi, first_bi_from_peer) = mpsc::unbounded_channel();
+    let (first_uni, second_uni_from_peer) = mpsc::unbounded_channel();
+    let (second_uni, first_uni_from_peer) = mpsc::unbounded_channel();
+    let (first_datagrams, second_datagrams_from_peer) = mpsc::unbounded_channel();
+    let (second_datagrams, first_datagrams_from_peer) = mpsc::unbounded_channel();
+
+    // Each direction gets its own generator, so that the traffic in one direction does not
+    // change the fate of the datagrams sent in the other one
+    let first = LoopbackConnection::new(
+        conditions.clone(),
+        conditions.seed,
+        (first_bi, first_bi_from_peer),
+        (first_uni, first_uni_from_peer),
+        (first_datagrams, first_datagrams_from_peer),
+    );
+    let second = LoopbackConnection::new(
+        conditions.clone(),
+        conditions.seed.wrapping_add(1),
+        (second_bi, second_bi_from_peer),
+        (second_uni, second_uni_from_peer),
+        (second_datagrams, second_datagrams_from_peer),
+    );
+
+    (first, second)
+}
+
+type Channel<T> = (UnboundedSender<T>, UnboundedReceiver<T>);
+
+impl LoopbackConnection {
+    fn new(
+        conditions: DatagramConditions,
+        seed: u64,
+        bi_streams: Channel<(LoopbackSendStream, LoopbackRecvStream)>,
+        uni_streams: Channel<LoopbackRecvStream>,
+        datagrams: Channel<ScheduledDatagram>,
+    ) -> Self {
+        Self {
+            conditions,
+            bi_streams_to_peer: bi_streams.0,
+            bi_streams_from_peer: Mutex::new(bi_streams.1),
+            uni_streams_to_peer: uni_streams.0,
+            uni_streams_from_peer: Mutex::new(uni_streams.1),
+            datagrams_to_peer: datagrams.0,
+            outbox: std::sync::Mutex::new(DatagramOutbox {
+                rng: StdRng::seed_from_u64(seed),
+                sequence: 0,
+            }),
+            inbox: Mutex::new(DatagramInbox {
+                datagrams: datagrams.1,
+                scheduled: BinaryHeap::new(),
+            }),
+        }
+    }
+}
+
+/// The error returned once the peer end of the connection has been dropped.
+fn closed_by_peer() -> ConnectionError {
+    ConnectionError::ClosedByPeer {
+        code: 0,
+        reason: Vec::new(),
+    }
+}
+
+#[async_trait]
+impl super::Connection for LoopbackConnection {
+    type SendStream = LoopbackSendStream;
+    type RecvStream = LoopbackRecvStream;
+
+    async fn open_bi(&self) -> Result<(Self::SendStream, Self::RecvStream), ConnectionError> {
+        let (local_send, peer_recv) = stream();
+        let (peer_send, local_recv) = stream();
+
+        self.bi_streams_to_peer
+            .send((peer_send, peer_recv))
+            .map_err(|_| closed_by_peer())?;
+
+        Ok((local_send, local_recv))
+    }
+
+    async fn accept_bi(&self) -> Result<(Self::SendStream, Self::RecvStream), ConnectionError> {
+        self.bi_streams_from_peer
+            .lock()
+            .await
+            .recv()
+            .await
+            .ok_or_else(closed_by_peer)
+    }
+
+    async fn open_uni(&self) -> Result<Self::SendStream, ConnectionError> {
+        let (local_send, peer_recv) = stream();
+
+        self.uni_streams_to_peer
+            .send(peer_recv)
+            .map_err(|_| closed_by_peer())?;
+
+        Ok(local_send)
+    }
+
+    async fn accept_uni(&self) -> Result<Self::RecvStream, ConnectionError> {
+        self.uni_streams_from_peer
+            .lock()
+            .await
+            .recv()
+            .await
+            .ok_or_else(closed_by_peer)
+    }
+
+    fn send_datagram(&self, payload: &[u8]) -> Result<(), DatagramError> {
+        let mut outbox = self.outbox.lock().expect("datagram outbox lock poisoned");
+
+        outbox.sequence += 1;
+
+        if outbox.rng.gen_bool(self.conditions.loss_probability) {
+            return Ok(());
+        }
+
+        let mut delay = self.conditions.latency;
+        if outbox.rng.gen_bool(self.conditions.reorder_probability) {
+            delay += self.conditions.reorder_delay;
+        }
+
+        self.datagrams_to_peer
+            .send(Reverse((
+                Instant::now() + delay,
+                outbox.sequence,
+                payload.to_vec(),
+            )))
+            .map_err(|_| DatagramError::ConnectionClosed)
+    }
+
+    async fn receive_datagram(&self) -> Result<Vec<u8>, DatagramError> {
+        let mut inbox = self.inbox.lock().await;
+        let DatagramInbox {
+            datagrams,
+            scheduled,
+        } = &mut *inbox;
+
+        loop {
+            while let Ok(datagram) = datagrams.try_recv() {
+                scheduled.push(datagram);
+            }
+
+            let deliver_at = match scheduled.peek() {
+                Some(Reverse((deliver_at, _, _))) => *deliver_at,
+                None => match datagrams.recv().await {
+                    Some(datagram) => {
+                        scheduled.push(datagram);
+                        continue;
+                    }
+                    None => return Err(DatagramError::ConnectionClosed),
+                },
+            };
+
+            if deliver_at <= Instant::now() {
+                let Reverse((_, _, payload)) = scheduled.pop().expect("peeked datagram is gone");
+
+                return Ok(payload);
+            }
+
+            // A datagram sent later with a shorter delay might be due first
+            tokio::select! {
+                _ = time::sleep_until(deliver_at) => {}
+                datagram = datagrams.recv() => {
+                    if let Some(datagram) = datagram {
+                        scheduled.push(datagram);
+                    }
+                }
+            }
+        }
+    }
+
+    fn remote_address(&self) -> SocketAddr {
+        LOOPBACK_ADDRESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{Connection, RecvStream, SendStream};
+
+    async fn read_to_end(stream: &mut LoopbackRecvStream) -> Result<Vec<u8>, ReadStreamError> {
+        let mut bytes = Vec::new();
+        let mut buf = [0; 4];
+
+        while let Some(read) = stream.read(&mut buf).await? {
+            bytes.extend_from_slice(&buf[..read]);
+        }
+
+        Ok(bytes)
+    }
+
+    #[tokio::test]
+    async fn test_should_carry_bidirectional_streams() {
+        let (client, server) = loopback_pair(DatagramConditions::default());
+
+        let (mut client_send, mut client_recv) = client.open_bi().await.unwrap();
+        let (mut server_send, mut server_recv) = server.accept_bi().await.unwrap();
+
+        client_send.write_all(b"Ping!").await.unwrap();
+        client_send.write_all(b" Ping!").await.unwrap();
+        client_send.finish().await.unwrap();
+
+        server_send.write_all(b"Pong!").await.unwrap();
+        server_send.finish().await.unwrap();
+
+        assert_eq!(read_to_end(&mut server_recv).await.unwrap(), b"Ping! Ping!");
+        assert_eq!(read_to_end(&mut client_recv).await.unwrap(), b"Pong!");
+    }
+
+    #[tokio::test]
+    async fn test_should_carry_unidirectional_streams() {
+        let (client, server) = loopback_pair(DatagramConditions::default());
+
+        let mut client_send = client.open_uni().await.unwrap();
+        let mut server_recv = server.accept_uni().await.unwrap();
+
+        client_send.write_all(b"Ping!").await.unwrap();
+        client_send.finish().await.unwrap();
+
+        assert_eq!(read_to_end(&mut server_recv).await.unwrap(), b"Ping!");
+    }
+
+    #[tokio::test]
+    async fn test_should_propagate_reset_and_stop() {
+        let (client, server) = loopback_pair(DatagramConditions::default());
+
+        let (client_send, client_recv) = client.open_bi().await.unwrap();
+        let (mut server_send, mut server_recv) = server.accept_bi().await.unwrap();
+
+        client_send.reset(0);
+        assert_eq!(
+            read_to_end(&mut server_recv).await,
+            Err(ReadStreamError::StreamStopped)
+        );
+
+        client_recv.stop(0);
+        assert_eq!(
+            server_send.write_all(b"Pong!").await,
+            Err(WriteStreamError::StreamStopped)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_should_close_streams_when_peer_is_dropped() {
+        let (client, server) = loopback_pair(DatagramConditions::default());
+
+        let (mut client_send, mut client_recv) = client.open_bi().await.unwrap();
+        let streams = server.accept_bi().await.unwrap();
+
+        drop(streams);
+
+        assert_eq!(
+            client_send.write_all(b"Ping!").await,
+            Err(WriteStreamError::ConnectionClosed)
+        );
+        assert_eq!(
+            read_to_end(&mut client_recv).await,
+            Err(ReadStreamError::ConnectionClosed)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_should_close_connection_when_peer_is_dropped() {
+        let (client, server) = loopback_pair(DatagramConditions::default());
+
+        drop(server);
+
+        assert_eq!(client.open_bi().await.err(), Some(closed_by_peer()));
+        assert_eq!(client.accept_uni().await.err(), Some(closed_by_peer()));
+        assert_eq!(
+            client.receive_datagram().await,
+            Err(DatagramError::ConnectionClosed)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_delay_datagrams_by_latency() {
+        let (client, server) = loopback_pair(DatagramConditions {
+            latency: Duration::from_millis(50),
+            ..Default::default()
+        });
+
+        let sent_at = Instant::now();
+        client.send_datagram(b"Ping!").unwrap();
+
+        assert_eq!(server.receive_datagram().await.unwrap(), b"Ping!");
+        assert_eq!(sent_at.elapsed(), Duration::from_millis(50));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_drop_lost_datagrams() {
+        let (client, server) = loopback_pair(DatagramConditions {
+            loss_probability: 1.0,
+            ..Default::default()
+        });
+
+        client.send_datagram(b"Ping!").unwrap();
+
+        let received = time::timeout(Duration::from_secs(1), server.receive_datagram()).await;
+
+        assert!(received.is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "loss_probability must be between 0 and 1")]
+    fn test_should_reject_invalid_loss_probability() {
+        loopback_pair(DatagramConditions {
+            loss_probability: f64::NAN,
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "reorder_probability must be between 0 and 1")]
+    fn test_should_reject_invalid_reorder_probability() {
+        loopback_pair(DatagramConditions {
+            reorder_probability: 1.5,
+            ..Default::default()
+        });
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_reorder_datagrams_deterministically() {
+        let conditions = DatagramConditions {
+            reorder_probability: 0.5,
+            seed: 42,
+            ..Default::default()
+        };
+
+        let mut orders = Vec::new();
+
+        for _ in 0..2 {
+            let (client, server) = loopback_pair(conditions.clone());
+
+            for sequence in 0..20u8 {
+                client.send_datagram(&[sequence]).unwrap();
+            }
+
+            let mut order = Vec::new();
+            for _ in 0..20 {
+                order.push(server.receive_datagram().await.unwrap()[0]);
+            }
+
+            orders.push(order);
+        }
+
+        let mut sorted = orders[0].clone();
+        sorted.sort();
+
+        assert_ne!(orders[0], sorted);
+        assert_eq!(sorted, (0..20).collect::<Vec<u8>>());
+        assert_eq!(orders[0], orders[1]);
+    }
+}
@@ -0,0 +1,158 @@
+use std::net::SocketAddr;
+
+use async_trait::async_trait;
+
+use crate::error::{ConnectionError, DatagramError, ReadStreamError, WriteStreamError};
+
+pub mod loopback;
+pub mod webtransport;
+
+/// The sending half of a stream.
+///
+/// Streams carry raw bytes, the framing of messages is done on top of them by the `stream` module.
+#[async_trait]
+pub trait SendStream: Send {
+    /// Writes the whole buffer into the stream.
+    ///
+    /// # Parameters
+    ///
+    /// * `buf` - The bytes to write.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is:
+    ///
+    /// * `Ok` - If all the bytes are written.
+    /// * `Err` - Contains a `WriteStreamError` if the stream or the connection has been closed.
+    async fn write_all(&mut self, buf: &[u8]) -> Result<(), WriteStreamError>;
+
+    /// Gracefully closes the stream, waiting until all the written bytes are acknowledged by the peer.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is:
+    ///
+    /// * `Ok` - If the stream is finished.
+    /// * `Err` - Contains a `WriteStreamError` if the stream has been stopped by the peer or the connection has been closed.
+    async fn finish(&mut self) -> Result<(), WriteStreamError>;
+
+    /// Abruptly closes the stream, discarding all the bytes not delivered yet.
+    ///
+    /// # Parameters
+    ///
+    /// * `code` - The application error code sent to the peer.
+    fn reset(self, code: u32)
+    where
+        Self: Sized;
+}
+
+/// The receiving half of a stream.
+#[async_trait]
+pub trait RecvStream: Send {
+    /// Reads bytes from the stream into the buffer.
+    ///
+    /// # Parameters
+    ///
+    /// * `buf` - The buffer to read into.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is:
+    ///
+    /// * `Ok` - Contains the number of bytes read, or `None` if the peer has finished the stream.
+    /// * `Err` - Contains a `ReadStreamError` if the stream has been reset by the peer or the connection has been closed.
+    async fn read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, ReadStreamError>;
+
+    /// Asks the peer to stop sending on the stream, discarding all the bytes not read yet.
+    ///
+    /// # Parameters
+    ///
+    /// * `code` - The application error code sent to the peer.
+    fn stop(self, code: u32)
+    where
+        Self: Sized;
+}
+
+/// A connection between two peers, multiplexing streams and datagrams.
+///
+/// The client and server handlers are generic over this trait, so that they can run over
+/// WebTransport as well as over any other transport implementing it.
+#[async_trait]
+pub trait Connection: Send + Sync {
+    /// The sending half of the streams of the connection.
+    type SendStream: SendStream;
+
+    /// The receiving half of the streams of the connection.
+    type RecvStream: RecvStream;
+
+    /// Opens a bidirectional stream.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is:
+    ///
+    /// * `Ok` - Contains the sending and receiving halves of the stream.
+    /// * `Err` - Contains a `ConnectionError` if the connection has been closed.
+    async fn open_bi(&self) -> Result<(Self::SendStream, Self::RecvStream), ConnectionError>;
+
+    /// Waits for the peer to open a bidirectional stream.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is:
+    ///
+    /// * `Ok` - Contains the sending and receiving halves of the stream.
+    /// * `Err` - Contains a `ConnectionError` if the connection has been closed.
+    async fn accept_bi(&self) -> Result<(Self::SendStream, Self::RecvStream), ConnectionError>;
+
+    /// Opens a unidirectional stream.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is:
+    ///
+    /// * `Ok` - Contains the sending stream.
+    /// * `Err` - Contains a `ConnectionError` if the connection has been closed.
+    async fn open_uni(&self) -> Result<Self::SendStream, ConnectionError>;
+
+    /// Waits for the peer to open a unidirectional stream.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is:
+    ///
+    /// * `Ok` - Contains the receiving stream.
+    /// * `Err` - Contains a `ConnectionError` if the connection has been closed.
+    async fn accept_uni(&self) -> Result<Self::RecvStream, ConnectionError>;
+
+    /// Sends a datagram. Datagrams are unreliable, they might be lost or reordered.
+    ///
+    /// # Parameters
+    ///
+    /// * `payload` - The content of the datagram.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is:
+    ///
+    /// * `Ok` - If the datagram is queued for sending.
+    /// * `Err` - Contains a `DatagramError` if datagrams are not supported or the connection has been closed.
+    fn send_datagram(&self, payload: &[u8]) -> Result<(), DatagramError>;
+
+    /// Waits for the next datagram.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is:
+    ///
+    /// * `Ok` - Contains the content of the datagram.
+    /// * `Err` - Contains a `DatagramError` if datagrams are not supported or the connection has been closed.
+    async fn receive_datagram(&self) -> Result<Vec<u8>, DatagramError>;
+
+    /// Gets the address of the peer.
+    ///
+    /// # Returns
+    ///
+    /// The address the peer is connected from.
+    fn remote_address(&self) -> SocketAddr;
+}
@@ -0,0 +1,70 @@
+use std::net::SocketAddr;
+
+use async_trait::async_trait;
+use wtransport::VarInt;
+
+use crate::error::{ConnectionError, DatagramError, ReadStreamError, WriteStreamError};
+
+#[async_trait]
+impl super::SendStream for wtransport::SendStream {
+    async fn write_all(&mut self, buf: &[u8]) -> Result<(), WriteStreamError> {
+        Ok(wtransport::SendStream::write_all(self, buf).await?)
+    }
+
+    async fn finish(&mut self) -> Result<(), WriteStreamError> {
+        Ok(wtransport::SendStream::finish(self).await?)
+    }
+
+    fn reset(self, code: u32) {
+        // The stream might already be closed by the peer, there is nothing left to reset then
+        wtransport::SendStream::reset(self, VarInt::from_u32(code)).ok();
+    }
+}
+
+#[async_trait]
+impl super::RecvStream for wtransport::RecvStream {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, ReadStreamError> {
+        Ok(wtransport::RecvStream::read(self, buf).await?)
+    }
+
+    fn stop(self, code: u32) {
+        // The stream might already be finished by the peer, there is nothing left to stop then
+        wtransport::RecvStream::stop(self, VarInt::from_u32(code)).ok();
+    }
+}
+
+#[async_trait]
+impl super::Connection for wtransport::Connection {
+    type SendStream = wtransport::SendStream;
+    type RecvStream = wtransport::RecvStream;
+
+    async fn open_bi(&self) -> Result<(Self::SendStream, Self::RecvStream), ConnectionError> {
+        Ok(wtransport::Connection::open_bi(self).await?)
+    }
+
+    async fn accept_bi(&self) -> Result<(Self::SendStream, Self::RecvStream), ConnectionError> {
+        Ok(wtransport::Connection::accept_bi(self).await?)
+    }
+
+    async fn open_uni(&self) -> Result<Self::SendStream, ConnectionError> {
+        Ok(wtransport::Connection::open_uni(self).await?)
+    }
+
+    async fn accept_uni(&self) -> Result<Self::RecvStream, ConnectionError> {
+        Ok(wtransport::Connection::accept_uni(self).await?)
+    }
+
+    fn send_datagram(&self, payload: &[u8]) -> Result<(), DatagramError> {
+        Ok(wtransport::Connection::send_datagram(self, payload)?)
+    }
+
+    async fn receive_datagram(&self) -> Result<Vec<u8>, DatagramError> {
+        let datagram = wtransport::Connection::receive_datagram(self).await?;
+
+        Ok(datagram.to_vec())
+    }
+
+    fn remote_address(&self) -> SocketAddr {
+        wtransport::Connection::remote_address(self)
+    }
+}
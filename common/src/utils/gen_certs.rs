@@ -13,18 +13,35 @@ use std::io::Write;
 use time::Duration;
 use time::OffsetDateTime;
 
+/// The longest validity period of a certificate which can be pinned by its hash.
+///
+/// Clients verifying the server with WebTransport `serverCertificateHashes` semantics reject certificates
+/// valid for longer.
+pub const MAX_PINNABLE_VALIDITY: Duration = Duration::days(14);
+
+/// How long before its generation the certificate is valid, tolerating clocks running behind.
+const CLOCK_SKEW_ALLOWANCE: Duration = Duration::hours(1);
+
 /// Generates a certificate and private key for use in tests.
 /// The code copied from the original `wttransport` crate repository.
 ///
+/// The certificate is self-signed with an ECDSA P-256 key. It can be pinned by its hash as long as
+/// `validity` does not exceed `MAX_PINNABLE_VALIDITY`.
+///
 /// # Arguments
 ///
 /// * `cert_path` - The path to the certificate file.
 /// * `key_path` - The path to the certificate key file.
+/// * `validity` - How long the certificate is valid, starting shortly before its generation.
 ///
 /// # Returns
 ///
-/// * `Result<(), Box<dyn Error>>` - The result of generating the certificate.
-pub fn gen_certs(cert_path: String, key_path: String) -> Result<(), Box<dyn Error>> {
+/// * `Result<String, Box<dyn Error>>` - The base64 encoded SHA-256 hash of the DER encoded certificate.
+pub fn gen_certs(
+    cert_path: String,
+    key_path: String,
+    validity: Duration,
+) -> Result<String, Box<dyn Error>> {
     const COMMON_NAME: &str = "localhost";
 
     let mut dname = DistinguishedName::new();
@@ -32,37 +49,101 @@ pub fn gen_certs(cert_path: String, key_path: String) -> Result<(), Box<dyn Erro
 
     let keypair = KeyPair::generate(&PKCS_ECDSA_P256_SHA256)?;
 
-    let digest = digest(&SHA256, &keypair.public_key_der());
-
     let mut cert_params = CertificateParams::new(vec![COMMON_NAME.to_string()]);
 
     cert_params.distinguished_name = dname;
     cert_params.alg = &PKCS_ECDSA_P256_SHA256;
     cert_params.key_pair = Some(keypair);
     cert_params.not_before = OffsetDateTime::now_utc()
-        .checked_sub(Duration::days(2))
+        .checked_sub(CLOCK_SKEW_ALLOWANCE)
         .ok_or_else(|| {
             std::io::Error::new(
                 std::io::ErrorKind::Other,
-                "Could not add 2 days to the current date",
+                "Could not subtract the clock skew allowance from the current date",
             )
         })?;
-    cert_params.not_after = OffsetDateTime::now_utc()
-        .checked_add(Duration::days(2))
+    cert_params.not_after = cert_params
+        .not_before
+        .checked_add(validity)
         .ok_or_else(|| {
             std::io::Error::new(
                 std::io::ErrorKind::Other,
-                "Could not add 2 days to the current date",
+                "Could not add the validity to the current date",
             )
         })?;
 
     let certificate = rcgen::Certificate::from_params(cert_params)?;
 
-    fs::File::create(cert_path)?.write_all(certificate.serialize_pem()?.as_bytes())?;
+    // Signing is randomized, so the certificate is serialized once for the hash and the file to match
+    let certificate_der = certificate.serialize_der()?;
+
+    let fingerprint = fingerprint(&certificate_der);
+
+    let certificate_pem = pem::encode(&pem::Pem {
+        tag: "CERTIFICATE".to_string(),
+        contents: certificate_der,
+    });
+
+    fs::File::create(cert_path)?.write_all(certificate_pem.as_bytes())?;
     fs::File::create(key_path)?.write_all(certificate.serialize_private_key_pem().as_bytes())?;
 
     println!("Certificate generated");
-    println!("Fingerprint: {}", Base64Engine.encode(digest));
+    println!("Fingerprint: {}", fingerprint);
+
+    if validity > MAX_PINNABLE_VALIDITY {
+        println!(
+            "The certificate is valid for more than {} days, it cannot be pinned by its hash",
+            MAX_PINNABLE_VALIDITY.whole_days()
+        );
+    }
+
+    Ok(fingerprint)
+}
+
+/// Computes the hash of a certificate file, the one printed by `gen_certs`.
+///
+/// # Arguments
+///
+/// * `cert_path` - The path to the PEM encoded certificate file.
+///
+/// # Returns
+///
+/// * `Result<String, Box<dyn Error>>` - The base64 encoded SHA-256 hash of the DER encoded certificate.
+pub fn certificate_fingerprint(cert_path: &str) -> Result<String, Box<dyn Error>> {
+    let certificate = pem::parse(fs::read(cert_path)?)?;
+
+    Ok(fingerprint(&certificate.contents))
+}
+
+/// Hashes a DER encoded certificate.
+///
+/// The hash pinned by clients is the one of the certificate, not of its public key.
+fn fingerprint(certificate_der: &[u8]) -> String {
+    Base64Engine.encode(digest(&SHA256, certificate_der))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn test_should_compute_fingerprint_printed_at_generation() {
+        let temp_path = |name: &str| {
+            let mut path = env::temp_dir();
+            path.push(format!("{}-{}", name, std::process::id()));
+            path.into_os_string().into_string().unwrap()
+        };
+        let cert_path = temp_path("fingerprint-cert.pem");
+
+        let fingerprint = gen_certs(
+            cert_path.clone(),
+            temp_path("fingerprint-key.pem"),
+            MAX_PINNABLE_VALIDITY,
+        )
+        .unwrap();
 
-    Ok(())
+        assert_eq!(certificate_fingerprint(&cert_path).unwrap(), fingerprint);
+    }
 }
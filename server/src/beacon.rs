@@ -0,0 +1,172 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use common::{
+    beacon::{Announcement, DEFAULT_BEACON_GROUP},
+    message::hello::PROTOCOL_VERSION,
+};
+use tokio::{net::UdpSocket, task::JoinHandle};
+
+use crate::error::ServerSetupError;
+
+/// How many announcement intervals an announcement is valid for, so that a lost datagram
+/// does not make the server disappear.
+const INTERVALS_PER_TTL: u32 = 3;
+
+/// The configuration of the announcements of the server on the local network.
+///
+/// # Fields
+///
+/// * `group` - The address announcements are sent to, usually a multicast group.
+///   Broadcast and unicast addresses work as well, e.g. on networks without multicast routing.
+/// * `interval` - The delay between two announcements.
+/// * `server_name` - The name the certificate of the server is issued for, announced for clients to verify it with.
+#[derive(Debug, Clone)]
+pub struct BeaconConfig {
+    pub group: SocketAddr,
+    pub interval: Duration,
+    pub server_name: String,
+}
+
+impl Default for BeaconConfig {
+    fn default() -> Self {
+        Self {
+            group: DEFAULT_BEACON_GROUP,
+            interval: Duration::from_secs(1),
+            server_name: "localhost".to_string(),
+        }
+    }
+}
+
+/// Periodically announces the server on the local network, until it is dropped.
+pub struct Beacon {
+    task: JoinHandle<()>,
+}
+
+impl Beacon {
+    /// Starts announcing the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration of the announcements.
+    /// * `host` - The address the server is bound to.
+    /// * `port` - The port the server listens on.
+    /// * `certificate_hash` - The base64 encoded SHA-256 hash of the certificate of the server, if known.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Self, ServerSetupError>` - The running beacon, or a `ServerSetupError::BeaconSetupError`
+    ///   if the socket announcements are sent from could not be set up.
+    pub async fn start(
+        config: &BeaconConfig,
+        host: IpAddr,
+        port: u16,
+        certificate_hash: Option<String>,
+    ) -> Result<Self, ServerSetupError> {
+        let group = config.group;
+        let setup_failed = |error: std::io::Error| ServerSetupError::BeaconSetupError {
+            group,
+            reason: error.to_string(),
+        };
+
+        let unspecified = match group {
+            SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        let socket = UdpSocket::bind((unspecified, 0))
+            .await
+            .map_err(setup_failed)?;
+
+        match group.ip() {
+            // Announcements are only meant for the local network
+            IpAddr::V4(address) if address.is_multicast() => {
+                socket.set_multicast_ttl_v4(1).map_err(setup_failed)?
+            }
+            IpAddr::V4(address) if address.is_broadcast() => {
+                socket.set_broadcast(true).map_err(setup_failed)?
+            }
+            _ => {}
+        }
+
+        let announcement = Announcement {
+            server_name: config.server_name.clone(),
+            host: Some(host).filter(|host| !host.is_unspecified()),
+            port,
+            certificate_hash,
+            protocol_version: PROTOCOL_VERSION,
+            ttl: config.interval * INTERVALS_PER_TTL,
+        }
+        .encode();
+
+        let mut interval = tokio::time::interval(config.interval);
+        let task = tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+
+                // The network may come back, so a failed announcement does not stop the next ones
+                if let Err(error) = socket.send_to(&announcement, group).await {
+                    println!("Failed to announce the server to {}: {}", group, error);
+                }
+            }
+        });
+
+        Ok(Self { task })
+    }
+}
+
+impl Drop for Beacon {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::beacon::MAX_ANNOUNCEMENT_SIZE;
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_announce_server_periodically() {
+        let listener = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let config = BeaconConfig {
+            group: listener.local_addr().unwrap(),
+            interval: Duration::from_secs(5),
+            ..BeaconConfig::default()
+        };
+
+        let beacon = Beacon::start(
+            &config,
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            4433,
+            Some("hash".to_string()),
+        )
+        .await
+        .unwrap();
+
+        let mut buf = [0; MAX_ANNOUNCEMENT_SIZE];
+        for _ in 0..2 {
+            let (read, _) = listener.recv_from(&mut buf).await.unwrap();
+
+            assert_eq!(
+                Announcement::decode(&buf[..read]),
+                Ok(Announcement {
+                    server_name: "localhost".to_string(),
+                    host: None,
+                    port: 4433,
+                    certificate_hash: Some("hash".to_string()),
+                    protocol_version: PROTOCOL_VERSION,
+                    ttl: Duration::from_secs(15),
+                })
+            );
+        }
+
+        drop(beacon);
+
+        let next =
+            tokio::time::timeout(Duration::from_secs(60), listener.recv_from(&mut buf)).await;
+        assert!(next.is_err(), "the beacon has not stopped");
+    }
+}
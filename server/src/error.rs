@@ -1,4 +1,7 @@
-use common::error::{ConnectionError, StreamError};
+use common::{
+    error::{ConnectionError, HandshakeError, RpcError, StreamError},
+    message::error::ErrorCode,
+};
 use thiserror::Error;
 
 /// Represents all the errors that can occur in the Server.
@@ -7,6 +10,7 @@ use thiserror::Error;
 /// * `SetupError`: An error occurred during the setup process.
 /// * `ServerStreamError`: An error occurred during streaming.
 /// * `ConnectionError`: An error occurred during connection setup or maintenance.
+/// * `HandshakeError`: The client could not complete the protocol handshake.
 #[derive(Error, Debug)]
 pub enum ServerError {
     #[error(transparent)]
@@ -17,6 +21,9 @@ pub enum ServerError {
 
     #[error("Server connection error: {0}")]
     ConnectionError(#[from] ConnectionError),
+
+    #[error("Server handshake error: {0}")]
+    HandshakeError(#[from] HandshakeError),
 }
 
 /// Represents the errors that can occur during server setup.
@@ -24,6 +31,7 @@ pub enum ServerError {
 /// Variants:
 /// * `EndpointCreationError`: An error occurred while creating the WebTransport client endpoint.
 /// * `CertificateSetupError`: An error occurred while setting up the certificate.
+/// * `BeaconSetupError`: The socket to announce the server on the local network could not be set up.
 #[derive(Error, Debug)]
 pub enum ServerSetupError {
     #[error("failed to create WebTransport server endpoint")]
@@ -31,6 +39,41 @@ pub enum ServerSetupError {
 
     #[error("failed to load certificate. Check certificate path ({cert_path:?}) and key path ({key_path:?})")]
     CertificateSetupError { cert_path: String, key_path: String },
+
+    #[error("failed to set up announcements to {group}: {reason}")]
+    BeaconSetupError {
+        group: std::net::SocketAddr,
+        reason: String,
+    },
+}
+
+/// Represents the errors a `RequestHandler` can answer a request with.
+///
+/// Variants:
+/// * `Unsupported`: The request is valid but cannot be handled by this handler.
+/// * `Failed`: The handler has failed to process the request.
+/// * `RpcError`: The remote procedure call made by the request could not be served.
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum RequestHandlerError {
+    #[error("unsupported request: {reason}")]
+    Unsupported { reason: String },
+
+    #[error("failed to handle request: {reason}")]
+    Failed { reason: String },
+
+    #[error(transparent)]
+    RpcError(#[from] RpcError),
+}
+
+impl RequestHandlerError {
+    /// Gets the code of the error message the request is answered with.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            RequestHandlerError::Unsupported { .. } => ErrorCode::UnsupportedMessage,
+            RequestHandlerError::Failed { .. } => ErrorCode::InternalError,
+            RequestHandlerError::RpcError(error) => error.code(),
+        }
+    }
 }
 
 impl From<wtransport::error::ConnectionError> for ServerError {
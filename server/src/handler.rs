@@ -1,103 +1,1505 @@
+use std::future::Future;
+
 use common::{
-    error::{DatagramError, StreamError},
-    message::Message,
-    stream::{read_next_message, write_message},
+    clock,
+    codec::Codec,
+    error::{DatagramError, HandshakeError, ReadStreamError, StreamError},
+    message::{
+        error::{ErrorCode, ErrorMessage},
+        hello::{Capabilities, ConnectionParameters, HelloAckMessage, HelloMessage},
+        id::MessageId,
+        response::ResponseMessage,
+        resume::ResumeMessage,
+        Message,
+    },
+    stream::{
+        read_codec_preamble, read_next_frame, read_next_message, reset_oversized_stream,
+        write_message,
+    },
+    transport::{Connection, RecvStream, SendStream},
 };
-use wtransport::Connection;
+use futures::{stream::FuturesUnordered, StreamExt};
 
-use crate::error::ServerError;
+use crate::{
+    error::ServerError,
+    mailbox::Mailbox,
+    request_handler::{RequestContext, RequestHandler, TransportKind},
+};
 
-/// Handles a bidirectional stream.
+/// Handles the handshake of a newly established connection.
+///
+/// This function will accept the control stream opened by the client, read the codec preamble and the hello
+/// message from it and respond with an acknowledgement, or with a rejection if the client is incompatible.
+/// The reply is serialized with the codec announced in the preamble. If that codec is not supported,
+/// the stream is reset as no reply could be understood by the client.
 ///
-/// This function will read messages from the stream and respond to them with a "Pong!" message.
+/// Once acknowledged, a reconnecting client may resume its session by sending a resume message,
+/// in which case the responses kept in its mailbox which it has not received are replayed on the control stream,
+/// see `resume_session`.
+/// Otherwise the client just finishes the control stream.
 ///
 /// # Arguments
 ///
 /// * `connection` - A reference to the connection.
+/// * `capabilities` - The capabilities of the server.
+/// * `mailbox` - The mailbox keeping the recent responses of the clients.
 ///
 /// # Returns
 ///
-/// An empty `Result` indicating success or an error.
-pub async fn handle_bidirectional(connection: &Connection) -> Result<(), ServerError> {
+/// A `Result` containing the client's hello message and the acknowledgement sent back, or an error.
+pub async fn handle_handshake<C: Connection>(
+    connection: &C,
+    capabilities: &Capabilities,
+    mailbox: &Mailbox,
+) -> Result<(HelloMessage, HelloAckMessage), ServerError> {
     let (mut send_stream, mut recv_stream) = connection.accept_bi().await?;
 
-    loop {
-        println!("Reading next message from the stream...");
+    let codec = match read_codec_preamble(&mut recv_stream).await {
+        Ok(codec) => codec,
+        Err(error) => {
+            if let ReadStreamError::UnsupportedCodec { .. } = error {
+                send_stream.reset(0);
+            }
+
+            return Err(StreamError::from(error).into());
+        }
+    };
+
+    let message =
+        match read_next_message(&mut recv_stream, codec, capabilities.max_message_size).await {
+            Ok(message) => message,
+            Err(error @ ReadStreamError::FrameTooLarge { .. }) => {
+                send_stream.reset(ErrorCode::MessageTooLarge.code().into());
+                reset_oversized_stream(recv_stream);
+
+                return Err(StreamError::from(error).into());
+            }
+            Err(error) => return Err(StreamError::from(error).into()),
+        };
 
-        let message = read_next_message(&mut recv_stream)
+    let hello = match message {
+        Message::Hello(hello) => hello,
+        other => {
+            return Err(ServerError::HandshakeError(
+                HandshakeError::UnexpectedMessage {
+                    message_type: other.message_type(),
+                },
+            ))
+        }
+    };
+
+    let (reply, result) = match hello.negotiate(capabilities) {
+        Ok(ack) => (Message::HelloAck(ack.clone()), Ok((hello, ack))),
+        Err(error) => (
+            Message::HelloReject(hello.reject(&error)),
+            Err(ServerError::HandshakeError(error)),
+        ),
+    };
+
+    write_message(&mut send_stream, &reply, codec)
+        .await
+        .map_err(StreamError::from)?;
+
+    let (hello, ack) = match result {
+        Ok(handshake) => handshake,
+        Err(error) => {
+            // Make sure the reply is delivered even if the connection is closed right after a rejection
+            send_stream.finish().await.map_err(StreamError::from)?;
+
+            return Err(error);
+        }
+    };
+
+    match read_next_message(&mut recv_stream, codec, capabilities.max_message_size).await {
+        Ok(Message::Resume(resume)) => {
+            resume_session(&mut send_stream, &resume, codec, mailbox, &hello.client_id).await?
+        }
+        Ok(other) => {
+            return Err(ServerError::HandshakeError(
+                HandshakeError::UnexpectedMessage {
+                    message_type: other.message_type(),
+                },
+            ))
+        }
+        // The client has finished the control stream without resuming
+        Err(ReadStreamError::StreamStopped) => {}
+        Err(error) => return Err(StreamError::from(error).into()),
+    }
+
+    // The client stops listening on the control stream once done with it, so it may not be there anymore
+    send_stream.finish().await.ok();
+
+    Ok((hello, ack))
+}
+
+/// Resumes the session of a reconnecting client.
+///
+/// The resume message is acknowledged with the number of responses kept in the client's mailbox
+/// which answer requests the client does not have the responses of, and these are replayed right after.
+///
+/// # Arguments
+///
+/// * `send_stream` - The control stream to reply on.
+/// * `resume` - The resume message sent by the client.
+/// * `codec` - The codec of the control stream.
+/// * `mailbox` - The mailbox keeping the recent responses of the clients.
+/// * `client_id` - The ID of the reconnecting client.
+///
+/// # Returns
+///
+/// An empty `Result` indicating success or an error.
+async fn resume_session<S: SendStream>(
+    send_stream: &mut S,
+    resume: &ResumeMessage,
+    codec: &dyn Codec,
+    mailbox: &Mailbox,
+    client_id: &str,
+) -> Result<(), ServerError> {
+    let replayed = mailbox.replay(client_id, &resume.received);
+
+    let ack = Message::ResumeAck(resume.acknowledge(replayed.len() as u32));
+    write_message(send_stream, &ack, codec)
+        .await
+        .map_err(StreamError::from)?;
+
+    println!(
+        "Resuming session of client {}, replaying {} responses",
+        client_id,
+        replayed.len()
+    );
+
+    for response in replayed {
+        write_message(send_stream, &Message::Response(response), codec)
             .await
             .map_err(StreamError::from)?;
+    }
+
+    Ok(())
+}
 
-        println!("Received request data: {}", message.get_data());
+/// Writes a reply, keeping it in the client's mailbox if it is a response.
+///
+/// A successful write only means that the reply has been handed over to the transport, which may still lose it
+/// along with the connection. Responses are therefore kept whether they are written or not, so that they can be
+/// replayed once the client resumes its session.
+///
+/// # Arguments
+///
+/// * `send_stream` - The stream the replies are sent on.
+/// * `reply` - The reply to send.
+/// * `parameters` - The connection parameters negotiated during the handshake.
+/// * `mailbox` - The mailbox keeping the recent responses of the clients.
+/// * `client_id` - The ID of the client the reply is for.
+///
+/// # Returns
+///
+/// An empty `Result` indicating success or an error.
+async fn deliver_reply<S: SendStream>(
+    send_stream: &mut S,
+    reply: Message,
+    parameters: &ConnectionParameters,
+    mailbox: &Mailbox,
+    client_id: &str,
+) -> Result<(), ServerError> {
+    if let Message::Response(response) = &reply {
+        mailbox.record(client_id, response.clone());
+    }
+
+    write_message(send_stream, &reply, parameters.codec)
+        .await
+        .map_err(|error| StreamError::from(error).into())
+}
 
-        if let Message::Request(request) = message {
-            let response = Message::new_response(&request.id, "Pong!".to_string());
+/// Builds the reply to a received frame.
+///
+/// Requests are answered with the response computed by the request handler. Frames which are too large,
+/// cannot be deserialized or do not contain a request, as well as the requests the handler fails,
+/// are answered with an error message, so that the peer learns about the problem while the stream stays usable.
+///
+/// # Arguments
+///
+/// * `frame` - The received frame.
+/// * `received_at` - The monotonic clock reading taken when the frame was received.
+/// * `parameters` - The connection parameters negotiated during the handshake.
+/// * `handler` - The handler computing the responses to requests.
+/// * `context` - The context of the connection the frame has been received over.
+///
+/// # Returns
+///
+/// The reply to send back.
+async fn reply_to_frame(
+    frame: &[u8],
+    received_at: u64,
+    parameters: &ConnectionParameters,
+    handler: &dyn RequestHandler,
+    context: &RequestContext,
+) -> Message {
+    let max_message_size = parameters.max_message_size;
 
-            write_message(&mut send_stream, &response)
-                .await
-                .map_err(StreamError::from)?;
+    if frame.len() as u64 > max_message_size {
+        return Message::Error(ErrorMessage::new(
+            ErrorCode::MessageTooLarge,
+            format!(
+                "message of {} bytes exceeds the maximum of {} bytes",
+                frame.len(),
+                max_message_size
+            ),
+            None,
+        ));
+    }
+
+    let message = match Message::decode(frame, parameters.codec) {
+        Ok(message) => message,
+        Err(error) => {
+            return Message::Error(ErrorMessage::new(
+                ErrorCode::MalformedMessage,
+                error.to_string(),
+                None,
+            ))
         }
+    };
+
+    println!(
+        "Received request data: {}",
+        String::from_utf8_lossy(message.get_data())
+    );
+
+    match message {
+        Message::Request(request) => match handler.handle(&request, context).await {
+            Ok(response) => {
+                let mut reply = ResponseMessage::new(&request, received_at, response.data);
+                reply.headers = response.headers;
+
+                Message::Response(reply)
+            }
+            Err(error) => Message::Error(ErrorMessage::new(
+                error.code(),
+                error.to_string(),
+                Some(request.id),
+            )),
+        },
+        other => Message::Error(ErrorMessage::new(
+            ErrorCode::UnsupportedMessage,
+            format!("unsupported {:?} message", other.message_type()),
+            Some(other.id()),
+        )),
     }
 }
 
-/// Handles a unidirectional stream.
+/// Rejects a frame which exceeds the negotiated maximum message size.
 ///
-/// This function will read messages from the stream and respond to them with a "Pong!" message.
-/// Using 2 distinct streams for reading and writing.
+/// The frame is answered with an error message, after which the sending stream is finished
+/// and the receiving one is reset, as the rest of the frame cannot be skipped.
+///
+/// # Arguments
+///
+/// * `send_stream` - The stream the replies are sent on.
+/// * `recv_stream` - The stream the oversized frame has been announced on.
+/// * `error` - The `FrameTooLarge` error returned while reading the frame.
+/// * `parameters` - The connection parameters negotiated during the handshake.
+///
+/// # Returns
+///
+/// The `Err` ending the stream handling.
+async fn reject_oversized_frame<S: SendStream, R: RecvStream>(
+    mut send_stream: S,
+    recv_stream: R,
+    error: ReadStreamError,
+    parameters: &ConnectionParameters,
+) -> Result<(), ServerError> {
+    let reply = Message::Error(ErrorMessage::new(
+        ErrorCode::MessageTooLarge,
+        error.to_string(),
+        None,
+    ));
+
+    reset_oversized_stream(recv_stream);
+
+    write_message(&mut send_stream, &reply, parameters.codec)
+        .await
+        .map_err(StreamError::from)?;
+
+    send_stream.finish().await.map_err(StreamError::from)?;
+
+    Err(StreamError::from(error).into())
+}
+
+/// Accepts a bidirectional stream and handles it, see `handle_stream`.
 ///
 /// # Arguments
 ///
 /// * `connection` - A reference to the connection.
+/// * `parameters` - The connection parameters negotiated during the handshake.
+/// * `mailbox` - The mailbox keeping the recent responses of the clients.
+/// * `handler` - The handler computing the responses to requests.
+/// * `client_id` - The ID of the connected client.
+/// * `session_id` - The ID generated for the connection.
 ///
 /// # Returns
 ///
 /// An empty `Result` indicating success or an error.
-pub async fn handle_unidirectional(connection: &Connection) -> Result<(), ServerError> {
-    let mut recv_stream = connection.accept_uni().await?;
-    let mut send_stream = connection.open_uni().await?;
+pub async fn handle_bidirectional<C: Connection>(
+    connection: &C,
+    parameters: &ConnectionParameters,
+    mailbox: &Mailbox,
+    handler: &dyn RequestHandler,
+    client_id: &str,
+    session_id: &str,
+) -> Result<(), ServerError> {
+    let (send_stream, recv_stream) = connection.accept_bi().await?;
+    let context = request_context(
+        connection,
+        parameters,
+        client_id,
+        session_id,
+        TransportKind::Bidirectional,
+    );
 
-    loop {
+    handle_stream(
+        send_stream,
+        recv_stream,
+        parameters,
+        mailbox,
+        handler,
+        &context,
+    )
+    .await
+}
+
+/// Accepts a unidirectional stream, opens another one to reply on and handles them, see `handle_stream`.
+///
+/// # Arguments
+///
+/// * `connection` - A reference to the connection.
+/// * `parameters` - The connection parameters negotiated during the handshake.
+/// * `mailbox` - The mailbox keeping the recent responses of the clients.
+/// * `handler` - The handler computing the responses to requests.
+/// * `client_id` - The ID of the connected client.
+/// * `session_id` - The ID generated for the connection.
+///
+/// # Returns
+///
+/// An empty `Result` indicating success or an error.
+pub async fn handle_unidirectional<C: Connection>(
+    connection: &C,
+    parameters: &ConnectionParameters,
+    mailbox: &Mailbox,
+    handler: &dyn RequestHandler,
+    client_id: &str,
+    session_id: &str,
+) -> Result<(), ServerError> {
+    let recv_stream = connection.accept_uni().await?;
+    let send_stream = connection.open_uni().await?;
+    let context = request_context(
+        connection,
+        parameters,
+        client_id,
+        session_id,
+        TransportKind::Unidirectional,
+    );
+
+    handle_stream(
+        send_stream,
+        recv_stream,
+        parameters,
+        mailbox,
+        handler,
+        &context,
+    )
+    .await
+}
+
+/// Delivers the replies still being computed for a stream which is ending.
+///
+/// The responses are kept in the client's mailbox, see `deliver_reply`.
+///
+/// # Arguments
+///
+/// * `send_stream` - The stream the replies are sent on.
+/// * `replies` - The replies still being computed.
+/// * `parameters` - The connection parameters negotiated during the handshake.
+/// * `mailbox` - The mailbox keeping the recent responses of the clients.
+/// * `client_id` - The ID of the client the replies are for.
+///
+/// # Returns
+///
+/// An empty `Result` indicating success or the first error delivering the replies has failed with.
+async fn deliver_pending_replies<S: SendStream, F: Future<Output = Message>>(
+    send_stream: &mut S,
+    replies: &mut FuturesUnordered<F>,
+    parameters: &ConnectionParameters,
+    mailbox: &Mailbox,
+    client_id: &str,
+) -> Result<(), ServerError> {
+    let mut result = Ok(());
+
+    while let Some(reply) = replies.next().await {
+        // Once the stream has failed, the remaining responses are only kept in the mailbox
+        let delivered = deliver_reply(send_stream, reply, parameters, mailbox, client_id).await;
+
+        result = result.and(delivered);
+    }
+
+    result
+}
+
+/// Handles a stream, either a bidirectional one or a pair of unidirectional ones.
+///
+/// This function will read messages from `recv_stream` and respond to them on `send_stream`
+/// with the responses computed by the request handler.
+/// Requests are handled concurrently and answered as soon as their response is computed, so a client
+/// pipelining its requests may receive the responses out of order, correlated by the ID of their request.
+/// Messages which cannot be processed are answered with an error message without closing the streams,
+/// except for messages exceeding the negotiated maximum size, after which the streams are reset.
+///
+/// Responses are kept in the client's mailbox, to be replayed if they do not reach the client.
+///
+/// # Arguments
+///
+/// * `send_stream` - The stream to reply on.
+/// * `recv_stream` - The stream to read messages from.
+/// * `parameters` - The connection parameters negotiated during the handshake.
+/// * `mailbox` - The mailbox keeping the recent responses of the clients.
+/// * `handler` - The handler computing the responses to requests.
+/// * `context` - The context of the connection the stream belongs to.
+///
+/// # Returns
+///
+/// An empty `Result` indicating success or an error.
+pub async fn handle_stream<S: SendStream, R: RecvStream>(
+    mut send_stream: S,
+    recv_stream: R,
+    parameters: &ConnectionParameters,
+    mailbox: &Mailbox,
+    handler: &dyn RequestHandler,
+    context: &RequestContext,
+) -> Result<(), ServerError> {
+    let read = |mut recv_stream: R| async move {
         println!("Reading next message from the stream...");
 
-        let message = read_next_message(&mut recv_stream)
-            .await
-            .map_err(StreamError::from)?;
+        let frame = read_next_frame(&mut recv_stream, parameters.max_message_size).await;
 
-        println!("Received request data: {}", message.get_data());
+        (recv_stream, frame)
+    };
+    let reply = |frame: Vec<u8>, received_at: u64| async move {
+        reply_to_frame(&frame, received_at, parameters, handler, context).await
+    };
 
-        if let Message::Request(request) = message {
-            let response = Message::new_response(&request.id, "Pong!".to_string());
+    // The pending read is kept while replies are delivered, so that no frame is cut in half
+    let mut reading = Box::pin(read(recv_stream));
+    let mut replies = FuturesUnordered::new();
 
-            write_message(&mut send_stream, &response)
-                .await
-                .map_err(StreamError::from)?;
+    loop {
+        tokio::select! {
+            (recv_stream, frame) = &mut reading => {
+                let frame = match frame {
+                    Ok(frame) => frame,
+                    Err(error) => {
+                        // The requests read so far are answered before the stream is given up on
+                        deliver_pending_replies(
+                            &mut send_stream,
+                            &mut replies,
+                            parameters,
+                            mailbox,
+                            &context.client_id,
+                        )
+                        .await?;
+
+                        if let ReadStreamError::FrameTooLarge { .. } = error {
+                            return reject_oversized_frame(send_stream, recv_stream, error, parameters)
+                                .await;
+                        }
+
+                        return Err(StreamError::from(error).into());
+                    }
+                };
+
+                replies.push(reply(frame, clock::monotonic_nanos()));
+                reading = Box::pin(read(recv_stream));
+            }
+            Some(reply) = replies.next() => {
+                let delivered = deliver_reply(
+                    &mut send_stream,
+                    reply,
+                    parameters,
+                    mailbox,
+                    &context.client_id,
+                )
+                .await;
+
+                if let Err(error) = delivered {
+                    deliver_pending_replies(
+                        &mut send_stream,
+                        &mut replies,
+                        parameters,
+                        mailbox,
+                        &context.client_id,
+                    )
+                    .await
+                    .ok();
+
+                    return Err(error);
+                }
+            }
         }
     }
 }
 
 /// Handles a datagram.
 ///
-/// This function will read datagram message and respond with a "Pong!" datagram message.
+/// This function will read datagram message and respond with a datagram message holding the response
+/// computed by the request handler, or with an error datagram message if the received one cannot be processed.
 ///
 /// # Arguments
 ///
 /// * `connection` - A reference to the connection.
+/// * `parameters` - The connection parameters negotiated during the handshake.
+/// * `handler` - The handler computing the responses to requests.
+/// * `context` - The context of the connection.
 ///
 /// # Returns
 ///
 /// An empty `Result` indicating success or an error.
-pub async fn handle_datagram(connection: &Connection) -> Result<(), DatagramError> {
+pub async fn handle_datagram<C: Connection>(
+    connection: &C,
+    parameters: &ConnectionParameters,
+    handler: &dyn RequestHandler,
+    context: &RequestContext,
+) -> Result<(), DatagramError> {
     let datagram = connection.receive_datagram().await?;
 
-    let message = Message::from_bytes(&datagram)?;
+    let reply = reply_to_frame(
+        &datagram,
+        clock::monotonic_nanos(),
+        parameters,
+        handler,
+        context,
+    )
+    .await;
 
-    println!("Received request data: {}", message.get_data());
+    connection.send_datagram(&reply.encode(parameters.codec)?)?;
 
-    if let Message::Request(request) = message {
-        let response = Message::new_response(&request.id, "Pong!".to_string());
+    Ok(())
+}
 
-        connection.send_datagram(response.as_bytes()?)?;
+/// Handles the datagrams of a connection until it is closed.
+///
+/// Datagrams which cannot be processed are skipped, so that a single bad datagram does not stop
+/// the next ones from being answered.
+///
+/// # Arguments
+///
+/// * `connection` - A reference to the connection.
+/// * `parameters` - The connection parameters negotiated during the handshake.
+/// * `handler` - The handler computing the responses to requests.
+/// * `client_id` - The ID of the connected client.
+/// * `session_id` - The ID generated for the connection.
+///
+/// # Returns
+///
+/// The `DatagramError` which has ended the connection, or made datagrams unusable on it.
+pub async fn serve_datagrams<C: Connection>(
+    connection: &C,
+    parameters: &ConnectionParameters,
+    handler: &dyn RequestHandler,
+    client_id: &str,
+    session_id: &str,
+) -> DatagramError {
+    let context = request_context(
+        connection,
+        parameters,
+        client_id,
+        session_id,
+        TransportKind::Datagram,
+    );
+
+    loop {
+        match handle_datagram(connection, parameters, handler, &context).await {
+            Ok(()) => {}
+            Err(error @ DatagramError::DeserializationFailed(_)) => {
+                println!("Skipping datagram: {}", error);
+            }
+            Err(error) => return error,
+        }
     }
+}
 
-    Ok(())
+/// Builds the context of the requests received over a connection.
+///
+/// # Arguments
+///
+/// * `connection` - A reference to the connection.
+/// * `parameters` - The connection parameters negotiated during the handshake.
+/// * `client_id` - The ID of the connected client.
+/// * `session_id` - The ID generated for the connection, see `new_session_id`.
+/// * `transport` - The transport the requests are received over.
+///
+/// # Returns
+///
+/// The context passed to the request handler.
+pub fn request_context<C: Connection>(
+    connection: &C,
+    parameters: &ConnectionParameters,
+    client_id: &str,
+    session_id: &str,
+    transport: TransportKind,
+) -> RequestContext {
+    RequestContext {
+        peer_address: connection.remote_address(),
+        client_id: client_id.to_string(),
+        session_id: session_id.to_string(),
+        transport,
+        codec: parameters.codec,
+    }
+}
+
+/// Generates the ID of a new connection of a client.
+///
+/// Session IDs are unique, so that the successive connections of a client resuming its session can be told apart.
+///
+/// # Returns
+///
+/// The generated session ID.
+pub fn new_session_id() -> String {
+    MessageId::generate().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use client::{
+        client::{PingClient, PingClientConfig},
+        error::ClientError,
+        handler::{
+            perform_handshake, send_bidirectional, send_datagram, send_pipelined,
+            send_unidirectional,
+        },
+        inbox::InboxEntry,
+        pipeline::PipelineConfig,
+        stats::PingStatistics,
+    };
+    use common::{
+        codec::{self, default_codec},
+        error::WriteStreamError,
+        message::request::RequestMessage,
+        transport::loopback::{
+            loopback_pair, DatagramConditions, LoopbackConnection, LOOPBACK_ADDRESS,
+        },
+    };
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::{
+        error::RequestHandlerError,
+        request_handler::{Echo, HandlerResponse, Pong},
+    };
+
+    fn parameters(max_message_size: u64) -> ConnectionParameters {
+        ConnectionParameters {
+            codec: default_codec(),
+            datagrams_supported: true,
+            max_message_size,
+        }
+    }
+
+    fn mailbox() -> Mailbox {
+        Mailbox::new(Duration::from_secs(60), 16)
+    }
+
+    fn context() -> RequestContext {
+        RequestContext {
+            peer_address: LOOPBACK_ADDRESS,
+            client_id: "client".to_string(),
+            session_id: "session".to_string(),
+            transport: TransportKind::Bidirectional,
+            codec: default_codec(),
+        }
+    }
+
+    async fn reply(frame: &[u8], parameters: &ConnectionParameters) -> Message {
+        reply_to_frame(frame, 0, parameters, &Pong, &context()).await
+    }
+
+    fn expect_error(reply: Message) -> ErrorMessage {
+        match reply {
+            Message::Error(error) => error,
+            other => panic!("Expected an error message, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_should_respond_to_request() {
+        let request = Message::new_request("Ping!");
+
+        let reply = reply(&request.as_bytes().unwrap(), &parameters(1024)).await;
+
+        match reply {
+            Message::Response(response) => {
+                assert_eq!(response.request_id, request.id());
+                assert_eq!(response.data, b"Pong!");
+            }
+            other => panic!("Expected a response, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_should_reject_malformed_frame() {
+        let error = expect_error(reply(&[42, 0, 0], &parameters(1024)).await);
+
+        assert_eq!(error.code, ErrorCode::MalformedMessage);
+        assert_eq!(error.request_id, None);
+    }
+
+    #[tokio::test]
+    async fn test_should_reject_oversized_frame() {
+        let request = Message::new_request(vec![0; 64]);
+
+        let error = expect_error(reply(&request.as_bytes().unwrap(), &parameters(32)).await);
+
+        assert_eq!(error.code, ErrorCode::MessageTooLarge);
+    }
+
+    #[tokio::test]
+    async fn test_should_reject_unsupported_message() {
+        let request = Message::new_request("Ping!");
+        let response = match &request {
+            Message::Request(request) => Message::new_response(request, 0, "Pong!"),
+            _ => unreachable!(),
+        };
+
+        let error = expect_error(reply(&response.as_bytes().unwrap(), &parameters(1024)).await);
+
+        assert_eq!(error.code, ErrorCode::UnsupportedMessage);
+        assert_eq!(error.request_id, Some(response.id()));
+    }
+
+    #[tokio::test]
+    async fn test_should_reply_with_negotiated_codec() {
+        let request = Message::new_request("Ping!");
+
+        for codec in codec::supported_codecs() {
+            let parameters = ConnectionParameters {
+                codec: *codec,
+                ..parameters(1024)
+            };
+
+            let reply = reply(&request.encode(*codec).unwrap(), &parameters).await;
+
+            assert!(matches!(reply, Message::Response(_)), "{:?}", codec);
+        }
+    }
+
+    /// Fails every request, telling the context it has been received in.
+    struct Failing;
+
+    #[async_trait]
+    impl RequestHandler for Failing {
+        async fn handle(
+            &self,
+            _request: &RequestMessage,
+            context: &RequestContext,
+        ) -> Result<HandlerResponse, RequestHandlerError> {
+            Err(RequestHandlerError::Failed {
+                reason: format!("{} over {:?}", context.client_id, context.transport),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_should_answer_failed_request_with_error() {
+        let request = Message::new_request("Ping!");
+
+        let error = expect_error(
+            reply_to_frame(
+                &request.as_bytes().unwrap(),
+                0,
+                &parameters(1024),
+                &Failing,
+                &context(),
+            )
+            .await,
+        );
+
+        assert_eq!(error.code, ErrorCode::InternalError);
+        assert_eq!(error.request_id, Some(request.id()));
+        assert_eq!(
+            error.reason,
+            "failed to handle request: client over Bidirectional"
+        );
+    }
+
+    fn assert_pongs(inbox: &[InboxEntry], count: usize) {
+        assert_eq!(inbox.len(), count);
+        for entry in inbox {
+            assert_eq!(entry.message.get_data(), b"Pong!");
+            assert!(entry.round_trip_time.is_some());
+        }
+    }
+
+    /// Answers every request with its arrival rank, holding the first ones back for the given delays.
+    /// The requests of the ranks in `failing` are failed once their delay has passed.
+    struct Ranked {
+        arrivals: AtomicUsize,
+        delays: Vec<Duration>,
+        failing: Vec<usize>,
+    }
+
+    #[async_trait]
+    impl RequestHandler for Ranked {
+        async fn handle(
+            &self,
+            _request: &RequestMessage,
+            _context: &RequestContext,
+        ) -> Result<HandlerResponse, RequestHandlerError> {
+            let rank = self.arrivals.fetch_add(1, Ordering::SeqCst);
+
+            if let Some(delay) = self.delays.get(rank) {
+                tokio::time::sleep(*delay).await;
+            }
+
+            if self.failing.contains(&rank) {
+                return Err(RequestHandlerError::Failed {
+                    reason: format!("request {} failed", rank),
+                });
+            }
+
+            Ok(HandlerResponse::new(format!("Pong {}", rank)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_should_complete_handshake_over_loopback() {
+        let (client, server) = loopback_pair(DatagramConditions::default());
+        let mailbox = mailbox();
+        let hello = HelloMessage::new("client".to_string(), Capabilities::default());
+        let capabilities = Capabilities::default();
+
+        let (client_result, server_result) = tokio::join!(
+            perform_handshake(&client, &hello, default_codec(), None),
+            handle_handshake(&server, &capabilities, &mailbox)
+        );
+
+        let (received_hello, server_ack) = server_result.unwrap();
+        assert_eq!(received_hello, hello);
+        assert_eq!(client_result.unwrap(), (server_ack, vec![]));
+    }
+
+    #[tokio::test]
+    async fn test_should_serve_bidirectional_over_loopback() {
+        let (client, server) = loopback_pair(DatagramConditions::default());
+        let mailbox = mailbox();
+        let parameters = parameters(1024);
+        let mut inbox = Vec::new();
+        let mut stats = PingStatistics::new();
+        let message = Message::new_request("Ping!");
+
+        let (client_result, _) = tokio::join!(
+            send_bidirectional(
+                &client,
+                &message,
+                Some(3),
+                &mut inbox,
+                &mut stats,
+                &parameters
+            ),
+            handle_bidirectional(&server, &parameters, &mailbox, &Pong, "client", "session")
+        );
+
+        client_result.unwrap();
+        assert_pongs(&inbox, 3);
+        assert_eq!(stats.received(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_pipeline_requests_answered_out_of_order_over_loopback() {
+        let (client, server) = loopback_pair(DatagramConditions::default());
+        let mailbox = mailbox();
+        let parameters = parameters(1024);
+        let mut inbox = Vec::new();
+        let mut stats = PingStatistics::new();
+        let message = Message::new_request("Ping!");
+        let pipeline = PipelineConfig {
+            depth: 5,
+            request_timeout: Duration::from_millis(200),
+        };
+        // The first request outlives its timeout, the second one is overtaken by the others
+        let handler = Ranked {
+            arrivals: AtomicUsize::new(0),
+            delays: vec![Duration::from_secs(5), Duration::from_millis(50)],
+            failing: vec![],
+        };
+
+        let (client_result, _) = tokio::join!(
+            async {
+                let streams = client.open_bi().await?;
+
+                send_pipelined(
+                    streams,
+                    &message,
+                    Some(5),
+                    &pipeline,
+                    &mut inbox,
+                    &mut stats,
+                    &parameters,
+                )
+                .await
+            },
+            handle_bidirectional(
+                &server,
+                &parameters,
+                &mailbox,
+                &handler,
+                "client",
+                "session"
+            )
+        );
+
+        client_result.unwrap();
+        let answered: Vec<_> = inbox
+            .iter()
+            .map(|entry| String::from_utf8_lossy(entry.message.get_data()))
+            .collect();
+        assert_eq!(answered, ["Pong 2", "Pong 3", "Pong 4", "Pong 1"]);
+        assert_eq!(stats.sent(), 5);
+        assert_eq!(stats.received(), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_discard_error_answering_expired_pipelined_request() {
+        let (client, server) = loopback_pair(DatagramConditions::default());
+        let mailbox = mailbox();
+        let parameters = parameters(1024);
+        let mut inbox = Vec::new();
+        let mut stats = PingStatistics::new();
+        let message = Message::new_request("Ping!");
+        let pipeline = PipelineConfig {
+            depth: 1,
+            request_timeout: Duration::from_millis(500),
+        };
+        // The first request fails after it has been given up on, while the second one is in flight,
+        // the last one fails in time
+        let handler = Ranked {
+            arrivals: AtomicUsize::new(0),
+            delays: vec![Duration::from_millis(700), Duration::from_millis(400)],
+            failing: vec![0, 4],
+        };
+
+        let (client_result, _) = tokio::join!(
+            async {
+                let streams = client.open_bi().await?;
+
+                send_pipelined(
+                    streams,
+                    &message,
+                    Some(5),
+                    &pipeline,
+                    &mut inbox,
+                    &mut stats,
+                    &parameters,
+                )
+                .await
+            },
+            handle_bidirectional(
+                &server,
+                &parameters,
+                &mailbox,
+                &handler,
+                "client",
+                "session"
+            )
+        );
+
+        assert!(matches!(
+            client_result,
+            Err(ClientError::ErrorResponse {
+                code: ErrorCode::InternalError,
+                ..
+            })
+        ));
+        assert_eq!(stats.sent(), 5);
+        assert_eq!(stats.received(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_should_serve_with_request_handler_over_loopback() {
+        let (client, server) = loopback_pair(DatagramConditions::default());
+        let mailbox = mailbox();
+        let parameters = parameters(1024);
+        let mut inbox = Vec::new();
+        let mut stats = PingStatistics::new();
+        let message = Message::new_request("Ping!");
+
+        let (client_result, _) = tokio::join!(
+            send_bidirectional(
+                &client,
+                &message,
+                Some(2),
+                &mut inbox,
+                &mut stats,
+                &parameters
+            ),
+            handle_bidirectional(&server, &parameters, &mailbox, &Echo, "client", "session")
+        );
+
+        client_result.unwrap();
+        assert_eq!(inbox.len(), 2);
+        for entry in inbox {
+            assert_eq!(entry.message.get_data(), b"Ping!");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_should_serve_unidirectional_over_loopback() {
+        let (client, server) = loopback_pair(DatagramConditions::default());
+        let mailbox = mailbox();
+        let parameters = parameters(1024);
+        let mut inbox = Vec::new();
+        let mut stats = PingStatistics::new();
+        let message = Message::new_request("Ping!");
+
+        let (client_result, _) = tokio::join!(
+            send_unidirectional(
+                &client,
+                &message,
+                Some(3),
+                &mut inbox,
+                &mut stats,
+                &parameters
+            ),
+            handle_unidirectional(&server, &parameters, &mailbox, &Pong, "client", "session")
+        );
+
+        client_result.unwrap();
+        assert_pongs(&inbox, 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_serve_datagrams_over_loopback() {
+        let (client, server) = loopback_pair(DatagramConditions {
+            latency: Duration::from_millis(20),
+            ..Default::default()
+        });
+        let parameters = parameters(1024);
+        let mut inbox = Vec::new();
+        let mut stats = PingStatistics::new();
+        let message = Message::new_request("Ping!");
+
+        let serve = async {
+            while handle_datagram(&server, &parameters, &Pong, &context())
+                .await
+                .is_ok()
+            {}
+        };
+        let started_at = tokio::time::Instant::now();
+
+        let client_result = tokio::select! {
+            result = send_datagram(
+                &client,
+                &message,
+                Some(3),
+                Duration::from_secs(1),
+                &mut inbox,
+                &mut stats,
+                &parameters
+            ) => result,
+            _ = serve => panic!("Serving datagrams has failed"),
+        };
+
+        client_result.unwrap();
+        assert_pongs(&inbox, 3);
+        assert!(started_at.elapsed() >= Duration::from_millis(3 * 40));
+    }
+
+    #[tokio::test]
+    async fn test_should_discard_stray_datagrams_and_fail_on_error_naming_request() {
+        let (client, server) = loopback_pair(DatagramConditions::default());
+        let parameters = parameters(1024);
+        let mut inbox = Vec::new();
+        let mut stats = PingStatistics::new();
+        let message = Message::new_request("Ping!");
+
+        let serve = async {
+            let codec = parameters.codec;
+            let mut requests = Vec::new();
+
+            for _ in 0..2 {
+                let datagram = server.receive_datagram().await.unwrap();
+                let request = match Message::decode(&datagram, codec).unwrap() {
+                    Message::Request(request) => request,
+                    other => panic!("Expected a request, got {:?}", other),
+                };
+
+                let stray_errors = [
+                    None,
+                    requests.last().map(|request: &RequestMessage| request.id),
+                ]
+                .map(|request_id| ErrorMessage::new(ErrorCode::InternalError, "stray", request_id));
+
+                server.send_datagram(b"not a message").unwrap();
+                for error in stray_errors {
+                    server
+                        .send_datagram(&Message::Error(error).encode(codec).unwrap())
+                        .unwrap();
+                }
+
+                let reply = match requests.len() {
+                    0 => Message::new_response(&request, 0, b"Pong!".to_vec()),
+                    _ => Message::Error(ErrorMessage::new(
+                        ErrorCode::InternalError,
+                        "failed",
+                        Some(request.id),
+                    )),
+                };
+                server.send_datagram(&reply.encode(codec).unwrap()).unwrap();
+                requests.push(request);
+            }
+
+            std::future::pending::<()>().await;
+        };
+
+        let client_result = tokio::select! {
+            result = send_datagram(
+                &client,
+                &message,
+                Some(3),
+                Duration::from_secs(1),
+                &mut inbox,
+                &mut stats,
+                &parameters
+            ) => result,
+            _ = serve => unreachable!(),
+        };
+
+        assert!(matches!(
+            client_result,
+            Err(ClientError::ErrorResponse {
+                code: ErrorCode::InternalError,
+                ..
+            })
+        ));
+        assert_eq!(inbox.len(), 1);
+        assert_eq!(stats.summary().sent, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_account_lost_datagrams_over_loopback() {
+        let (client, server) = loopback_pair(DatagramConditions {
+            latency: Duration::from_millis(20),
+            loss_probability: 0.3,
+            seed: 7,
+            ..Default::default()
+        });
+        let parameters = parameters(1024);
+        let mut inbox = Vec::new();
+        let mut stats = PingStatistics::new();
+        let message = Message::new_request("Ping!");
+
+        let serve = async {
+            while handle_datagram(&server, &parameters, &Pong, &context())
+                .await
+                .is_ok()
+            {}
+        };
+
+        let client_result = tokio::select! {
+            result = send_datagram(
+                &client,
+                &message,
+                Some(20),
+                Duration::from_millis(100),
+                &mut inbox,
+                &mut stats,
+                &parameters
+            ) => result,
+            _ = serve => panic!("Serving datagrams has failed"),
+        };
+
+        client_result.unwrap();
+        let summary = stats.summary();
+        assert_eq!(summary.sent, 20);
+        assert!(summary.received < 20);
+        assert!(summary.loss_percent > 0.0);
+        assert_eq!(inbox.len() as u64, summary.received);
+        assert_pongs(&inbox, inbox.len());
+    }
+
+    #[tokio::test]
+    async fn test_should_reset_stream_of_oversized_message_over_loopback() {
+        let (client, server) = loopback_pair(DatagramConditions::default());
+        let mailbox = mailbox();
+        let parameters = parameters(256);
+        let mut inbox = Vec::new();
+        let mut stats = PingStatistics::new();
+        let message = Message::new_request(vec![0; 1024]);
+
+        let (client_result, server_result) = tokio::join!(
+            send_bidirectional(
+                &client,
+                &message,
+                Some(1),
+                &mut inbox,
+                &mut stats,
+                &parameters
+            ),
+            handle_bidirectional(&server, &parameters, &mailbox, &Pong, "client", "session")
+        );
+
+        match client_result {
+            Err(ClientError::ErrorResponse { code, .. }) => {
+                assert_eq!(code, ErrorCode::MessageTooLarge)
+            }
+            other => panic!("Expected an error response, got {:?}", other),
+        }
+        assert!(matches!(
+            server_result,
+            Err(ServerError::ServerStreamError(StreamError::ReadError(
+                ReadStreamError::FrameTooLarge {
+                    size: _,
+                    max_size: 256
+                }
+            )))
+        ));
+    }
+
+    /// Serves a loopback connection, cutting it off after `pongs_before_drop` pongs if given.
+    async fn serve_loopback(
+        connection: LoopbackConnection,
+        mailbox: Arc<Mailbox>,
+        pongs_before_drop: Option<usize>,
+    ) {
+        let (hello, ack) = handle_handshake(&connection, &Capabilities::default(), &mailbox)
+            .await
+            .unwrap();
+        let parameters = ack.parameters().unwrap();
+
+        let Some(pongs_before_drop) = pongs_before_drop else {
+            handle_bidirectional(
+                &connection,
+                &parameters,
+                &mailbox,
+                &Pong,
+                &hello.client_id,
+                "session",
+            )
+            .await
+            .ok();
+            return;
+        };
+
+        let (mut send_stream, mut recv_stream) = connection.accept_bi().await.unwrap();
+
+        for _ in 0..pongs_before_drop {
+            let frame = read_next_frame(&mut recv_stream, parameters.max_message_size)
+                .await
+                .unwrap();
+            let reply = reply_to_frame(
+                &frame,
+                clock::monotonic_nanos(),
+                &parameters,
+                &Pong,
+                &context(),
+            )
+            .await;
+            write_message(&mut send_stream, &reply, parameters.codec)
+                .await
+                .unwrap();
+        }
+
+        // Dropping the connection along with its streams cuts the client off
+    }
+
+    #[tokio::test]
+    async fn test_should_reconnect_and_send_remaining_pings_over_loopback() {
+        let mut ping_client = PingClient::new(PingClientConfig {
+            max_reconnects: 1,
+            ..Default::default()
+        });
+        let message = Message::new_request("Ping!");
+        let mailbox = Arc::new(mailbox());
+        let mut connections = 0;
+
+        ping_client
+            .send_message_over(
+                || {
+                    connections += 1;
+                    let (client, server) = loopback_pair(DatagramConditions::default());
+                    let pongs_before_drop = (connections == 1).then_some(2);
+
+                    tokio::spawn(serve_loopback(server, mailbox.clone(), pongs_before_drop));
+
+                    std::future::ready(Ok(client))
+                },
+                &message,
+                Some(5),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(connections, 2);
+        assert_pongs(&ping_client.get_indbox(), 5);
+
+        let summary = ping_client.get_statistics();
+        assert_eq!(summary.sent, 5);
+        assert_eq!(summary.received, 5);
+        assert_eq!(summary.reconnects, 1);
+    }
+
+    #[tokio::test]
+    async fn test_should_give_up_without_reconnects_over_loopback() {
+        let mut ping_client = PingClient::new(PingClientConfig {
+            max_reconnects: 0,
+            ..Default::default()
+        });
+        let message = Message::new_request("Ping!");
+
+        let result = ping_client
+            .send_message_over(
+                || {
+                    let (client, server) = loopback_pair(DatagramConditions::default());
+
+                    tokio::spawn(serve_loopback(server, Arc::new(mailbox()), Some(2)));
+
+                    std::future::ready(Ok(client))
+                },
+                &message,
+                Some(5),
+            )
+            .await;
+
+        assert!(result.unwrap_err().is_connection_lost());
+        assert_eq!(ping_client.get_statistics().reconnects, 0);
+    }
+
+    #[tokio::test]
+    async fn test_should_replay_undelivered_responses_on_resume() {
+        let mailbox = mailbox();
+        let hello = HelloMessage::new("client".to_string(), Capabilities::default());
+        let capabilities = Capabilities::default();
+        let parameters = parameters(1024);
+        let request = Message::new_request("Ping!");
+
+        // The client is gone right after sending its request
+        let (client, server) = loopback_pair(DatagramConditions::default());
+        let (_, server_result) = tokio::join!(
+            async {
+                perform_handshake(&client, &hello, default_codec(), None)
+                    .await
+                    .unwrap();
+
+                let (mut send_stream, recv_stream) = client.open_bi().await.unwrap();
+                write_message(&mut send_stream, &request, default_codec())
+                    .await
+                    .unwrap();
+
+                drop((send_stream, recv_stream, client));
+            },
+            async {
+                handle_handshake(&server, &capabilities, &mailbox)
+                    .await
+                    .unwrap();
+                handle_bidirectional(
+                    &server,
+                    &parameters,
+                    &mailbox,
+                    &Pong,
+                    &hello.client_id,
+                    "session",
+                )
+                .await
+            }
+        );
+
+        assert!(matches!(
+            server_result,
+            Err(ServerError::ServerStreamError(StreamError::WriteError(
+                WriteStreamError::ConnectionClosed
+            )))
+        ));
+
+        let (client, server) = loopback_pair(DatagramConditions::default());
+        let (client_result, server_result) = tokio::join!(
+            perform_handshake(&client, &hello, default_codec(), Some(vec![])),
+            handle_handshake(&server, &capabilities, &mailbox)
+        );
+
+        server_result.unwrap();
+        let (_, replayed) = client_result.unwrap();
+        match replayed.as_slice() {
+            [Message::Response(response)] => assert_eq!(response.request_id, request.id()),
+            other => panic!("Expected a single replayed response, got {:?}", other),
+        }
+        assert!(mailbox.replay(&hello.client_id, &[request.id()]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_should_replay_written_responses_lost_with_connection() {
+        let mailbox = mailbox();
+        let hello = HelloMessage::new("client".to_string(), Capabilities::default());
+        let capabilities = Capabilities::default();
+        let parameters = parameters(1024);
+        let received = Message::new_request("Ping!");
+        let lost = Message::new_request("Ping!");
+
+        // Both responses are written, but the second one is lost along with the connection
+        let (client, server) = loopback_pair(DatagramConditions::default());
+        let (_, server_result) = tokio::join!(
+            async {
+                perform_handshake(&client, &hello, default_codec(), None)
+                    .await
+                    .unwrap();
+
+                let (mut send_stream, mut recv_stream) = client.open_bi().await.unwrap();
+                for request in [&received, &lost] {
+                    write_message(&mut send_stream, request, default_codec())
+                        .await
+                        .unwrap();
+                    read_next_message(&mut recv_stream, default_codec(), 1024)
+                        .await
+                        .unwrap();
+                }
+
+                drop((send_stream, recv_stream, client));
+            },
+            async {
+                handle_handshake(&server, &capabilities, &mailbox)
+                    .await
+                    .unwrap();
+                handle_bidirectional(
+                    &server,
+                    &parameters,
+                    &mailbox,
+                    &Pong,
+                    &hello.client_id,
+                    "session",
+                )
+                .await
+            }
+        );
+
+        assert!(matches!(
+            server_result,
+            Err(ServerError::ServerStreamError(StreamError::ReadError(_)))
+        ));
+
+        let (client, server) = loopback_pair(DatagramConditions::default());
+        let (client_result, server_result) = tokio::join!(
+            perform_handshake(&client, &hello, default_codec(), Some(vec![received.id()])),
+            handle_handshake(&server, &capabilities, &mailbox)
+        );
+
+        server_result.unwrap();
+        let (_, replayed) = client_result.unwrap();
+        match replayed.as_slice() {
+            [Message::Response(response)] => assert_eq!(response.request_id, lost.id()),
+            other => panic!("Expected a single replayed response, got {:?}", other),
+        }
+    }
 }
@@ -1,3 +1,8 @@
+pub mod beacon;
 pub mod error;
 pub mod handler;
+pub mod mailbox;
+pub mod request_handler;
+pub mod rpc;
 pub mod server;
+pub mod session;
@@ -0,0 +1,211 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Mutex,
+    time::Duration,
+};
+
+use common::message::{id::MessageId, response::ResponseMessage};
+use tokio::time::Instant;
+
+/// A response kept in a mailbox along with the time it has been recorded at.
+struct StoredResponse {
+    stored_at: Instant,
+    response: ResponseMessage,
+}
+
+/// Keeps a window of the most recent responses of every client, to replay them when it resumes its session.
+///
+/// A response written to a stream is only buffered by the transport, so the server cannot tell whether it has
+/// reached its client before the connection was lost. Every response is therefore recorded, and a resuming client
+/// tells which requests it already has the responses of, so that only the other ones are replayed.
+///
+/// Every client gets its own mailbox, identified by the client ID announced in the hello message.
+/// Responses expire after `ttl`, and only the `capacity` most recent responses of a client are kept.
+/// The mailbox is shared by all the connections of the server.
+///
+/// # Fields
+///
+/// * `ttl` - How long a response is kept.
+/// * `capacity` - The maximum number of responses kept per client.
+/// * `mailboxes` - The responses kept for every client, the oldest first.
+pub struct Mailbox {
+    ttl: Duration,
+    capacity: usize,
+    mailboxes: Mutex<HashMap<String, VecDeque<StoredResponse>>>,
+}
+
+impl Mailbox {
+    /// Creates an empty mailbox.
+    ///
+    /// # Arguments
+    ///
+    /// * `ttl` - How long a response is kept.
+    /// * `capacity` - The maximum number of responses kept per client.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The created mailbox.
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            mailboxes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records a response sent to a client.
+    ///
+    /// The oldest response of the client is dropped if its mailbox is full.
+    /// Expired responses of all the clients are dropped along the way.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - The ID of the client the response is for.
+    /// * `response` - The sent response.
+    pub fn record(&self, client_id: &str, response: ResponseMessage) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut mailboxes = self.mailboxes.lock().expect("mailbox lock poisoned");
+
+        mailboxes.retain(|_, responses| {
+            Self::drop_expired(responses, now, self.ttl);
+            !responses.is_empty()
+        });
+
+        let responses = mailboxes.entry(client_id.to_string()).or_default();
+
+        if responses.len() >= self.capacity {
+            responses.pop_front();
+        }
+
+        responses.push_back(StoredResponse {
+            stored_at: now,
+            response,
+        });
+    }
+
+    /// Gives the responses to replay to a client resuming its session.
+    ///
+    /// The responses are kept, so that they can be replayed again if the connection is lost during resumption.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - The ID of the client.
+    /// * `received` - The IDs of the requests the client already has the responses of.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<ResponseMessage>` - The responses which have not expired yet and answer the other requests,
+    ///   the oldest first.
+    pub fn replay(&self, client_id: &str, received: &[MessageId]) -> Vec<ResponseMessage> {
+        let mut mailboxes = self.mailboxes.lock().expect("mailbox lock poisoned");
+
+        let responses = match mailboxes.get_mut(client_id) {
+            Some(responses) => responses,
+            None => return Vec::new(),
+        };
+
+        Self::drop_expired(responses, Instant::now(), self.ttl);
+
+        let received: HashSet<_> = received.iter().collect();
+
+        responses
+            .iter()
+            .filter(|stored| !received.contains(&stored.response.request_id))
+            .map(|stored| stored.response.clone())
+            .collect()
+    }
+
+    /// Drops the expired responses from the front of a client's mailbox.
+    fn drop_expired(responses: &mut VecDeque<StoredResponse>, now: Instant, ttl: Duration) {
+        while let Some(stored) = responses.front() {
+            if now.duration_since(stored.stored_at) < ttl {
+                break;
+            }
+
+            responses.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::message::Message;
+
+    use super::*;
+
+    fn pongs(count: usize) -> Vec<ResponseMessage> {
+        (0..count)
+            .map(|_| match Message::new_request("Ping!") {
+                Message::Request(request) => ResponseMessage::new(&request, 0, "Pong!"),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_should_keep_responses_per_client() {
+        let mailbox = Mailbox::new(Duration::from_secs(60), 8);
+        let responses = pongs(3);
+
+        mailbox.record("first", responses[0].clone());
+        mailbox.record("second", responses[1].clone());
+        mailbox.record("first", responses[2].clone());
+
+        assert_eq!(
+            mailbox.replay("first", &[]),
+            vec![responses[0].clone(), responses[2].clone()]
+        );
+        assert_eq!(mailbox.replay("second", &[]), vec![responses[1].clone()]);
+        assert_eq!(mailbox.replay("third", &[]), vec![]);
+    }
+
+    #[test]
+    fn test_should_only_replay_responses_not_received_yet() {
+        let mailbox = Mailbox::new(Duration::from_secs(60), 8);
+        let responses = pongs(3);
+
+        for response in &responses {
+            mailbox.record("client", response.clone());
+        }
+
+        let received = [responses[0].request_id, responses[2].request_id];
+        assert_eq!(
+            mailbox.replay("client", &received),
+            vec![responses[1].clone()]
+        );
+        // Replaying keeps the responses, in case the resumed connection is lost as well
+        assert_eq!(
+            mailbox.replay("client", &received),
+            vec![responses[1].clone()]
+        );
+    }
+
+    #[test]
+    fn test_should_drop_oldest_responses_beyond_capacity() {
+        let mailbox = Mailbox::new(Duration::from_secs(60), 2);
+        let responses = pongs(3);
+
+        for response in &responses {
+            mailbox.record("client", response.clone());
+        }
+
+        assert_eq!(mailbox.replay("client", &[]), responses[1..].to_vec());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_should_drop_expired_responses() {
+        let mailbox = Mailbox::new(Duration::from_secs(60), 8);
+        let responses = pongs(2);
+
+        mailbox.record("client", responses[0].clone());
+        tokio::time::advance(Duration::from_secs(45)).await;
+        mailbox.record("client", responses[1].clone());
+        tokio::time::advance(Duration::from_secs(30)).await;
+
+        assert_eq!(mailbox.replay("client", &[]), vec![responses[1].clone()]);
+    }
+}
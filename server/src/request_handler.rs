@@ -0,0 +1,189 @@
+use std::net::SocketAddr;
+
+use async_trait::async_trait;
+use common::{
+    codec::Codec,
+    message::{
+        headers::{HeaderValue, Headers},
+        request::RequestMessage,
+    },
+};
+
+use crate::error::RequestHandlerError;
+
+/// The transport a request has been received over.
+///
+/// Variants:
+/// * `Bidirectional`: A bidirectional stream, replied to on the same stream.
+/// * `Unidirectional`: A unidirectional stream, replied to on another unidirectional stream.
+/// * `Datagram`: A datagram, replied to with a datagram.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TransportKind {
+    Bidirectional,
+    Unidirectional,
+    Datagram,
+}
+
+/// The context of the connection a request has been received over.
+///
+/// # Fields
+///
+/// * `peer_address` - The address the client is connected from.
+/// * `client_id` - The ID the client has announced in its `Hello` message, kept when it resumes its session.
+/// * `session_id` - The ID generated for the connection, distinguishing the successive connections of a client.
+/// * `transport` - The transport the request has been received over.
+/// * `codec` - The codec negotiated for the connection, which RPC payloads are serialized with as well.
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    pub peer_address: SocketAddr,
+    pub client_id: String,
+    pub session_id: String,
+    pub transport: TransportKind,
+    pub codec: &'static dyn Codec,
+}
+
+/// The response computed by a `RequestHandler`.
+///
+/// # Fields
+///
+/// * `data` - The payload of the response.
+/// * `headers` - The metadata attached to the response, e.g. describing its payload.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct HandlerResponse {
+    pub data: Vec<u8>,
+    pub headers: Headers,
+}
+
+impl HandlerResponse {
+    /// Creates a response without any header.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The payload of the response.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The created response.
+    pub fn new(data: impl Into<Vec<u8>>) -> Self {
+        Self {
+            data: data.into(),
+            headers: Headers::new(),
+        }
+    }
+
+    /// Attaches a header to the response, replacing the one with the same name if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the header.
+    /// * `value` - The value of the header.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The response, to attach further headers.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<HeaderValue>) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+}
+
+/// Handles the requests received by the `PongServer`.
+///
+/// Handlers only compute the payload and the headers of the response, the server takes care of the framing,
+/// of correlating the response with its request and of answering the errors with an error message.
+/// A handler is shared by all the connections, so it is called concurrently.
+#[async_trait]
+pub trait RequestHandler: Send + Sync {
+    /// Handles a request.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The received request.
+    /// * `context` - The context of the connection the request has been received over.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<HandlerResponse, RequestHandlerError>` - The response, or the error the request is answered with.
+    async fn handle(
+        &self,
+        request: &RequestMessage,
+        context: &RequestContext,
+    ) -> Result<HandlerResponse, RequestHandlerError>;
+}
+
+/// Answers every request with "Pong!".
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Pong;
+
+#[async_trait]
+impl RequestHandler for Pong {
+    async fn handle(
+        &self,
+        _request: &RequestMessage,
+        _context: &RequestContext,
+    ) -> Result<HandlerResponse, RequestHandlerError> {
+        Ok(HandlerResponse::new("Pong!"))
+    }
+}
+
+/// Answers every request with its own payload.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Echo;
+
+#[async_trait]
+impl RequestHandler for Echo {
+    async fn handle(
+        &self,
+        request: &RequestMessage,
+        _context: &RequestContext,
+    ) -> Result<HandlerResponse, RequestHandlerError> {
+        Ok(HandlerResponse::new(request.data.clone()))
+    }
+}
+
+/// Answers every request with the same payload.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Static(pub Vec<u8>);
+
+#[async_trait]
+impl RequestHandler for Static {
+    async fn handle(
+        &self,
+        _request: &RequestMessage,
+        _context: &RequestContext,
+    ) -> Result<HandlerResponse, RequestHandlerError> {
+        Ok(HandlerResponse::new(self.0.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::{codec::default_codec, transport::loopback::LOOPBACK_ADDRESS};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_should_respond_with_built_in_handlers() {
+        let request = RequestMessage::new("Ping!");
+        let context = RequestContext {
+            peer_address: LOOPBACK_ADDRESS,
+            client_id: "client".to_string(),
+            session_id: "session".to_string(),
+            transport: TransportKind::Bidirectional,
+            codec: default_codec(),
+        };
+
+        let cases: [(&dyn RequestHandler, &[u8]); 3] = [
+            (&Pong, b"Pong!"),
+            (&Echo, b"Ping!"),
+            (&Static(b"Hello!".to_vec()), b"Hello!"),
+        ];
+
+        for (handler, expected) in cases {
+            assert_eq!(
+                handler.handle(&request, &context).await,
+                Ok(HandlerResponse::new(expected))
+            );
+        }
+    }
+}
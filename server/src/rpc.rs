@@ -0,0 +1,225 @@
+use std::{collections::HashMap, future::Future, pin::Pin};
+
+use async_trait::async_trait;
+use common::{
+    error::RpcError,
+    message::request::RequestMessage,
+    rpc::{decode_payload, encode_payload, method_of},
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    error::RequestHandlerError,
+    request_handler::{HandlerResponse, RequestContext, RequestHandler},
+};
+
+/// The result of a routed method, the serialized result of the call.
+type MethodFuture = Pin<Box<dyn Future<Output = Result<Vec<u8>, RequestHandlerError>> + Send>>;
+
+/// A routed method, taking the request and its context and returning the serialized result.
+type Method = Box<dyn Fn(&RequestMessage, &RequestContext) -> MethodFuture + Send + Sync>;
+
+/// Routes remote procedure calls to typed handlers by the name of their method.
+///
+/// The method of a call is named by the `rpc-method` header of the request, its parameters and its result
+/// are carried in the payloads of the request and of the response, serialized with the codec of the connection.
+/// Calls to methods which are not routed are answered with an `UnknownMethod` error message,
+/// calls whose parameters cannot be decoded with an `InvalidPayload` one.
+///
+/// # Fields
+///
+/// * `methods` - The handlers of the routed methods, by name.
+#[derive(Default)]
+pub struct Router {
+    methods: HashMap<String, Method>,
+}
+
+impl Router {
+    /// Creates a router without any method.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The created router.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Routes a method to a typed handler, replacing the one routed under the same name if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The name of the method.
+    /// * `handler` - Computes the result of a call from its parameters and the context of its connection.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The router, to route further methods.
+    pub fn route<P, R, F, Fut>(mut self, method: impl Into<String>, handler: F) -> Self
+    where
+        P: DeserializeOwned,
+        R: Serialize,
+        F: Fn(P, RequestContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<R, RequestHandlerError>> + Send + 'static,
+    {
+        let routed: Method = Box::new(move |request, context| {
+            let codec = context.codec;
+            let call = decode_payload::<P>(&request.data, codec)
+                .map(|params| handler(params, context.clone()));
+
+            Box::pin(async move {
+                let result = call?.await?;
+
+                Ok(encode_payload(&result, codec)?)
+            })
+        });
+
+        self.methods.insert(method.into(), routed);
+        self
+    }
+}
+
+#[async_trait]
+impl RequestHandler for Router {
+    async fn handle(
+        &self,
+        request: &RequestMessage,
+        context: &RequestContext,
+    ) -> Result<HandlerResponse, RequestHandlerError> {
+        let name = method_of(request)?;
+        let method = self
+            .methods
+            .get(name)
+            .ok_or_else(|| RpcError::UnknownMethod {
+                method: name.to_string(),
+            })?;
+
+        method(request, context).await.map(HandlerResponse::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use client::{error::ClientError, rpc::RpcClient};
+    use common::{
+        codec::default_codec,
+        message::{error::ErrorCode, hello::Capabilities, Message},
+        rpc::new_call,
+        transport::loopback::{loopback_pair, DatagramConditions, LOOPBACK_ADDRESS},
+    };
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::{
+        handler::handle_handshake, mailbox::Mailbox, request_handler::TransportKind,
+        session::Session,
+    };
+
+    #[derive(Serialize, Deserialize)]
+    struct Add {
+        a: i64,
+        b: i64,
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .route("add", |Add { a, b }, _| async move { Ok(a + b) })
+            .route("whoami", |(), context: RequestContext| async move {
+                Ok(context.client_id)
+            })
+    }
+
+    fn context() -> RequestContext {
+        RequestContext {
+            peer_address: LOOPBACK_ADDRESS,
+            client_id: "client".to_string(),
+            session_id: "session".to_string(),
+            transport: TransportKind::Bidirectional,
+            codec: default_codec(),
+        }
+    }
+
+    async fn call<P: Serialize>(method: &str, params: &P) -> Result<Vec<u8>, RequestHandlerError> {
+        match new_call(method, params, default_codec()).unwrap() {
+            Message::Request(request) => router()
+                .handle(&request, &context())
+                .await
+                .map(|response| response.data),
+            other => panic!("Expected a request, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_should_route_calls_to_their_method() {
+        let sum = call("add", &Add { a: 40, b: 2 }).await.unwrap();
+        let client_id = call("whoami", &()).await.unwrap();
+
+        assert_eq!(decode_payload::<i64>(&sum, default_codec()), Ok(42));
+        assert_eq!(
+            decode_payload::<String>(&client_id, default_codec()),
+            Ok("client".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_should_reject_unknown_method_and_invalid_payload() {
+        let unknown = call("subtract", &Add { a: 40, b: 2 }).await.unwrap_err();
+        let invalid = call("add", &1u8).await.unwrap_err();
+        let missing = router()
+            .handle(&RequestMessage::new("Ping!"), &context())
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            unknown,
+            RequestHandlerError::RpcError(RpcError::UnknownMethod {
+                method: "subtract".to_string()
+            })
+        );
+        assert_eq!(unknown.code(), ErrorCode::UnknownMethod);
+        assert_eq!(invalid.code(), ErrorCode::InvalidPayload);
+        assert_eq!(
+            missing,
+            RequestHandlerError::RpcError(RpcError::MissingMethod)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_should_serve_typed_calls_over_session() {
+        let (client, server) = loopback_pair(DatagramConditions::default());
+        let mailbox = Arc::new(Mailbox::new(Duration::from_secs(60), 16));
+        let capabilities = Capabilities::default();
+
+        let (rpc_client, handshake) = tokio::join!(
+            RpcClient::handshake(client, "client"),
+            handle_handshake(&server, &capabilities, &mailbox)
+        );
+        let (hello, ack) = handshake.unwrap();
+        let session = Session::new(
+            server,
+            ack.parameters().unwrap(),
+            mailbox,
+            Arc::new(router()),
+            &hello.client_id,
+        );
+        tokio::spawn(session.run());
+
+        let rpc_client = rpc_client.unwrap();
+        let (sum, client_id, unknown) = tokio::join!(
+            rpc_client.call::<_, i64>("add", &Add { a: 40, b: 2 }),
+            rpc_client.call::<_, String>("whoami", &()),
+            rpc_client.call::<_, i64>("subtract", &Add { a: 40, b: 2 }),
+        );
+
+        assert_eq!(sum, Ok(42));
+        assert_eq!(client_id, Ok("client".to_string()));
+        assert!(matches!(
+            unknown,
+            Err(ClientError::ErrorResponse {
+                code: ErrorCode::UnknownMethod,
+                ..
+            })
+        ));
+    }
+}
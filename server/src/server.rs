@@ -1,10 +1,19 @@
-use std::net::{IpAddr, SocketAddr};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
 
+use common::{message::hello::Capabilities, utils::gen_certs::certificate_fingerprint};
 use wtransport::{tls::Certificate, Endpoint, ServerConfig};
 
 use crate::{
+    beacon::{Beacon, BeaconConfig},
     error::{ServerError, ServerSetupError},
-    handler::{handle_bidirectional, handle_datagram, handle_unidirectional},
+    handler::handle_handshake,
+    mailbox::Mailbox,
+    request_handler::RequestHandler,
+    session::Session,
 };
 
 /// The configuration for the server.
@@ -16,11 +25,19 @@ use crate::{
 /// * `port` - The port to bind the server to.
 /// * `certificate_path` - The path to the certificate file.
 /// * `certificate_key_path` - The path to the certificate key file.
+/// * `max_frame_size` - The maximum size of a single message in bytes. Larger messages are rejected and their stream is reset.
+/// * `mailbox_ttl` - How long the responses are kept for their client to reconnect and resume its session.
+/// * `mailbox_capacity` - The maximum number of recent responses kept per client.
+/// * `beacon` - Announces the server on the local network for clients to find it, if set.
 pub struct PongServerConfig {
     pub host: IpAddr,
     pub port: u16,
     pub certificate_path: String,
     pub certificate_key_path: String,
+    pub max_frame_size: u64,
+    pub mailbox_ttl: Duration,
+    pub mailbox_capacity: usize,
+    pub beacon: Option<BeaconConfig>,
 }
 
 /// The Pong server.
@@ -29,8 +46,12 @@ pub struct PongServerConfig {
 /// # Fields
 ///
 /// * `config` - The configuration for the server.
+/// * `mailbox` - The mailbox keeping the recent responses, shared by all the connections.
+/// * `handler` - The handler computing the responses to requests, shared by all the connections.
 pub struct PongServer {
     config: PongServerConfig,
+    mailbox: Arc<Mailbox>,
+    handler: Arc<dyn RequestHandler>,
 }
 
 impl PongServer {
@@ -40,12 +61,19 @@ impl PongServer {
     /// # Arguments
     ///
     /// * `config` - The configuration for the server.
+    /// * `handler` - The handler computing the responses to requests, e.g. `Pong`, `Echo` or `Static`.
     ///
     /// # Returns
     ///
     /// * `Self` - The created Pong server.
-    pub fn new(config: PongServerConfig) -> Self {
-        Self { config }
+    pub fn new(config: PongServerConfig, handler: Arc<dyn RequestHandler>) -> Self {
+        let mailbox = Arc::new(Mailbox::new(config.mailbox_ttl, config.mailbox_capacity));
+
+        Self {
+            config,
+            mailbox,
+            handler,
+        }
     }
 
     /// Asynchronously serve incoming connections.
@@ -74,6 +102,20 @@ impl PongServer {
         let server = Endpoint::server(config)
             .map_err(|_| ServerError::SetupError(ServerSetupError::EndpointCreationError))?;
 
+        // Announcements stop as soon as the server does
+        let _beacon = match &self.config.beacon {
+            Some(beacon) => Some(
+                Beacon::start(
+                    beacon,
+                    self.config.host,
+                    self.config.port,
+                    certificate_fingerprint(&self.config.certificate_path).ok(),
+                )
+                .await?,
+            ),
+            None => None,
+        };
+
         loop {
             println!("Waiting for incoming connection...");
 
@@ -85,23 +127,44 @@ impl PongServer {
                 ));
             }
 
+            let capabilities = Capabilities {
+                max_message_size: self.config.max_frame_size,
+                ..Capabilities::default()
+            };
+
+            let mailbox = self.mailbox.clone();
+            let handler = self.handler.clone();
+
             tokio::spawn(async move {
                 let connection = maybe_acception.unwrap().await.unwrap();
 
-                println!("Waiting for data from client...");
-                loop {
-                    tokio::select! {
-                        _ = handle_bidirectional(&connection) => {
-                            println!("Connection closed by client");
-                            break;
+                let (hello, parameters) =
+                    match handle_handshake(&connection, &capabilities, &mailbox)
+                        .await
+                        .and_then(|(hello, ack)| Ok((hello, ack.parameters()?)))
+                    {
+                        Ok((hello, parameters)) => {
+                            println!(
+                                "Handshake completed with client {} using {:?} codec",
+                                hello.client_id, parameters.codec
+                            );
+                            (hello, parameters)
                         }
-                        _ = handle_unidirectional(&connection) => {
-                            println!("Connection closed by client");
-                            break;
+                        Err(error) => {
+                            println!("Rejecting client: {}", error);
+                            return;
                         }
-                        _ = handle_datagram(&connection) => {}
-                    }
-                }
+                    };
+
+                println!("Waiting for data from client...");
+                let error =
+                    Session::new(connection, parameters, mailbox, handler, &hello.client_id)
+                        .run()
+                        .await;
+                println!(
+                    "Connection with client {} closed: {}",
+                    hello.client_id, error
+                );
             });
 
             // Exit the loop if we are running tests.
@@ -116,13 +179,22 @@ impl PongServer {
 mod tests {
     use std::env::{self};
 
-    use client::client::{PingClient, PingClientConfig, PingClientConnectionType};
-    use common::message::Message;
+    use client::{
+        client::{PingClient, PingClientConfig, PingClientConnectionType},
+        discovery::{DiscoveryConfig, DiscoveryQuery},
+        error::ClientError,
+        tls::CertificateVerification,
+    };
+    use common::{
+        message::{error::ErrorCode, hello::DEFAULT_MAX_MESSAGE_SIZE, Message},
+        utils::gen_certs::MAX_PINNABLE_VALIDITY,
+    };
     use rand::{distributions::Alphanumeric, Rng};
 
     use super::*;
+    use crate::request_handler::Pong;
 
-    fn setup_certificates() -> (String, String) {
+    fn setup_certificates() -> (String, String, String) {
         let cert_name: String = rand::thread_rng()
             .sample_iter(&Alphanumeric)
             .take(7)
@@ -150,30 +222,48 @@ mod tests {
             .into_string()
             .expect("failed to construct certificate key file path");
 
-        common::utils::gen_certs::gen_certs(cert_path_string.clone(), key_path_string.clone())
-            .expect("failed to generate certificate files");
+        let fingerprint = common::utils::gen_certs::gen_certs(
+            cert_path_string.clone(),
+            key_path_string.clone(),
+            MAX_PINNABLE_VALIDITY,
+        )
+        .expect("failed to generate certificate files");
 
-        (cert_path_string, key_path_string)
+        (cert_path_string, key_path_string, fingerprint)
     }
 
     fn setup_client_server(host: String, port: u16) -> (PongServer, PingClient) {
-        let (cert_path, key_path) = setup_certificates();
+        setup_client_server_with_max_frame_size(host, port, DEFAULT_MAX_MESSAGE_SIZE)
+    }
+
+    fn setup_client_server_with_max_frame_size(
+        host: String,
+        port: u16,
+        max_frame_size: u64,
+    ) -> (PongServer, PingClient) {
+        let (cert_path, key_path, _) = setup_certificates();
 
         let pong_server_config = PongServerConfig {
             host: host.parse().expect("failed to parse host for the server"),
             port,
-            certificate_path: cert_path,
+            certificate_path: cert_path.clone(),
             certificate_key_path: key_path,
+            max_frame_size,
+            mailbox_ttl: Duration::from_secs(60),
+            mailbox_capacity: 16,
+            beacon: None,
         };
 
-        let pong_server = PongServer::new(pong_server_config);
+        let pong_server = PongServer::new(pong_server_config, Arc::new(Pong));
 
+        // The certificate is issued for localhost, which resolves to the address the server is bound to
         let ping_client_config = PingClientConfig {
-            host: host.parse().expect("failed to parse host for the server"),
-            port,
+            urls: vec![format!("https://localhost:{}", port)],
             connection_type: PingClientConnectionType::Bidirectional,
             max_retries: 3,
-            retry_timeout_millis: 1000,
+            // The self-signed certificate of the server is its own certificate authority
+            certificate_verification: CertificateVerification::CustomCa { ca_path: cert_path },
+            ..Default::default()
         };
 
         let ping_client = PingClient::new(ping_client_config);
@@ -187,7 +277,7 @@ mod tests {
 
         let times = Some(3);
 
-        let message = Message::new_request("Ping!".to_string());
+        let message = Message::new_request("Ping!");
 
         let (_, _) = tokio::join!(
             pong_server.serve(),
@@ -197,8 +287,9 @@ mod tests {
         let inbox = ping_client.get_indbox();
 
         assert_eq!(inbox.len(), 3);
-        for message in inbox {
-            assert_eq!(message.get_data(), "Pong!");
+        for entry in inbox {
+            assert_eq!(entry.message.get_data(), b"Pong!");
+            assert!(entry.round_trip_time.is_some());
         }
     }
 
@@ -208,7 +299,7 @@ mod tests {
 
         let times = Some(3);
 
-        let message = Message::new_request("Ping!".to_string());
+        let message = Message::new_request("Ping!");
 
         let (_, _) = tokio::join!(
             pong_server.serve(),
@@ -218,8 +309,9 @@ mod tests {
         let inbox = ping_client.get_indbox();
 
         assert_eq!(inbox.len(), 3);
-        for message in inbox {
-            assert_eq!(message.get_data(), "Pong!");
+        for entry in inbox {
+            assert_eq!(entry.message.get_data(), b"Pong!");
+            assert!(entry.round_trip_time.is_some());
         }
     }
 
@@ -229,7 +321,7 @@ mod tests {
 
         let times = Some(3);
 
-        let message = Message::new_request("Ping!".to_string());
+        let message = Message::new_request("Ping!");
 
         let (_, _) = tokio::join!(
             pong_server.serve(),
@@ -239,8 +331,159 @@ mod tests {
         let inbox = ping_client.get_indbox();
 
         assert_eq!(inbox.len(), 3);
-        for message in inbox {
-            assert_eq!(message.get_data(), "Pong!");
+        for entry in inbox {
+            assert_eq!(entry.message.get_data(), b"Pong!");
+            assert!(entry.round_trip_time.is_some());
         }
     }
+
+    #[tokio::test]
+    async fn test_integration_reject_oversized_message() {
+        let (pong_server, mut ping_client) =
+            setup_client_server_with_max_frame_size("127.0.0.1".to_string(), 4436, 1024);
+
+        let message = Message::new_request(vec![0; 4096]);
+
+        let (_, result) = tokio::join!(
+            pong_server.serve(),
+            ping_client.send_message(&message, Some(1))
+        );
+
+        match result {
+            Err(ClientError::ErrorResponse { code, .. }) => {
+                assert_eq!(code, ErrorCode::MessageTooLarge)
+            }
+            other => panic!("Expected an error response, got {:?}", other),
+        }
+        assert!(ping_client.get_indbox().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_integration_verify_pinned_certificate_hash() {
+        let (cert_path, key_path, fingerprint) = setup_certificates();
+
+        let pong_server = PongServer::new(
+            PongServerConfig {
+                host: "127.0.0.1".parse().unwrap(),
+                port: 4437,
+                certificate_path: cert_path,
+                certificate_key_path: key_path,
+                max_frame_size: DEFAULT_MAX_MESSAGE_SIZE,
+                mailbox_ttl: Duration::from_secs(60),
+                mailbox_capacity: 16,
+                beacon: None,
+            },
+            Arc::new(Pong),
+        );
+
+        let mut ping_client = PingClient::new(PingClientConfig {
+            urls: vec!["https://localhost:4437".to_string()],
+            connection_type: PingClientConnectionType::Bidirectional,
+            max_retries: 3,
+            certificate_verification: CertificateVerification::PinnedHashes {
+                hashes: vec![fingerprint],
+            },
+            ..Default::default()
+        });
+
+        let message = Message::new_request("Ping!");
+
+        let (_, result) = tokio::join!(
+            pong_server.serve(),
+            ping_client.send_message(&message, Some(1))
+        );
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(ping_client.get_indbox().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_integration_discover_announcing_server() {
+        let (cert_path, key_path, fingerprint) = setup_certificates();
+
+        // Announcements are sent to a loopback port, as multicast may not be routed on the test host
+        let group = std::net::UdpSocket::bind("127.0.0.1:0")
+            .and_then(|socket| socket.local_addr())
+            .expect("failed to find a free port for announcements");
+
+        let pong_server = PongServer::new(
+            PongServerConfig {
+                host: "127.0.0.1".parse().unwrap(),
+                port: 4438,
+                certificate_path: cert_path,
+                certificate_key_path: key_path,
+                max_frame_size: DEFAULT_MAX_MESSAGE_SIZE,
+                mailbox_ttl: Duration::from_secs(60),
+                mailbox_capacity: 16,
+                beacon: Some(BeaconConfig {
+                    group,
+                    ..BeaconConfig::default()
+                }),
+            },
+            Arc::new(Pong),
+        );
+
+        // The URL is not connected to, the server is found through its announcements
+        let mut ping_client = PingClient::new(PingClientConfig {
+            urls: vec!["https://pong.invalid".to_string()],
+            connection_type: PingClientConnectionType::Bidirectional,
+            max_retries: 3,
+            certificate_verification: CertificateVerification::PinnedHashes {
+                hashes: vec![fingerprint],
+            },
+            discovery: Some(DiscoveryConfig::new(DiscoveryQuery::Beacon { group })),
+            ..Default::default()
+        });
+
+        let message = Message::new_request("Ping!");
+
+        let (_, result) = tokio::join!(
+            pong_server.serve(),
+            ping_client.send_message(&message, Some(1))
+        );
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(ping_client.get_indbox().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_integration_fail_over_to_another_server() {
+        let (cert_path, key_path, _) = setup_certificates();
+
+        let pong_server = PongServer::new(
+            PongServerConfig {
+                host: "127.0.0.1".parse().unwrap(),
+                port: 4439,
+                certificate_path: cert_path.clone(),
+                certificate_key_path: key_path,
+                max_frame_size: DEFAULT_MAX_MESSAGE_SIZE,
+                mailbox_ttl: Duration::from_secs(60),
+                mailbox_capacity: 16,
+                beacon: None,
+            },
+            Arc::new(Pong),
+        );
+
+        // Nothing listens on the first URL, the retry goes to the next one
+        let mut ping_client = PingClient::new(PingClientConfig {
+            urls: vec![
+                "https://localhost:4440".to_string(),
+                "https://localhost:4439".to_string(),
+            ],
+            connection_type: PingClientConnectionType::Bidirectional,
+            max_retries: 3,
+            certificate_verification: CertificateVerification::CustomCa { ca_path: cert_path },
+            ..Default::default()
+        });
+
+        let message = Message::new_request("Ping!");
+
+        let (_, result) = tokio::join!(
+            pong_server.serve(),
+            ping_client.send_message(&message, Some(1))
+        );
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(ping_client.get_indbox().len(), 1);
+    }
 }
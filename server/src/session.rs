@@ -0,0 +1,512 @@
+use std::{future::Future, sync::Arc};
+
+use common::{
+    error::ConnectionError,
+    message::hello::ConnectionParameters,
+    transport::{Connection, RecvStream, SendStream},
+};
+use tokio::task::{JoinError, JoinSet};
+
+use crate::{
+    error::ServerError,
+    handler::{handle_stream, new_session_id, request_context, serve_datagrams},
+    mailbox::Mailbox,
+    request_handler::{RequestHandler, TransportKind},
+};
+
+/// The session of a client over a connection which has completed the handshake.
+///
+/// The session keeps accepting the streams opened by the client and serves each of them in its own task,
+/// while datagrams are served in a dedicated loop. A stream failing does not affect the others, the session
+/// only ends once the connection is closed.
+///
+/// # Fields
+///
+/// * `connection` - The connection to the client, shared with the tasks serving its streams.
+/// * `parameters` - The connection parameters negotiated during the handshake.
+/// * `mailbox` - The mailbox keeping the responses which could not be delivered.
+/// * `handler` - The handler computing the responses to requests.
+/// * `client_id` - The ID of the connected client.
+/// * `session_id` - The ID generated for the connection, passed to the handler along with the client ID.
+pub struct Session<C> {
+    connection: Arc<C>,
+    parameters: ConnectionParameters,
+    mailbox: Arc<Mailbox>,
+    handler: Arc<dyn RequestHandler>,
+    client_id: Arc<str>,
+    session_id: Arc<str>,
+}
+
+// Not derived, as cloning the session does not require cloning the connection itself
+impl<C> Clone for Session<C> {
+    fn clone(&self) -> Self {
+        Self {
+            connection: self.connection.clone(),
+            parameters: self.parameters,
+            mailbox: self.mailbox.clone(),
+            handler: self.handler.clone(),
+            client_id: self.client_id.clone(),
+            session_id: self.session_id.clone(),
+        }
+    }
+}
+
+impl<C> Session<C>
+where
+    C: Connection + 'static,
+    C::SendStream: 'static,
+    C::RecvStream: 'static,
+{
+    /// Creates the session of a client, generating the ID of the session.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection` - The connection to the client.
+    /// * `parameters` - The connection parameters negotiated during the handshake.
+    /// * `mailbox` - The mailbox keeping the responses which could not be delivered.
+    /// * `handler` - The handler computing the responses to requests.
+    /// * `client_id` - The ID of the connected client.
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The created session.
+    pub fn new(
+        connection: C,
+        parameters: ConnectionParameters,
+        mailbox: Arc<Mailbox>,
+        handler: Arc<dyn RequestHandler>,
+        client_id: &str,
+    ) -> Self {
+        Self {
+            connection: Arc::new(connection),
+            parameters,
+            mailbox,
+            handler,
+            client_id: client_id.into(),
+            session_id: new_session_id().into(),
+        }
+    }
+
+    /// Serves the streams and datagrams of the client until the connection is closed.
+    ///
+    /// Streams still being served when the connection is closed are awaited, so that their undelivered
+    /// responses are kept in the mailbox.
+    ///
+    /// # Returns
+    ///
+    /// * `ConnectionError` - The error the connection has been closed with.
+    pub async fn run(self) -> ConnectionError {
+        let bidirectional = accept_streams(
+            || self.connection.accept_bi(),
+            |(send_stream, recv_stream)| {
+                let session = self.clone();
+                async move {
+                    session
+                        .serve(send_stream, recv_stream, TransportKind::Bidirectional)
+                        .await
+                }
+            },
+        );
+        let unidirectional = accept_streams(
+            || self.connection.accept_uni(),
+            |recv_stream| {
+                let session = self.clone();
+                async move {
+                    let send_stream = session.connection.open_uni().await?;
+                    session
+                        .serve(send_stream, recv_stream, TransportKind::Unidirectional)
+                        .await
+                }
+            },
+        );
+
+        let streams = async { tokio::join!(bidirectional, unidirectional) };
+        tokio::pin!(streams);
+
+        let (error, _) = tokio::select! {
+            closed = &mut streams => closed,
+            error = serve_datagrams(
+                self.connection.as_ref(),
+                &self.parameters,
+                self.handler.as_ref(),
+                &self.client_id,
+                &self.session_id,
+            ) => {
+                // Datagrams may be unsupported on a connection whose streams are still usable
+                println!("Stopped serving datagrams of client {}: {}", self.client_id, error);
+                streams.await
+            }
+        };
+
+        error
+    }
+
+    /// Serves a single stream of the client.
+    async fn serve<S: SendStream, R: RecvStream>(
+        &self,
+        send_stream: S,
+        recv_stream: R,
+        transport: TransportKind,
+    ) -> Result<(), ServerError> {
+        let context = request_context(
+            self.connection.as_ref(),
+            &self.parameters,
+            &self.client_id,
+            &self.session_id,
+            transport,
+        );
+
+        handle_stream(
+            send_stream,
+            recv_stream,
+            &self.parameters,
+            &self.mailbox,
+            self.handler.as_ref(),
+            &context,
+        )
+        .await
+    }
+}
+
+/// Accepts streams until the connection is closed, serving each of them in its own task.
+///
+/// The pending accept is kept while the served streams complete, so that no incoming stream is lost
+/// in between.
+///
+/// # Arguments
+///
+/// * `accept` - Accepts the next stream of the connection.
+/// * `serve` - Serves an accepted stream.
+///
+/// # Returns
+///
+/// * `ConnectionError` - The error the connection has been closed with, once all the streams have been served.
+async fn accept_streams<T, A, AF, H, HF>(mut accept: A, mut serve: H) -> ConnectionError
+where
+    A: FnMut() -> AF,
+    AF: Future<Output = Result<T, ConnectionError>>,
+    H: FnMut(T) -> HF,
+    HF: Future<Output = Result<(), ServerError>> + Send + 'static,
+{
+    let mut streams = JoinSet::new();
+    let mut accepting = Box::pin(accept());
+
+    loop {
+        tokio::select! {
+            accepted = &mut accepting => {
+                match accepted {
+                    Ok(stream) => {
+                        streams.spawn(serve(stream));
+                    }
+                    Err(error) => {
+                        while let Some(served) = streams.join_next().await {
+                            report_served_stream(served);
+                        }
+
+                        return error;
+                    }
+                }
+
+                accepting = Box::pin(accept());
+            }
+            Some(served) = streams.join_next() => report_served_stream(served),
+        }
+    }
+}
+
+/// Reports how serving a stream has ended.
+fn report_served_stream(served: Result<Result<(), ServerError>, JoinError>) {
+    match served {
+        Ok(Ok(())) => {}
+        Ok(Err(error)) => println!("Stream closed: {}", error),
+        Err(error) => println!("Serving a stream has panicked: {}", error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+    use client::{
+        client::{PingClient, PingClientConfig, PingClientConnectionType},
+        error::ClientError,
+    };
+    use common::{
+        codec::default_codec,
+        message::{error::ErrorCode, hello::Capabilities, request::RequestMessage, Message},
+        stream::{read_next_message, write_message},
+        transport::loopback::{loopback_pair, DatagramConditions, LoopbackConnection},
+    };
+
+    use super::*;
+    use crate::{
+        error::RequestHandlerError,
+        handler::handle_handshake,
+        request_handler::{HandlerResponse, Pong, RequestContext},
+    };
+
+    fn parameters() -> ConnectionParameters {
+        ConnectionParameters {
+            codec: default_codec(),
+            datagrams_supported: true,
+            max_message_size: 256,
+        }
+    }
+
+    fn start_session(server: LoopbackConnection) -> tokio::task::JoinHandle<ConnectionError> {
+        let mailbox = Arc::new(Mailbox::new(Duration::from_secs(60), 16));
+
+        tokio::spawn(Session::new(server, parameters(), mailbox, Arc::new(Pong), "client").run())
+    }
+
+    async fn ping<S: SendStream, R: RecvStream>(send_stream: &mut S, recv_stream: &mut R) {
+        let request = Message::new_request("Ping!");
+        write_message(send_stream, &request, default_codec())
+            .await
+            .unwrap();
+
+        match read_next_message(recv_stream, default_codec(), 1024).await {
+            Ok(Message::Response(response)) => {
+                assert_eq!(response.request_id, request.id());
+                assert_eq!(response.data, b"Pong!");
+            }
+            other => panic!("Expected a response, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_should_serve_concurrent_streams_and_datagrams() {
+        let (client, server) = loopback_pair(DatagramConditions::default());
+        let session = start_session(server);
+
+        let (mut first_send, mut first_recv) = client.open_bi().await.unwrap();
+        let (mut second_send, mut second_recv) = client.open_bi().await.unwrap();
+        let mut uni_send = client.open_uni().await.unwrap();
+        let mut uni_recv = client.accept_uni().await.unwrap();
+
+        // The streams are interleaved, each is served while the others are still open
+        ping(&mut second_send, &mut second_recv).await;
+        ping(&mut first_send, &mut first_recv).await;
+        ping(&mut uni_send, &mut uni_recv).await;
+        ping(&mut second_send, &mut second_recv).await;
+
+        let request = Message::new_request("Ping!");
+        client
+            .send_datagram(&request.encode(default_codec()).unwrap())
+            .unwrap();
+        let datagram = client.receive_datagram().await.unwrap();
+        assert!(matches!(
+            Message::decode(&datagram, default_codec()),
+            Ok(Message::Response(_))
+        ));
+
+        drop((first_send, first_recv, second_send, second_recv));
+        drop((uni_send, uni_recv, client));
+
+        assert!(matches!(
+            session.await.unwrap(),
+            ConnectionError::ClosedByPeer { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_should_keep_serving_streams_after_one_has_failed() {
+        let (client, server) = loopback_pair(DatagramConditions::default());
+        let session = start_session(server);
+
+        let (mut healthy_send, mut healthy_recv) = client.open_bi().await.unwrap();
+        ping(&mut healthy_send, &mut healthy_recv).await;
+
+        let (mut failing_send, mut failing_recv) = client.open_bi().await.unwrap();
+        write_message(
+            &mut failing_send,
+            &Message::new_request(vec![0; 1024]),
+            default_codec(),
+        )
+        .await
+        .unwrap();
+        match read_next_message(&mut failing_recv, default_codec(), 1024).await {
+            Ok(Message::Error(error)) => assert_eq!(error.code, ErrorCode::MessageTooLarge),
+            other => panic!("Expected an error message, got {:?}", other),
+        }
+
+        ping(&mut healthy_send, &mut healthy_recv).await;
+
+        let (mut new_send, mut new_recv) = client.open_bi().await.unwrap();
+        ping(&mut new_send, &mut new_recv).await;
+        assert!(!session.is_finished());
+    }
+
+    /// Connects to a session performing the handshake and serving the client with the given handler.
+    fn connect_to_session(handler: Arc<dyn RequestHandler>) -> LoopbackConnection {
+        let (client, server) = loopback_pair(DatagramConditions::default());
+
+        tokio::spawn(async move {
+            let mailbox = Arc::new(Mailbox::new(Duration::from_secs(60), 16));
+            let (hello, ack) = handle_handshake(&server, &Capabilities::default(), &mailbox)
+                .await
+                .unwrap();
+            let parameters = ack.parameters().unwrap();
+
+            Session::new(server, parameters, mailbox, handler, &hello.client_id)
+                .run()
+                .await
+        });
+
+        client
+    }
+
+    /// Answers requests with "Pong!", except for the one arriving at the given rank, which is failed.
+    struct FailingAt {
+        arrivals: AtomicUsize,
+        rank: usize,
+    }
+
+    #[async_trait]
+    impl RequestHandler for FailingAt {
+        async fn handle(
+            &self,
+            _request: &RequestMessage,
+            _context: &RequestContext,
+        ) -> Result<HandlerResponse, RequestHandlerError> {
+            if self.arrivals.fetch_add(1, Ordering::SeqCst) == self.rank {
+                return Err(RequestHandlerError::Failed {
+                    reason: "out of pongs".to_string(),
+                });
+            }
+
+            Ok(HandlerResponse::new("Pong!"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_should_end_run_when_one_stream_fails() {
+        let mut ping_client = PingClient::new(PingClientConfig {
+            streams: 3,
+            ..Default::default()
+        });
+        let message = Message::new_request("Ping!");
+        let handler = Arc::new(FailingAt {
+            arrivals: AtomicUsize::new(0),
+            rank: 10,
+        });
+
+        // The healthy streams would keep pinging forever if the failure were not reported
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            ping_client.send_message_over(
+                || std::future::ready(Ok(connect_to_session(handler.clone()))),
+                &message,
+                None,
+            ),
+        )
+        .await
+        .expect("the failure of a stream should end the run");
+
+        assert!(matches!(
+            result,
+            Err(ClientError::ErrorResponse {
+                code: ErrorCode::InternalError,
+                ..
+            })
+        ));
+        assert!(ping_client.get_statistics().received >= 10);
+    }
+
+    #[tokio::test]
+    async fn test_should_serve_pings_spread_over_several_streams() {
+        for connection_type in [
+            PingClientConnectionType::Bidirectional,
+            PingClientConnectionType::Unidirectional,
+        ] {
+            let mut ping_client = PingClient::new(PingClientConfig {
+                connection_type,
+                streams: 3,
+                ..Default::default()
+            });
+            let message = Message::new_request("Ping!");
+
+            ping_client
+                .send_message_over(
+                    || std::future::ready(Ok(connect_to_session(Arc::new(Pong)))),
+                    &message,
+                    Some(7),
+                )
+                .await
+                .unwrap();
+
+            let per_stream: Vec<_> = ping_client
+                .get_stream_statistics()
+                .iter()
+                .map(|summary| (summary.sent, summary.received))
+                .collect();
+            assert_eq!(per_stream, [(3, 3), (2, 2), (2, 2)]);
+
+            let summary = ping_client.get_statistics();
+            assert_eq!(summary.sent, 7);
+            assert_eq!(summary.received, 7);
+            assert_eq!(ping_client.get_indbox().len(), 7);
+        }
+    }
+
+    /// Answers every request with "Pong!", telling the IDs of its context in the headers of the response.
+    struct Identify;
+
+    #[async_trait]
+    impl RequestHandler for Identify {
+        async fn handle(
+            &self,
+            _request: &RequestMessage,
+            context: &RequestContext,
+        ) -> Result<HandlerResponse, RequestHandlerError> {
+            Ok(HandlerResponse::new("Pong!")
+                .with_header("client-id", context.client_id.as_str())
+                .with_header("session-id", context.session_id.as_str()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_should_tell_connections_of_same_client_apart() {
+        let mut session_ids = vec![];
+
+        for _ in 0..2 {
+            let (client, server) = loopback_pair(DatagramConditions::default());
+            let mailbox = Arc::new(Mailbox::new(Duration::from_secs(60), 16));
+            tokio::spawn(
+                Session::new(server, parameters(), mailbox, Arc::new(Identify), "client").run(),
+            );
+
+            let (mut send_stream, mut recv_stream) = client.open_bi().await.unwrap();
+            let mut responses = vec![];
+            for _ in 0..2 {
+                write_message(
+                    &mut send_stream,
+                    &Message::new_request("Ping!"),
+                    default_codec(),
+                )
+                .await
+                .unwrap();
+                match read_next_message(&mut recv_stream, default_codec(), 1024).await {
+                    Ok(Message::Response(response)) => responses.push(response),
+                    other => panic!("Expected a response, got {:?}", other),
+                }
+            }
+
+            for response in &responses {
+                assert_eq!(response.data, b"Pong!");
+                assert_eq!(response.headers["client-id"].as_text(), Some("client"));
+                assert_eq!(
+                    response.headers["session-id"],
+                    responses[0].headers["session-id"]
+                );
+            }
+            session_ids.push(responses[0].headers["session-id"].clone());
+        }
+
+        assert_ne!(session_ids[0], session_ids[1]);
+    }
+}